@@ -0,0 +1,126 @@
+#ifndef GRRS_H
+#define GRRS_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Treat the pattern as a regular expression, in the syntax of the `regex` crate.
+#define GRRS_REGEX 1
+
+// Ignore the case of the letters.
+#define GRRS_IGNORE_CASE (1 << 1)
+
+// Ignore the case of the letters, unless the pattern has an uppercase letter.
+#define GRRS_SMART_CASE (1 << 2)
+
+// Search hidden files and directories.
+#define GRRS_HIDDEN (1 << 3)
+
+// Follow symbolic links found in directories.
+#define GRRS_FOLLOW (1 << 4)
+
+// A line matched.
+#define GRRS_MATCHED 0
+
+// No line matched.
+#define GRRS_NO_MATCH 1
+
+// The search could not be made, or some files could not be searched: see `grrs_last_error`.
+#define GRRS_ERROR 2
+
+// The callback stopped the search.
+#define GRRS_STOPPED 3
+
+// A set-up search, which `grrs_searcher_search` runs. Only handled through pointers.
+typedef struct GrrsSearcher GrrsSearcher;
+
+// An occurrence of the pattern in a line: the byte offsets of its start and of its end,
+// which is excluded.
+typedef struct GrrsSpan {
+  size_t start;
+  size_t end;
+} GrrsSpan;
+
+// A matching line, as handed to the callback.
+typedef struct GrrsMatch {
+  // The path of the file, NUL-terminated.
+  const char *path;
+  // 1-based.
+  uint64_t line_number;
+  // Offset of the first byte of the line in the file.
+  uint64_t offset;
+  // The line, without its terminator, in UTF-8. It is NUL-terminated, but `line_len` is
+  // its length: a line may contain NUL bytes.
+  const char *line;
+  size_t line_len;
+  // The occurrences of the pattern in `line`.
+  const struct GrrsSpan *spans;
+  size_t spans_len;
+} GrrsMatch;
+
+// Called with each matching line, and the `user_data` given along. Everything `match`
+// points to is only valid until the callback returns: copy what is to be kept. Returning
+// anything but 0 stops the search.
+typedef int (*GrrsCallback)(const struct GrrsMatch*, void*);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Set up a search for `pattern`, with the `GRRS_*` `flags` or'ed together. Returns NULL if
+// the pattern or the flags are invalid.
+//
+// # Safety
+//
+// `pattern` is a NUL-terminated string, which is not used after the call. The searcher
+// returned belongs to the caller, who frees it with `grrs_searcher_free`.
+struct GrrsSearcher *grrs_searcher_new(const char *pattern, uint32_t flags);
+
+// Free a searcher made by `grrs_searcher_new`. Does nothing with NULL.
+//
+// # Safety
+//
+// `searcher` is NULL, or was returned by `grrs_searcher_new` and is not used after the call.
+void grrs_searcher_free(struct GrrsSearcher *searcher);
+
+// Search the file at `path`, or the files under it if it is a directory, calling `callback`
+// with each matching line, file after file in the order of their paths. Files that cannot
+// be searched are skipped, and `GRRS_ERROR` returned once the others were. Returns one of
+// the `GRRS_*` statuses.
+//
+// # Safety
+//
+// `searcher` was returned by `grrs_searcher_new` and not freed. It may be used by several
+// threads at once. `path` is a NUL-terminated string, which is not used after the call.
+// `callback` may be NULL, to only tell whether anything matches.
+int grrs_searcher_search(const struct GrrsSearcher *searcher,
+                         const char *path,
+                         GrrsCallback callback,
+                         void *user_data);
+
+// Search `path` for the literal `pattern`, as `grrs_searcher_search` does with a searcher
+// made without flags.
+//
+// # Safety
+//
+// As for `grrs_searcher_new` and `grrs_searcher_search`.
+int grrs_search_path(const char *pattern,
+                     const char *path,
+                     GrrsCallback callback,
+                     void *user_data);
+
+// The message of the last error of a call made on this thread, or NULL if it did not fail.
+// The message belongs to the library. It is valid until the next call made on this thread
+// to any of these functions.
+const char *grrs_last_error(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif // GRRS_H
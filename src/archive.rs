@@ -0,0 +1,371 @@
+#[cfg(feature = "compression")]
+use anyhow::Context;
+use anyhow::Result as AnyhowResult;
+
+#[cfg(feature = "compression")]
+use std::cell::Cell;
+use std::io::{BufRead, Read, Seek};
+#[cfg(feature = "compression")]
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+#[cfg(feature = "compression")]
+use std::path::PathBuf;
+
+use crate::decode::DecoderRegistry;
+use crate::globs::Glob;
+
+// Names of tar archives, plain or compressed. The compression itself is undone by the
+// `decode` module before the entries are read.
+const TAR_SUFFIXES: &[&str] = &[
+    ".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz2", ".tar.xz", ".txz", ".tar.zst", ".tar.lz4",
+];
+
+// Names of zip archives, including the formats that are zip files under another name.
+const ZIP_SUFFIXES: &[&str] = &[".zip", ".jar", ".war", ".ear"];
+
+/// Whether `--archive` searches the entries of the tar file at `path` instead of its raw bytes.
+pub fn is_tar(path: &Path) -> bool {
+    has_suffix(path, TAR_SUFFIXES)
+}
+
+/// Whether `--archive` searches the entries of the zip file at `path` instead of its raw bytes.
+pub fn is_zip(path: &Path) -> bool {
+    has_suffix(path, ZIP_SUFFIXES)
+}
+
+fn has_suffix(path: &Path, suffixes: &[&str]) -> bool {
+    let name = path.to_string_lossy().to_lowercase();
+    suffixes.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// The members of archives to search: those matching one of the `--archive-glob` globs, or
+/// all of them if there is none. The others are not decompressed. Also how deep to search
+/// the archives inside archives, and how much to unpack before giving up on a zip bomb.
+#[cfg_attr(not(feature = "compression"), allow(dead_code))]
+pub struct Members {
+    globs: Vec<Glob>,
+    // Whether to tell which members are skipped, for `--debug`.
+    debug: bool,
+    depth: usize,
+    // To decompress the archives inside archives.
+    decoders: DecoderRegistry,
+    max_size: u64,
+    max_ratio: u64,
+}
+
+impl Members {
+    pub fn new(globs: &[String], debug: bool) -> AnyhowResult<Members> {
+        let globs = globs.iter().map(|glob| Glob::new(glob)).collect::<AnyhowResult<_>>()?;
+        Ok(Members {
+            globs,
+            debug,
+            depth: 0,
+            decoders: DecoderRegistry::default(),
+            max_size: u64::MAX,
+            max_ratio: u64::MAX,
+        })
+    }
+
+    /// Search the members of the archives found in archives too, down to `depth` levels,
+    /// decompressing them with `decoders` besides the built-in decompressors.
+    pub fn depth(mut self, depth: usize, decoders: &DecoderRegistry) -> Members {
+        self.depth = depth;
+        self.decoders = decoders.clone();
+        self
+    }
+
+    /// Give up an archive once more than `max_size` bytes, or more than `max_ratio` times
+    /// its own size, were unpacked from it, nested archives included.
+    pub fn limits(mut self, max_size: u64, max_ratio: u64) -> Members {
+        self.max_size = max_size;
+        self.max_ratio = max_ratio;
+        self
+    }
+
+    // Whether the member `name` of `archive` is searched.
+    #[cfg(feature = "compression")]
+    fn selects(&self, archive: &str, name: &Path) -> bool {
+        if self.globs.is_empty() || self.globs.iter().any(|glob| glob.matches(name)) {
+            return true;
+        }
+        if self.debug {
+            eprintln!("Skipped {}!{}: no --archive-glob matches it", archive, name.display());
+        }
+        false
+    }
+
+    // What may be unpacked from the archive at `path`, `size` bytes long.
+    #[cfg(feature = "compression")]
+    fn budget(&self, path: &Path, size: u64) -> Budget {
+        let by_ratio = size.saturating_mul(self.max_ratio);
+        let exceeded = if by_ratio < self.max_size {
+            format!("{:?} unpacks to more than {} times its size, see --archive-max-ratio",
+                path, self.max_ratio)
+        } else {
+            format!("{:?} unpacks to more than {} bytes, see --archive-max-size", path,
+                self.max_size)
+        };
+        Budget { left: Cell::new(by_ratio.min(self.max_size)), exceeded }
+    }
+}
+
+// The bytes left to unpack from an archive before it is taken for a zip bomb.
+#[cfg(feature = "compression")]
+struct Budget {
+    left: Cell<u64>,
+    exceeded: String,
+}
+
+// A decompressed stream of an archive, whose bytes are charged to the budget of the archive.
+#[cfg(feature = "compression")]
+struct Metered<'a, R> {
+    inner: R,
+    budget: &'a Budget,
+}
+
+#[cfg(feature = "compression")]
+impl<R: Read> Read for Metered<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let left = self.budget.left.get().checked_sub(read as u64).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, self.budget.exceeded.clone())
+        })?;
+        self.budget.left.set(left);
+        Ok(read)
+    }
+}
+
+/// Call `search` with a display name and a reader for every regular file in the tar archive
+/// that `members` selects, `size` bytes long on disk. Entries are named
+/// `archive.tar!path/inside`, and those of nested archives `archive.tar!inner.zip!path`.
+/// Returns how many were skipped.
+#[cfg(feature = "compression")]
+pub fn for_each_tar_entry<F>(path: &Path, reader: Box<dyn BufRead + '_>, size: u64,
+    members: &Members, mut search: F) -> AnyhowResult<usize>
+    where F: FnMut(&str, &mut dyn BufRead) -> AnyhowResult<()>
+{
+    let budget = members.budget(path, size);
+    let mut unpack = Unpack { members, budget: &budget, search: &mut search, skipped: 0 };
+    unpack.tar(&path.display().to_string(), reader, 0)?;
+    Ok(unpack.skipped)
+}
+
+/// Call `search` with a display name and a reader for every file in the zip archive that
+/// `members` selects, `size` bytes long on disk. Entries are named `archive.zip!path/inside`,
+/// and those of nested archives `archive.zip!inner.tar!path`. Returns how many were skipped.
+#[cfg(feature = "compression")]
+pub fn for_each_zip_entry<F>(path: &Path, file: impl Read + Seek, size: u64,
+    members: &Members, mut search: F) -> AnyhowResult<usize>
+    where F: FnMut(&str, &mut dyn BufRead) -> AnyhowResult<()>
+{
+    let budget = members.budget(path, size);
+    let mut unpack = Unpack { members, budget: &budget, search: &mut search, skipped: 0 };
+    unpack.zip(&path.display().to_string(), file, 0)?;
+    Ok(unpack.skipped)
+}
+
+// The members of one archive on disk being searched, with those of the archives inside it.
+#[cfg(feature = "compression")]
+struct Unpack<'a> {
+    members: &'a Members,
+    budget: &'a Budget,
+    search: &'a mut dyn FnMut(&str, &mut dyn BufRead) -> AnyhowResult<()>,
+    skipped: usize,
+}
+
+#[cfg(feature = "compression")]
+impl Unpack<'_> {
+    // `archive` is the display name of the tar file `reader` reads, `depth` levels down.
+    fn tar(&mut self, archive: &str, reader: Box<dyn BufRead + '_>, depth: usize)
+        -> AnyhowResult<()>
+    {
+        let mut tar = tar::Archive::new(Metered { inner: reader, budget: self.budget });
+        let entries = tar.entries()
+            .with_context(|| format!("Could not read tar archive: {:?}", archive))?;
+        for entry in entries {
+            let entry = entry
+                .with_context(|| format!("Could not read tar archive: {:?}", archive))?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            // The data of a skipped entry is read past without being decompressed any
+            // further than the archive itself is.
+            let inner = entry.path()?.into_owned();
+            if !self.members.selects(archive, &inner) {
+                self.skipped += 1;
+                continue;
+            }
+            let name = format!("{}!{}", archive, inner.display());
+            self.member(&name, &inner, &mut BufReader::new(entry), depth)?;
+        }
+        Ok(())
+    }
+
+    // `archive` is the display name of the zip file `file` holds, `depth` levels down.
+    fn zip(&mut self, archive: &str, file: impl Read + Seek, depth: usize) -> AnyhowResult<()> {
+        // Unlike tar, zip keeps its table of contents at the end of the file, which is why
+        // the archive needs a seekable file rather than a stream.
+        let mut zip = zip::ZipArchive::new(file)
+            .with_context(|| format!("Could not read zip archive: {:?}", archive))?;
+        for index in 0..zip.len() {
+            // Looked at raw first, so that the skipped entries are never decompressed.
+            let raw = zip.by_index_raw(index)
+                .with_context(|| format!("Could not read zip archive: {:?}", archive))?;
+            if !raw.is_file() {
+                continue;
+            }
+            let inner = PathBuf::from(raw.name());
+            if !self.members.selects(archive, &inner) {
+                self.skipped += 1;
+                continue;
+            }
+            drop(raw);
+            let entry = zip.by_index(index)
+                .with_context(|| format!("Could not read zip archive: {:?}", archive))?;
+            let name = format!("{}!{}", archive, inner.display());
+            let mut reader = BufReader::new(Metered { inner: entry, budget: self.budget });
+            self.member(&name, &inner, &mut reader, depth)?;
+        }
+        Ok(())
+    }
+
+    // Search the member `name`, stored as `inner` in an archive `depth` levels down, or its
+    // own members if it is an archive and `--archive-depth` goes that deep.
+    fn member(&mut self, name: &str, inner: &Path, reader: &mut dyn BufRead, depth: usize)
+        -> AnyhowResult<()>
+    {
+        if depth < self.members.depth && is_tar(inner) {
+            let reader = crate::decode::reader(inner, Box::new(reader), true,
+                &self.members.decoders)?;
+            return self.tar(name, reader, depth + 1);
+        }
+        if depth < self.members.depth && is_zip(inner) {
+            // Read whole, since it has to be seekable. The budget bounds how much that is.
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)
+                .with_context(|| format!("Could not read zip archive: {:?}", name))?;
+            return self.zip(name, Cursor::new(bytes), depth + 1);
+        }
+        (self.search)(name, reader)
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn for_each_tar_entry<F>(path: &Path, _reader: Box<dyn BufRead + '_>, _size: u64,
+    _members: &Members, _search: F) -> AnyhowResult<usize>
+{
+    Err(anyhow::anyhow!("Cannot read {:?}: archives need the `compression` feature", path))
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn for_each_zip_entry<F>(path: &Path, _file: impl Read + Seek, _size: u64,
+    _members: &Members, _search: F) -> AnyhowResult<usize>
+{
+    Err(anyhow::anyhow!("Cannot read {:?}: archives need the `compression` feature", path))
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    const FILES: &[(&str, &str)] = &[("app.log", "a"), ("logs/old.log", "b"), ("README", "c")];
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *contents).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    // The names of the members of `archive`, named `name`, that `members` searches, and how
+    // many were skipped.
+    fn searched(name: &str, archive: &[u8], members: &Members)
+        -> AnyhowResult<(Vec<String>, usize)>
+    {
+        let mut names = Vec::new();
+        let search = |name: &str, reader: &mut dyn BufRead| {
+            std::io::copy(reader, &mut std::io::sink())?;
+            names.push(name.to_string());
+            Ok(())
+        };
+        let size = archive.len() as u64;
+        let skipped = if is_zip(Path::new(name)) {
+            for_each_zip_entry(Path::new(name), Cursor::new(archive), size, members, search)?
+        } else {
+            for_each_tar_entry(Path::new(name), Box::new(archive), size, members, search)?
+        };
+        Ok((names, skipped))
+    }
+
+    fn globbed(globs: &[&str], zip: bool) -> (Vec<String>, usize) {
+        let globs: Vec<_> = globs.iter().map(|glob| glob.to_string()).collect();
+        let members = Members::new(&globs, false).unwrap();
+        let files: Vec<_> = FILES.iter().map(|(name, text)| (*name, text.as_bytes())).collect();
+        if zip {
+            searched("a.zip", &self::zip(&files), &members).unwrap()
+        } else {
+            searched("a.tar", &tar(&files), &members).unwrap()
+        }
+    }
+
+    #[test]
+    fn archive_globs_select_members() {
+        for zip in [false, true] {
+            let archive = if zip { "a.zip" } else { "a.tar" };
+            let (names, skipped) = globbed(&[], zip);
+            assert_eq!(names.len(), 3);
+            assert_eq!(skipped, 0);
+            let (names, skipped) = globbed(&["*.log"], zip);
+            assert_eq!(names, [format!("{}!app.log", archive), format!("{}!logs/old.log", archive)]);
+            assert_eq!(skipped, 1);
+            let (names, skipped) = globbed(&["logs/*", "README"], zip);
+            assert_eq!(names, [format!("{}!logs/old.log", archive), format!("{}!README", archive)]);
+            assert_eq!(skipped, 1);
+        }
+    }
+
+    #[test]
+    fn nested_archives_are_searched_down_to_the_depth() {
+        let inner = zip(&[("deep.txt", b"x")]);
+        let middle = tar(&[("inner.zip", &inner), ("b.txt", b"y")]);
+        let outer = zip(&[("middle.tar", &middle)]);
+        let members = |depth| Members::new(&[], false).unwrap()
+            .depth(depth, &DecoderRegistry::default());
+        let (names, _) = searched("a.zip", &outer, &members(0)).unwrap();
+        assert_eq!(names, ["a.zip!middle.tar"]);
+        let (names, _) = searched("a.zip", &outer, &members(1)).unwrap();
+        assert_eq!(names, ["a.zip!middle.tar!inner.zip", "a.zip!middle.tar!b.txt"]);
+        let (names, _) = searched("a.zip", &outer, &members(2)).unwrap();
+        assert_eq!(names, ["a.zip!middle.tar!inner.zip!deep.txt", "a.zip!middle.tar!b.txt"]);
+    }
+
+    #[test]
+    fn archives_unpacking_too_much_are_given_up() {
+        let zeros = vec![0; 1 << 20];
+        let bomb = zip(&[("zeros", &zeros)]);
+        let nested = tar(&[("bomb.zip", &bomb)]);
+        let members = |max_size, max_ratio| Members::new(&[], false).unwrap()
+            .depth(1, &DecoderRegistry::default())
+            .limits(max_size, max_ratio);
+        assert!(searched("a.tar", &nested, &members(u64::MAX, u64::MAX)).is_ok());
+        let err = searched("a.tar", &nested, &members(1 << 19, u64::MAX)).unwrap_err();
+        assert!(format!("{:#}", err).contains("--archive-max-size"), "{:#}", err);
+        let err = searched("a.tar", &nested, &members(u64::MAX, 10)).unwrap_err();
+        assert!(format!("{:#}", err).contains("--archive-max-ratio"), "{:#}", err);
+    }
+}
@@ -0,0 +1,165 @@
+//! Batching of the printed lines, so that the writer underneath gets one call for many
+//! lines rather than one for every `write!` of the printer.
+
+use std::io::{Result, Write};
+
+/// Collects what is written and hands it to the writer underneath in one call once
+/// `batch_lines` complete lines have been collected. Only complete lines are handed over,
+/// so that the lines of the results never end up cut in the middle by anything else
+/// printed in between, like another thread or a pager quitting.
+///
+/// Like a `BufWriter`, the lines left are written when it is dropped, and errors are lost
+/// then: call `flush` to see them.
+pub struct Batched<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+    // Number of complete lines in `buffer`.
+    lines: usize,
+    batch_lines: usize,
+}
+
+impl<W: Write> Batched<W> {
+    pub fn new(writer: W, batch_lines: usize) -> Batched<W> {
+        Batched { writer, buffer: Vec::new(), lines: 0, batch_lines }
+    }
+
+    // Hand the complete lines to the writer, keeping the one being written, if any.
+    fn write_lines(&mut self) -> Result<()> {
+        let end = match memchr::memrchr(b'\n', &self.buffer) {
+            Some(end) => end + 1,
+            None => return Ok(()),
+        };
+        self.lines = 0;
+        // Dropped even on errors, which would otherwise be written again on the next batch.
+        let result = self.writer.write_all(&self.buffer[..end]);
+        self.buffer.drain(..end);
+        result
+    }
+}
+
+impl<W: Write> Write for Batched<W> {
+    fn write(&mut self, bytes: &[u8]) -> Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        self.lines += memchr::memchr_iter(b'\n', bytes).count();
+        if self.lines >= self.batch_lines {
+            self.write_lines()?;
+        }
+        Ok(bytes.len())
+    }
+
+    /// Write everything collected, the line being written included, and flush the writer.
+    fn flush(&mut self) -> Result<()> {
+        self.lines = 0;
+        let result = self.writer.write_all(&self.buffer);
+        self.buffer.clear();
+        result?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for Batched<W> {
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            let _ = self.writer.write_all(&self.buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Records each call it gets, and can be told to fail them.
+    #[derive(Clone, Default)]
+    struct Calls {
+        writes: Rc<RefCell<Vec<String>>>,
+        flushes: Rc<RefCell<usize>>,
+        failing: Rc<RefCell<bool>>,
+    }
+
+    impl Write for Calls {
+        fn write(&mut self, bytes: &[u8]) -> Result<usize> {
+            if *self.failing.borrow() {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            self.writes.borrow_mut().push(String::from_utf8(bytes.to_vec()).unwrap());
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            *self.flushes.borrow_mut() += 1;
+            Ok(())
+        }
+    }
+
+    impl Calls {
+        fn writes(&self) -> Vec<String> {
+            self.writes.borrow().clone()
+        }
+    }
+
+    #[test]
+    fn nothing_is_written_before_a_batch_is_complete() {
+        let calls = Calls::default();
+        let mut batched = Batched::new(calls.clone(), 3);
+        write!(batched, "a:1\nb:").unwrap();
+        writeln!(batched, "2").unwrap();
+        assert!(calls.writes().is_empty());
+    }
+
+    #[test]
+    fn a_batch_is_written_in_one_call() {
+        let calls = Calls::default();
+        let mut batched = Batched::new(calls.clone(), 3);
+        for line in 1..=3 {
+            write!(batched, "path:{}:", line).unwrap();
+            writeln!(batched, "text").unwrap();
+        }
+        assert_eq!(calls.writes(), ["path:1:text\npath:2:text\npath:3:text\n"]);
+    }
+
+    #[test]
+    fn the_line_being_written_waits_for_the_next_batch() {
+        let calls = Calls::default();
+        let mut batched = Batched::new(calls.clone(), 2);
+        write!(batched, "a\nb\nc").unwrap();
+        assert_eq!(calls.writes(), ["a\nb\n"]);
+        write!(batched, "\nd\n").unwrap();
+        assert_eq!(calls.writes(), ["a\nb\n", "c\nd\n"]);
+    }
+
+    #[test]
+    fn flush_writes_everything_and_flushes_the_writer() {
+        let calls = Calls::default();
+        let mut batched = Batched::new(calls.clone(), 10);
+        write!(batched, "a\nb").unwrap();
+        batched.flush().unwrap();
+        assert_eq!(calls.writes(), ["a\nb"]);
+        assert_eq!(*calls.flushes.borrow(), 1);
+        batched.flush().unwrap();
+        assert_eq!(calls.writes(), ["a\nb"]);
+    }
+
+    #[test]
+    fn drop_writes_what_is_left() {
+        let calls = Calls::default();
+        let mut batched = Batched::new(calls.clone(), 10);
+        write!(batched, "a\nb").unwrap();
+        drop(batched);
+        assert_eq!(calls.writes(), ["a\nb"]);
+    }
+
+    #[test]
+    fn errors_are_reported_once() {
+        let calls = Calls::default();
+        let mut batched = Batched::new(calls.clone(), 1);
+        *calls.failing.borrow_mut() = true;
+        assert!(writeln!(batched, "a").is_err());
+        *calls.failing.borrow_mut() = false;
+        writeln!(batched, "b").unwrap();
+        assert_eq!(calls.writes(), ["b\n"]);
+    }
+}
@@ -0,0 +1,113 @@
+//! `--bench`: run the same search again and again with the results thrown away, and report
+//! how fast it went, to compare engines, buffer sizes and thread counts.
+
+use anyhow::{anyhow, Context, Result as AnyhowResult};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::Write;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::printer::format_size;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::SearchConfig;
+
+/// How long to keep searching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bench {
+    /// This many times.
+    Runs(usize),
+    /// Until this much time has passed, finishing the search under way.
+    Duration(Duration),
+}
+
+/// Parse a `--bench` value: a number of runs like `10`, or a duration like `30s` or `500ms`.
+pub fn parse(value: &str) -> AnyhowResult<Bench> {
+    let duration = |digits: &str, unit: fn(u64) -> Duration| -> AnyhowResult<Bench> {
+        let number = digits.parse()
+            .with_context(|| format!("Invalid duration: {:?}", value))?;
+        Ok(Bench::Duration(unit(number)))
+    };
+    if let Some(digits) = value.strip_suffix("ms") {
+        duration(digits, Duration::from_millis)
+    } else if let Some(digits) = value.strip_suffix('s') {
+        duration(digits, Duration::from_secs)
+    } else if let Some(digits) = value.strip_suffix('m') {
+        duration(digits, |minutes| Duration::from_secs(minutes * 60))
+    } else {
+        match value.parse() {
+            Ok(0) => Err(anyhow!("The number of runs cannot be 0")),
+            Ok(runs) => Ok(Bench::Runs(runs)),
+            Err(_) => Err(anyhow!("Invalid number of runs or duration: {:?} \
+                (expected e.g. 10, 30s or 500ms)", value)),
+        }
+    }
+}
+
+// The time and throughput of one search.
+#[cfg(not(target_arch = "wasm32"))]
+struct Run {
+    elapsed: Duration,
+    bytes: u64,
+    lines: u64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Run {
+    fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+
+    fn lines_per_second(&self) -> f64 {
+        self.lines as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+}
+
+/// Run the search of `config` as `bench` says, without printing its results, then report
+/// each run and the throughput of the fastest and the median one to `writer`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run(config: &SearchConfig, bench: Bench, writer: &mut impl Write) -> AnyhowResult<()> {
+    let start = Instant::now();
+    let mut runs = Vec::new();
+    loop {
+        let run_start = Instant::now();
+        let stats = crate::run(config, &mut std::io::sink(), &AtomicBool::new(false))?;
+        let run = Run { elapsed: run_start.elapsed(), bytes: stats.bytes, lines: stats.lines };
+        writeln!(writer, "Run {}: {} and {} lines in {:.3} s, {}/s", runs.len() + 1,
+            format_size(run.bytes), run.lines, run.elapsed.as_secs_f64(),
+            format_size(run.bytes_per_second() as u64))?;
+        runs.push(run);
+        let done = match bench {
+            Bench::Runs(count) => runs.len() >= count,
+            Bench::Duration(duration) => start.elapsed() >= duration,
+        };
+        if done {
+            break;
+        }
+    }
+
+    // The first run may have read the files from the disk, the others most likely find them
+    // in the page cache. Both are worth knowing, but they do not mix.
+    writeln!(writer)?;
+    writeln!(writer, "First run (cold cache, unless the files were read recently): {}",
+        describe(&runs[0]))?;
+    let mut warm: Vec<_> = runs.iter().skip(1).collect();
+    if warm.is_empty() {
+        writeln!(writer, "Run more than once to measure with the files in the page cache.")?;
+        return Ok(());
+    }
+    warm.sort_by_key(|run| run.elapsed);
+    writeln!(writer, "Fastest warm run: {}", describe(warm[0]))?;
+    writeln!(writer, "Median warm run: {}", describe(warm[warm.len() / 2]))?;
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn describe(run: &Run) -> String {
+    format!("{:.3} s, {}/s, {:.0} lines/s", run.elapsed.as_secs_f64(),
+        format_size(run.bytes_per_second() as u64), run.lines_per_second())
+}
@@ -0,0 +1,58 @@
+//! Stopping a search from outside: a `CancellationToken` is shared with the search, which
+//! gives up the traversal and the file it is reading once the token is cancelled.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Cancels the searches it was given to, from any thread. Clones share the cancellation.
+/// Once cancelled, a token stays so: a new one is needed for the next search.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Stop the searches given this token. They fail with `SearchError::Cancelled`.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// The flag the search checks as it goes.
+    pub(crate) fn flag(&self) -> &AtomicBool {
+        &self.0
+    }
+}
+
+// Cancelled by Ctrl-C, once `interrupt` was called.
+static INTERRUPT: OnceLock<CancellationToken> = OnceLock::new();
+
+/// A token cancelled by Ctrl-C, for the search of the command line to stop cleanly: the
+/// results found are flushed and the exit status tells the search was cancelled. A second
+/// Ctrl-C exits at once. Ctrl-C keeps its default behavior where signals are not handled.
+pub(crate) fn interrupt() -> &'static CancellationToken {
+    INTERRUPT.get_or_init(|| {
+        #[cfg(target_os = "linux")]
+        // SAFETY: the handler only makes atomic loads and stores, or exits.
+        unsafe {
+            let handler: extern "C" fn(libc::c_int) = interrupted;
+            libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        }
+        CancellationToken::new()
+    })
+}
+
+#[cfg(target_os = "linux")]
+extern "C" fn interrupted(_signal: libc::c_int) {
+    match INTERRUPT.get() {
+        Some(token) if !token.is_cancelled() => token.cancel(),
+        // As the default handler would, with the status of a process killed by SIGINT.
+        // SAFETY: `_exit` is async-signal-safe.
+        _ => unsafe { libc::_exit(130) },
+    }
+}
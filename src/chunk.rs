@@ -0,0 +1,176 @@
+//! Searching one huge file on several threads: the file is cut into chunks at line
+//! boundaries, which are searched in parallel and printed in order.
+
+use anyhow::Result as AnyhowResult;
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+
+use crate::sink::Sink;
+use crate::{collect, encoding, lines, matcher, record, Cli, OutputError, Stats};
+
+/// Smaller files are searched on a single thread: their chunks would not keep the threads
+/// busy for long enough to make up for handing them out.
+pub const MIN_SIZE: usize = 64 << 20;
+
+// Size of the chunks, before they are extended to the end of their last line.
+const CHUNK_SIZE: usize = 8 << 20;
+
+/// Whether the mapped file `bytes` is worth searching in chunks on `threads` threads, and
+/// can be with the options of `args`. Chunks are searched as UTF-8 text with no context:
+/// files to decode or transcode, binary files, and context or `--max-count`, which carry
+/// over from one line to the next, are searched from start to end.
+pub fn applies(bytes: &[u8], args: &Cli, threads: usize) -> bool {
+    const BYTE_ORDER_MARKS: [&[u8]; 3] = [b"\xef\xbb\xbf", b"\xff\xfe", b"\xfe\xff"];
+    let head = &bytes[..bytes.len().min(crate::BINARY_CHECK_SIZE as usize)];
+    threads > 1
+        && bytes.len() >= MIN_SIZE
+        && !args.search_zip
+        && args.decoders.is_empty()
+        && matches!(args.encoding, encoding::Encoding::Auto)
+        && !BYTE_ORDER_MARKS.iter().any(|mark| bytes.starts_with(mark))
+        && (args.text || args.null_data || !head.contains(&0))
+        && args.context().is_none()
+        && args.max_count.is_none()
+}
+
+/// Cut `bytes` into chunks of about `CHUNK_SIZE` bytes that end after a `terminator`, the
+/// last one aside. A line is never split, so each match is found in exactly one chunk.
+fn chunks(bytes: &[u8], terminator: u8) -> Vec<Range<usize>> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let end = (start + CHUNK_SIZE).min(bytes.len());
+        let end = memchr::memchr(terminator, &bytes[end - 1..])
+            .map_or(bytes.len(), |terminator| end + terminator);
+        chunks.push(start..end);
+        start = end;
+    }
+    chunks
+}
+
+/// What a worker thread found in a chunk, for the thread printing to print.
+struct Searched {
+    recorder: record::Recorder,
+    stats: Stats,
+    // What `--count` counts and whether a line matched, as `search_lines` returns them.
+    result: AnyhowResult<(usize, bool)>,
+}
+
+/// Search the mapped file `bytes` in chunks on `threads` threads. Only the calling thread
+/// prints, the chunks in order, so that the output is the same as when searching it whole.
+#[allow(clippy::too_many_arguments)]
+pub fn search(name: &str, bytes: &[u8], threads: usize, args: &Cli, matcher: &dyn matcher::Matcher,
+    printer: &mut impl Sink, stats: &mut Stats, stop: &AtomicBool) -> AnyhowResult<()>
+{
+    printer.on_file_start(name);
+    stats.searched += 1;
+    let terminator = crate::line_terminator(args);
+    let chunks = chunks(bytes, terminator);
+    let (chunk_sender, chunk_receiver) = mpsc::channel::<usize>();
+    let chunk_receiver = Mutex::new(chunk_receiver);
+    let (result_sender, result_receiver) = mpsc::channel::<(usize, Searched)>();
+    let mut collector = collect::Collector::new(true);
+    // Set once the chunks left need not be searched: `stop` was set, a chunk could not be
+    // searched, or a line matched for `-l`, `-L` or `-q`, which is all they need.
+    let chunks_stop = AtomicBool::new(false);
+    let first_match_only = args.files_with_matches || args.files_without_match || args.quiet;
+    let printed = std::thread::scope(|scope| {
+        for _ in 0..threads {
+            let result_sender = result_sender.clone();
+            let (chunk_receiver, chunks_stop, chunks) = (&chunk_receiver, &chunks_stop, &chunks);
+            scope.spawn(move || loop {
+                let next = chunk_receiver.lock().expect("the queue is never poisoned").recv();
+                let index = match next {
+                    Ok(index) => index,
+                    Err(_) => break,
+                };
+                let mut searched = Searched {
+                    recorder: record::Recorder::default(),
+                    stats: Stats::default(),
+                    result: Ok((0, false)),
+                };
+                if !chunks_stop.load(Ordering::Relaxed) {
+                    let range = chunks[index].clone();
+                    let mut reader = &bytes[range.clone()];
+                    let mut lines = lines::LineReader::new(&mut reader, terminator,
+                        crate::max_line_length(args));
+                    searched.result = crate::search_lines(&mut lines, range.start as u64, args,
+                        matcher, &mut searched.recorder, &mut searched.stats, chunks_stop);
+                    // An error only stops the search once the chunks before it are printed.
+                    if first_match_only && searched.stats.matches > 0 {
+                        chunks_stop.store(true, Ordering::Relaxed);
+                    }
+                }
+                if result_sender.send((index, searched)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_sender);
+
+        // Only a few chunks are handed out ahead of the next one to print, so that the
+        // results waiting to be printed stay few, however large the file.
+        let ahead = threads * 2;
+        let mut printed = Printed::default();
+        let mut result = Ok(());
+        let mut sent = 0;
+        while result.is_ok() && printed.chunks < chunks.len() {
+            if stop.load(Ordering::Relaxed) {
+                chunks_stop.store(true, Ordering::Relaxed);
+            }
+            if sent < chunks.len() && sent < printed.chunks + ahead {
+                // The workers only stop once the sender is dropped.
+                chunk_sender.send(sent).expect("the workers outlive the sender");
+                sent += 1;
+                continue;
+            }
+            let (index, searched) =
+                result_receiver.recv().expect("every chunk sent gets a result");
+            collector.push(index, searched);
+            result = print_collected(&mut collector, printer, stats, &mut printed);
+        }
+        drop(chunk_sender);
+        // Whatever is still being searched is of no use after an error.
+        chunks_stop.store(true, Ordering::Relaxed);
+        result.map(|()| printed)
+    })?;
+
+    if printed.count > 0 {
+        printer.on_count(printed.count).map_err(OutputError)?;
+    }
+    if (args.files_with_matches || args.files_without_match) && !args.quiet {
+        crate::list_file(printed.found, args, printer)?;
+    }
+    printer.on_file_end().map_err(OutputError)?;
+    Ok(())
+}
+
+/// The chunks printed so far, and the totals of what was found in them.
+#[derive(Default)]
+struct Printed {
+    chunks: usize,
+    lines: u64,
+    count: usize,
+    found: bool,
+}
+
+// Print the results of the chunks that are next in turn, numbering their lines from the
+// end of the chunks before them. Stops at the first chunk that could not be searched, once
+// what was found in it before the error is printed.
+fn print_collected(collector: &mut collect::Collector<Searched>, printer: &mut impl Sink,
+    stats: &mut Stats, printed: &mut Printed) -> AnyhowResult<()>
+{
+    while let Some(mut searched) = collector.pop() {
+        searched.recorder.shift_lines(printed.lines);
+        searched.recorder.replay(printer).map_err(OutputError)?;
+        stats.add(&searched.stats);
+        printed.chunks += 1;
+        printed.lines += searched.stats.lines;
+        let (count, found) = searched.result?;
+        printed.count += count;
+        printed.found |= found;
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! Puts the results of the files searched on worker threads back in the order the files
+//! were handed out, so that `--threads` does not change the output.
+
+use std::collections::BTreeMap;
+
+/// Holds results back until the ones before them have been taken.
+pub struct Collector<T> {
+    ordered: bool,
+    // Index of the result to release next.
+    next: usize,
+    waiting: BTreeMap<usize, T>,
+}
+
+impl<T> Collector<T> {
+    /// A collector releasing results in order, or as they come unless `ordered` is set.
+    pub fn new(ordered: bool) -> Collector<T> {
+        Collector { ordered, next: 0, waiting: BTreeMap::new() }
+    }
+
+    /// Add the result of the file handed out as `index`th, counting from 0. Every index
+    /// needs a result, or none after it is released.
+    pub fn push(&mut self, index: usize, result: T) {
+        self.waiting.insert(index, result);
+    }
+
+    /// Take the next result in turn, if it has come.
+    pub fn pop(&mut self) -> Option<T> {
+        if !self.ordered {
+            return self.waiting.pop_first().map(|(_, result)| result);
+        }
+        let result = self.waiting.remove(&self.next)?;
+        self.next += 1;
+        Some(result)
+    }
+}
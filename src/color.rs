@@ -0,0 +1,219 @@
+//! Colors of the output. Without the `color` feature nothing is ever colored: there is no
+//! `Style`, and so no `Theme`.
+
+#[cfg(feature = "color")]
+pub use ansi_term::Style;
+#[cfg(feature = "color")]
+use ansi_term::Colour;
+use anyhow::{anyhow, Result as AnyhowResult};
+
+/// When to color the output, as picked with `--color`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    /// Only on terminals, and only if the `NO_COLOR` environment variable is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+/// Parse a `--color` value.
+pub fn parse(choice: &str) -> AnyhowResult<ColorChoice> {
+    match choice {
+        "auto" => Ok(ColorChoice::Auto),
+        "always" => Ok(ColorChoice::Always),
+        "never" => Ok(ColorChoice::Never),
+        _ => Err(anyhow!("Unknown color choice: {} (expected auto, always or never)", choice)),
+    }
+}
+
+impl ColorChoice {
+    /// Whether to color output going to a terminal, or elsewhere.
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // See https://no-color.org: any non-empty value turns colors off.
+            ColorChoice::Auto => {
+                terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
+
+/// A style of text, of which there is none without the `color` feature.
+#[cfg(not(feature = "color"))]
+#[derive(Debug, Clone, Copy)]
+pub enum Style {}
+
+/// The styles of the parts of the output, as changed with `--colors`.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub path: Style,
+    pub line_number: Style,
+    pub column: Style,
+    pub matched: Style,
+}
+
+#[cfg(feature = "color")]
+impl Default for Theme {
+    // The colors grep uses.
+    fn default() -> Theme {
+        Theme {
+            path: Colour::Purple.normal(),
+            line_number: Colour::Green.normal(),
+            column: Style::new(),
+            matched: Colour::Red.bold(),
+        }
+    }
+}
+
+#[cfg(feature = "color")]
+impl Theme {
+    /// Change the style of one part of the output as `spec` says.
+    fn apply(&mut self, spec: &ColorSpec) {
+        let style = match spec.part {
+            Part::Path => &mut self.path,
+            Part::LineNumber => &mut self.line_number,
+            Part::Column => &mut self.column,
+            Part::Match => &mut self.matched,
+        };
+        *style = match spec.change {
+            Change::None => Style::new(),
+            Change::Foreground(colour) => style.fg(colour),
+            Change::Background(colour) => style.on(colour),
+            Change::Bold(bold) => Style { is_bold: bold, ..*style },
+            Change::Italic(italic) => Style { is_italic: italic, ..*style },
+            Change::Underline(underline) => Style { is_underline: underline, ..*style },
+        };
+    }
+}
+
+/// The theme of the output: the colors grep uses, changed by `specs` in turn.
+#[cfg(feature = "color")]
+pub fn theme(specs: &[ColorSpec]) -> Option<Theme> {
+    let mut theme = Theme::default();
+    for spec in specs {
+        theme.apply(spec);
+    }
+    Some(theme)
+}
+
+#[cfg(not(feature = "color"))]
+pub fn theme(_specs: &[ColorSpec]) -> Option<Theme> {
+    None
+}
+
+/// `text` painted in `style`.
+#[cfg(feature = "color")]
+pub fn paint(style: Style, text: String) -> String {
+    style.paint(text).to_string()
+}
+
+#[cfg(not(feature = "color"))]
+pub fn paint(style: Style, _text: String) -> String {
+    match style {}
+}
+
+/// `message` in red, as errors are shown on terminals.
+pub fn error(message: String) -> String {
+    #[cfg(feature = "color")]
+    return Colour::Red.paint(message).to_string();
+    #[cfg(not(feature = "color"))]
+    message
+}
+
+/// One `--colors` value, like `match:fg:yellow` or `path:none`.
+#[cfg(feature = "color")]
+#[derive(Debug, Clone, Copy)]
+pub struct ColorSpec {
+    part: Part,
+    change: Change,
+}
+
+/// A `--colors` value, of which there is none without the `color` feature.
+#[cfg(not(feature = "color"))]
+#[derive(Debug, Clone, Copy)]
+pub enum ColorSpec {}
+
+#[cfg(feature = "color")]
+#[derive(Debug, Clone, Copy)]
+enum Part {
+    Path,
+    LineNumber,
+    Column,
+    Match,
+}
+
+#[cfg(feature = "color")]
+#[derive(Debug, Clone, Copy)]
+enum Change {
+    // Back to the terminal's default style.
+    None,
+    Foreground(Colour),
+    Background(Colour),
+    Bold(bool),
+    Italic(bool),
+    Underline(bool),
+}
+
+/// Parse a `--colors` value: `{part}:none`, `{part}:fg:{color}`, `{part}:bg:{color}` or
+/// `{part}:style:{style}`. Parts are `path`, `line`, `column` and `match`. Colors are names like
+/// `red`, numbers from the 256-color palette or `r,g,b` triples. Styles are `bold`, `italic`
+/// and `underline`, each with a `no` variant.
+#[cfg(feature = "color")]
+pub fn parse_spec(spec: &str) -> AnyhowResult<ColorSpec> {
+    let fields: Vec<_> = spec.split(':').collect();
+    let part = match fields[0] {
+        "path" => Part::Path,
+        "line" => Part::LineNumber,
+        "column" => Part::Column,
+        "match" => Part::Match,
+        part => return Err(anyhow!("Unknown part in {:?}: {} \
+            (expected path, line, column or match)", spec, part)),
+    };
+    let change = match fields[1..] {
+        ["none"] => Change::None,
+        ["fg", colour] => Change::Foreground(parse_colour(colour)?),
+        ["bg", colour] => Change::Background(parse_colour(colour)?),
+        ["style", "bold"] => Change::Bold(true),
+        ["style", "nobold"] => Change::Bold(false),
+        ["style", "italic"] => Change::Italic(true),
+        ["style", "noitalic"] => Change::Italic(false),
+        ["style", "underline"] => Change::Underline(true),
+        ["style", "nounderline"] => Change::Underline(false),
+        _ => return Err(anyhow!("Invalid color spec: {:?} \
+            (expected e.g. match:fg:yellow, path:style:bold or line:none)", spec)),
+    };
+    Ok(ColorSpec { part, change })
+}
+
+#[cfg(not(feature = "color"))]
+pub fn parse_spec(spec: &str) -> AnyhowResult<ColorSpec> {
+    Err(anyhow!("Cannot use --colors {:?}: colors need the `color` feature", spec))
+}
+
+#[cfg(feature = "color")]
+fn parse_colour(colour: &str) -> AnyhowResult<Colour> {
+    let named = match colour {
+        "black" => Some(Colour::Black),
+        "red" => Some(Colour::Red),
+        "green" => Some(Colour::Green),
+        "yellow" => Some(Colour::Yellow),
+        "blue" => Some(Colour::Blue),
+        "magenta" | "purple" => Some(Colour::Purple),
+        "cyan" => Some(Colour::Cyan),
+        "white" => Some(Colour::White),
+        _ => None,
+    };
+    if let Some(named) = named {
+        return Ok(named);
+    }
+    if let Ok(index) = colour.parse() {
+        return Ok(Colour::Fixed(index));
+    }
+    let rgb: Vec<_> = colour.split(',').map(str::parse).collect();
+    match rgb[..] {
+        [Ok(red), Ok(green), Ok(blue)] => Ok(Colour::RGB(red, green, blue)),
+        _ => Err(anyhow!("Unknown color: {} (expected a name, 0-255 or r,g,b)", colour)),
+    }
+}
@@ -0,0 +1,23 @@
+use anyhow::{Context, Result as AnyhowResult};
+
+use std::ffi::OsString;
+
+/// The environment variable naming the configuration file.
+pub const PATH_VARIABLE: &str = "GRRS_CONFIG_PATH";
+
+/// Read the arguments stored in the file named by `GRRS_CONFIG_PATH`, if it is set. The file
+/// holds one argument per line, like `--colors=match:fg:yellow`; empty lines and lines starting
+/// with `#` are ignored. The arguments go before those of the command line, which win.
+pub fn args() -> AnyhowResult<Vec<OsString>> {
+    let path = match std::env::var_os(PATH_VARIABLE) {
+        Some(path) if !path.is_empty() => path,
+        _ => return Ok(Vec::new()),
+    };
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Could not read the config file: {:?}", path))?;
+    Ok(contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(OsString::from)
+        .collect())
+}
@@ -0,0 +1,254 @@
+//! Turning the bytes of a file into the text to search. A `Decoder` is picked for each file
+//! by its extension, or else by the bytes it starts with. The compression formats are built
+//! in, for `-z/--search-zip`. Other decoders, such as extractors of the text of PDF or docx
+//! files, are added to a `DecoderRegistry` given to `SearchConfigBuilder::decoders`.
+
+use anyhow::{Context, Result as AnyhowResult};
+
+use std::fmt::{self, Debug, Formatter};
+use std::io::{BufRead, BufReader, Read, Result};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::encoding::{self, Encoding};
+
+/// Turns the bytes of a file in some format into the text to search, as they are read.
+/// Adding a compression format only takes an implementation and an entry in `decompressors`.
+pub trait Decoder: Send + Sync {
+    /// File extensions of the format, without the leading dot.
+    fn extensions(&self) -> &[&str];
+
+    /// The bytes every file of the format starts with. Empty, the default, if there are none.
+    fn magic(&self) -> &[u8] {
+        &[]
+    }
+
+    /// Wrap `input`, the bytes of a file, in a reader yielding its text.
+    fn decode<'a>(&self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>>;
+}
+
+/// Reads files as they are, as those no decoder is picked for are. Added for extensions, it
+/// keeps their files from being decoded because of the bytes they start with.
+#[derive(Debug, Clone, Copy)]
+pub struct Identity {
+    extensions: &'static [&'static str],
+}
+
+impl Identity {
+    pub fn new(extensions: &'static [&'static str]) -> Identity {
+        Identity { extensions }
+    }
+}
+
+impl Decoder for Identity {
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+
+    fn decode<'a>(&self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(input))
+    }
+}
+
+/// Transcodes files in another encoding than UTF-8, such as Latin-1 or Shift JIS, as
+/// `--encoding` does for every file. A byte-order mark still wins over the encoding.
+#[derive(Debug, Clone, Copy)]
+pub struct Transcoder {
+    encoding: &'static encoding_rs::Encoding,
+    extensions: &'static [&'static str],
+}
+
+impl Transcoder {
+    /// Transcode the files with `extensions` from the encoding called `label`, a WHATWG
+    /// label like `latin1` or `shift_jis`. `None` if there is no such encoding.
+    pub fn new(label: &str, extensions: &'static [&'static str]) -> Option<Transcoder> {
+        let encoding = encoding_rs::Encoding::for_label(label.as_bytes())?;
+        Some(Transcoder { encoding, extensions })
+    }
+}
+
+impl Decoder for Transcoder {
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+
+    fn decode<'a>(&self, input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(encoding::decoder(input, Encoding::Label(self.encoding))))
+    }
+}
+
+/// The decoders added to the built-in ones, which they are picked before, in the order they
+/// were added. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct DecoderRegistry {
+    decoders: Vec<Arc<dyn Decoder>>,
+}
+
+impl DecoderRegistry {
+    pub fn new() -> DecoderRegistry {
+        DecoderRegistry::default()
+    }
+
+    pub fn add(&mut self, decoder: impl Decoder + 'static) {
+        self.decoders.push(Arc::new(decoder));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decoders.is_empty()
+    }
+}
+
+impl Debug for DecoderRegistry {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.debug_list()
+            .entries(self.decoders.iter().map(|decoder| decoder.extensions()))
+            .finish()
+    }
+}
+
+#[cfg(feature = "compression")]
+struct Gzip;
+
+#[cfg(feature = "compression")]
+impl Decoder for Gzip {
+    fn extensions(&self) -> &[&str] {
+        &["gz"]
+    }
+
+    fn magic(&self) -> &[u8] {
+        &[0x1f, 0x8b]
+    }
+
+    fn decode<'a>(&self, compressed: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        // Rotated logs are sometimes concatenated gzip streams, which `GzDecoder` would stop
+        // reading after the first member.
+        Ok(Box::new(flate2::bufread::MultiGzDecoder::new(compressed)))
+    }
+}
+
+#[cfg(feature = "bzip2")]
+struct Bzip2;
+
+#[cfg(feature = "bzip2")]
+impl Decoder for Bzip2 {
+    fn extensions(&self) -> &[&str] {
+        &["bz2"]
+    }
+
+    fn magic(&self) -> &[u8] {
+        b"BZh"
+    }
+
+    fn decode<'a>(&self, compressed: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(bzip2::bufread::MultiBzDecoder::new(compressed)))
+    }
+}
+
+#[cfg(feature = "xz")]
+struct Xz;
+
+#[cfg(feature = "xz")]
+impl Decoder for Xz {
+    fn extensions(&self) -> &[&str] {
+        &["xz"]
+    }
+
+    fn magic(&self) -> &[u8] {
+        &[0xfd, b'7', b'z', b'X', b'Z', 0x00]
+    }
+
+    fn decode<'a>(&self, compressed: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(xz2::bufread::XzDecoder::new_multi_decoder(compressed)))
+    }
+}
+
+#[cfg(feature = "zstd")]
+struct Zstd;
+
+#[cfg(feature = "zstd")]
+impl Decoder for Zstd {
+    fn extensions(&self) -> &[&str] {
+        &["zst"]
+    }
+
+    fn magic(&self) -> &[u8] {
+        &[0x28, 0xb5, 0x2f, 0xfd]
+    }
+
+    fn decode<'a>(&self, compressed: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(zstd::stream::read::Decoder::with_buffer(compressed)?))
+    }
+}
+
+#[cfg(feature = "lz4")]
+struct Lz4;
+
+#[cfg(feature = "lz4")]
+impl Decoder for Lz4 {
+    fn extensions(&self) -> &[&str] {
+        &["lz4"]
+    }
+
+    fn magic(&self) -> &[u8] {
+        &[0x04, 0x22, 0x4d, 0x18]
+    }
+
+    fn decode<'a>(&self, compressed: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(lz4_flex::frame::FrameDecoder::new(compressed)))
+    }
+}
+
+// Every format compiled into this build: none without the `compression` feature.
+fn decompressors() -> Vec<Box<dyn Decoder>> {
+    vec![
+        #[cfg(feature = "compression")]
+        Box::new(Gzip),
+        #[cfg(feature = "bzip2")]
+        Box::new(Bzip2),
+        #[cfg(feature = "xz")]
+        Box::new(Xz),
+        #[cfg(feature = "zstd")]
+        Box::new(Zstd),
+        #[cfg(feature = "lz4")]
+        Box::new(Lz4),
+    ]
+}
+
+/// Wrap the contents of the file at `path` in a reader yielding their text: decoded by the
+/// decoder picked for the file among those of `registry`, and the compression formats when
+/// `search_zip` is set, or unchanged if none is. A decoder is picked by extension first and by
+/// magic bytes second, those of `registry` before the built-in ones.
+pub fn reader<'a>(path: &Path, mut buf_reader: Box<dyn BufRead + 'a>, search_zip: bool,
+    registry: &DecoderRegistry) -> AnyhowResult<Box<dyn BufRead + 'a>>
+{
+    if !search_zip && registry.is_empty() {
+        return Ok(buf_reader);
+    }
+    let extension = path.extension().map(|extension| extension.to_string_lossy());
+    // `fill_buf` only peeks: the magic bytes stay in the buffer for the decoder to read.
+    let head = buf_reader.fill_buf()
+        .with_context(|| format!("Could not read file: {:?}", path))?;
+    let decompressors = if search_zip { decompressors() } else { Vec::new() };
+    let decoders: Vec<&dyn Decoder> = registry.decoders.iter().map(|decoder| &**decoder)
+        .chain(decompressors.iter().map(|decoder| &**decoder))
+        .collect();
+    let decoder = decoders.iter()
+        .find(|decoder| {
+            extension.as_ref().is_some_and(|extension| {
+                decoder.extensions().contains(&extension.as_ref())
+            })
+        })
+        .or_else(|| {
+            decoders.iter().find(|decoder| {
+                !decoder.magic().is_empty() && head.starts_with(decoder.magic())
+            })
+        });
+    match decoder {
+        Some(decoder) => {
+            let decoded = decoder.decode(buf_reader)
+                .with_context(|| format!("Could not decode file: {:?}", path))?;
+            Ok(Box::new(BufReader::new(decoded)))
+        }
+        None => Ok(buf_reader),
+    }
+}
@@ -0,0 +1,41 @@
+use anyhow::{anyhow, Result as AnyhowResult};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+use std::io::{BufRead, BufReader, Read};
+
+/// The `--encoding` of the searched files.
+#[derive(Debug, Clone, Copy)]
+pub enum Encoding {
+    /// Pick the encoding from a byte-order mark, reading the file as UTF-8 if there is none.
+    Auto,
+    /// Transcode from this encoding, unless the file starts with a byte-order mark.
+    Label(&'static encoding_rs::Encoding),
+}
+
+/// Parse an `--encoding` value: `auto` or any WHATWG label like `utf-16le` or `latin1`.
+pub fn parse(label: &str) -> AnyhowResult<Encoding> {
+    if label.eq_ignore_ascii_case("auto") {
+        return Ok(Encoding::Auto);
+    }
+    encoding_rs::Encoding::for_label(label.as_bytes())
+        .map(Encoding::Label)
+        .ok_or_else(|| anyhow!("Unknown encoding: {}", label))
+}
+
+/// Wrap `reader` so that it yields UTF-8, transcoding on the fly.
+/// A leading byte-order mark is always removed: left in place it would be part of the
+/// first line and silently break patterns that match at its start.
+pub fn decoder<'a, R: Read + 'a>(reader: R, encoding: Encoding) -> Box<dyn BufRead + 'a> {
+    let encoding = match encoding {
+        Encoding::Auto => None,
+        Encoding::Label(encoding) => Some(encoding),
+    };
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(encoding)
+        .bom_sniffing(true)
+        .strip_bom(true)
+        // UTF-8 input is handed over untouched instead of being validated a second time.
+        .utf8_passthru(true)
+        .build(reader);
+    Box::new(BufReader::new(decoder))
+}
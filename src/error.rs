@@ -0,0 +1,84 @@
+//! The errors of the library, for its users and the command line to tell apart. The search
+//! itself works with `anyhow` errors, which are sorted into a `SearchError` when they leave
+//! the library.
+
+use std::path::{Path, PathBuf};
+
+use crate::OutputError;
+
+/// Any error, with the causes that led to it.
+type Cause = Box<dyn std::error::Error + Send + Sync>;
+
+/// Why a search failed, or could not search an input.
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    /// There is no pattern, or one is not a valid regular expression.
+    #[error(transparent)]
+    Pattern(Cause),
+    /// A setting is not valid, or cannot be used along another.
+    #[error(transparent)]
+    Config(Cause),
+    /// The input at `path` could not be read.
+    #[error("Could not read {path:?}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A line to return is not valid UTF-8, and the search is not lossy. `path` is that of the
+    /// input the line is in, when the error ended the search of one.
+    #[error("Invalid UTF-8{}", in_path(path))]
+    Encoding {
+        path: Option<PathBuf>,
+        #[source]
+        source: Cause,
+    },
+    /// The results could not be written.
+    #[error("Could not write the results: {0}")]
+    Output(#[source] std::io::Error),
+    /// The search was stopped before its end.
+    #[error("Search cancelled!")]
+    Cancelled,
+    #[error(transparent)]
+    Other(Cause),
+}
+
+impl SearchError {
+    /// `err`, which ended the search of the input at `path`.
+    pub(crate) fn in_input(err: anyhow::Error, path: &Path) -> SearchError {
+        match err.downcast::<std::io::Error>() {
+            Ok(source) => SearchError::Io { path: path.to_path_buf(), source },
+            Err(err) => match SearchError::from(err) {
+                SearchError::Encoding { path: None, source } => {
+                    SearchError::Encoding { path: Some(path.to_path_buf()), source }
+                }
+                err => err,
+            },
+        }
+    }
+}
+
+// The end of the message of an error about the input at `path`, if there is one.
+fn in_path(path: &Option<PathBuf>) -> String {
+    path.as_ref().map(|path| format!(" in {:?}", path)).unwrap_or_default()
+}
+
+impl From<anyhow::Error> for SearchError {
+    fn from(err: anyhow::Error) -> SearchError {
+        let err = match err.downcast::<SearchError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<OutputError>() {
+            Ok(OutputError(err)) => return SearchError::Output(err),
+            Err(err) => err,
+        };
+        if err.chain().any(|cause| cause.is::<regex::Error>()) {
+            SearchError::Pattern(err.into())
+        } else if err.chain().any(|cause| cause.is::<std::str::Utf8Error>()) {
+            SearchError::Encoding { path: None, source: err.into() }
+        } else {
+            SearchError::Other(err.into())
+        }
+    }
+}
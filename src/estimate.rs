@@ -0,0 +1,71 @@
+use anyhow::{Context, Result as AnyhowResult};
+
+use std::io::{BufRead, Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::matcher::Matcher;
+use crate::printer::format_size;
+use crate::walk::Walker;
+
+// How much data the calibration read goes through before extrapolating.
+const CALIBRATION_BYTES: u64 = 16 << 20;
+
+/// Walk the inputs without searching them, then report how much data would be searched
+/// and how long that is expected to take.
+pub fn estimate(walker: Walker, matcher: &dyn Matcher, writer: &mut impl Write)
+    -> AnyhowResult<()>
+{
+    let mut paths = Vec::new();
+    let mut total_bytes = 0;
+    for path in walker {
+        let path = path?;
+        let metadata = std::fs::metadata(&path)
+            .with_context(|| format!("Could not read metadata: {:?}", path))?;
+        total_bytes += metadata.len();
+        paths.push(path);
+    }
+
+    let (calibration_bytes, calibration_time) = calibrate(&paths, matcher)?;
+    writeln!(writer, "Files: {}", paths.len())?;
+    writeln!(writer, "Size: {}", format_size(total_bytes))?;
+    if calibration_bytes == 0 {
+        writeln!(writer, "Estimated time: no data to search")?;
+        return Ok(());
+    }
+    let bytes_per_second = calibration_bytes as f64 / calibration_time.as_secs_f64().max(1e-9);
+    writeln!(writer, "Calibration: {} in {:.1?} ({}/s)",
+        format_size(calibration_bytes), calibration_time, format_size(bytes_per_second as u64))?;
+    let estimate = Duration::from_secs_f64(total_bytes as f64 / bytes_per_second);
+    writeln!(writer, "Estimated time: {:.1?}", estimate)?;
+    Ok(())
+}
+
+// Time a line-by-line search over the first `CALIBRATION_BYTES` of the inputs.
+// The files are read the same way the real search reads them, so the page cache and
+// the disk are measured along with the matching itself.
+fn calibrate(paths: &[PathBuf], matcher: &dyn Matcher) -> AnyhowResult<(u64, Duration)> {
+    let start = Instant::now();
+    let mut read = 0;
+    let mut line = Vec::new();
+    for path in paths {
+        if read >= CALIBRATION_BYTES {
+            break;
+        }
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Could not open file: {:?}", path))?;
+        let mut reader = std::io::BufReader::new(file.take(CALIBRATION_BYTES - read));
+        loop {
+            line.clear();
+            let length = reader.read_until(b'\n', &mut line)
+                .with_context(|| format!("Could not read file: {:?}", path))?;
+            if length == 0 {
+                break;
+            }
+            read += length as u64;
+            // The result is irrelevant, only the time it takes to compute it matters.
+            std::hint::black_box(matcher.is_match(&line));
+        }
+    }
+    Ok((read, start.elapsed()))
+}
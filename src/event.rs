@@ -0,0 +1,119 @@
+//! The results of a search as structured events, for the machine-readable output formats.
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+use crate::sink::MatchedLine;
+#[cfg(feature = "json")]
+use crate::{FileSummary, RunStats};
+
+/// The version of the events of `--json`. It is raised when they change in a way their
+/// readers have to adapt to, such as a field removed, renamed or given another meaning. Fields
+/// and event types are added without raising it.
+#[cfg(feature = "json")]
+pub const VERSION: u32 = 1;
+
+/// The JSON Schema of the lines written by `--json`, as printed by `--json-schema`.
+#[cfg(feature = "json")]
+pub const SCHEMA: &str = include_str!("schema.json");
+
+/// An event as a line of `--json`: `{"version": 1, "type": "match", "data": {...}}`.
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+pub struct Envelope<'a> {
+    pub version: u32,
+    #[serde(flatten)]
+    pub event: &'a Event<'a>,
+}
+
+/// Something that happened during a search, as written by `--json` inside an `Envelope`.
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// Results of an input follow. Inputs without any are left out altogether.
+    Begin { path: &'a str },
+    Match(Match<'a>),
+    Context(Context<'a>),
+    /// The last result of an input was written.
+    End(FileSummary),
+    /// The search is over.
+    Summary(RunStats),
+}
+
+/// A line containing the pattern.
+#[cfg_attr(feature = "json", derive(Serialize))]
+// Some fields are only written as JSON.
+#[cfg_attr(not(feature = "json"), allow(dead_code))]
+pub struct Match<'a> {
+    pub path: &'a str,
+    /// 1-based line number.
+    pub line_number: u64,
+    /// 1-based column, in bytes, of the first occurrence.
+    pub column: usize,
+    /// Offset of the first byte of the line in the input.
+    pub absolute_offset: u64,
+    pub text: &'a str,
+    pub submatches: Vec<SubMatch<'a>>,
+    /// Identifies the match across runs, see `fingerprint`.
+    pub fingerprint: String,
+}
+
+impl<'a> Match<'a> {
+    /// The event reporting `line`, found in `path` by `pattern`.
+    pub fn new(path: &'a str, pattern: &str, line: &MatchedLine<'a>) -> Match<'a> {
+        let submatches = line.spans.iter().map(|span| SubMatch {
+            text: &line.text[span.clone()],
+            start: span.start,
+            end: span.end,
+        }).collect();
+        Match {
+            path,
+            line_number: line.number,
+            column: line.spans.first().map_or(0, |span| span.start) + 1,
+            absolute_offset: line.offset,
+            text: line.text,
+            submatches,
+            fingerprint: fingerprint(path, pattern, line.text),
+        }
+    }
+}
+
+/// A fingerprint of the match of `pattern` on the line `text` of `path`, as 16 hex digits. It
+/// leaves out the line number and the runs of whitespace of the line, so that it stays the
+/// same when lines are added above or the line is reindented. It is computed with FNV-1a
+/// rather than the hasher of `std`, which may change between Rust versions.
+pub fn fingerprint(path: &str, pattern: &str, text: &str) -> String {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let path = if cfg!(windows) { path.replace('\\', "/") } else { path.to_string() };
+    let mut path = path.as_str();
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    // NUL separates the fields, so that moving bytes from one to the next changes the hash.
+    let hash = [path, pattern, &text].join("\0").bytes()
+        .fold(OFFSET, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(PRIME));
+    format!("{:016x}", hash)
+}
+
+/// An occurrence of the pattern in a matching line.
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct SubMatch<'a> {
+    pub text: &'a str,
+    /// Byte offsets of the occurrence in the line, the end excluded.
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A line printed around a match for `-A`, `-B` or `-C`.
+#[cfg_attr(feature = "json", derive(Serialize))]
+// Some fields are only written as JSON.
+#[cfg_attr(not(feature = "json"), allow(dead_code))]
+pub struct Context<'a> {
+    pub path: &'a str,
+    pub line_number: u64,
+    pub absolute_offset: u64,
+    pub text: &'a str,
+}
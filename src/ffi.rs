@@ -0,0 +1,273 @@
+//! C bindings of `Searcher`, with the `ffi` feature, to embed the search in C and C++ tools.
+//! Build the shared library with
+//! `cargo rustc --release --lib --features ffi --crate-type cdylib`. `include/grrs.h`
+//! declares these functions. It is generated with
+//! `cbindgen --config cbindgen.toml --output include/grrs.h`.
+//!
+//! Ownership: strings are borrowed from the caller for the duration of the call only. A
+//! `GrrsSearcher` belongs to the caller, who frees it with `grrs_searcher_free`. Whatever a
+//! function hands back to the caller, such as a match or an error message, stays owned by
+//! the library and is only valid for as long as each function says.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::io::Result;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::AssertUnwindSafe;
+use std::ptr;
+
+use crate::sink::{MatchedLine, Sink};
+use crate::{Case, SearchConfig, SearchError, Searcher};
+
+/// Treat the pattern as a regular expression, in the syntax of the `regex` crate.
+pub const GRRS_REGEX: u32 = 1;
+/// Ignore the case of the letters.
+pub const GRRS_IGNORE_CASE: u32 = 1 << 1;
+/// Ignore the case of the letters, unless the pattern has an uppercase letter.
+pub const GRRS_SMART_CASE: u32 = 1 << 2;
+/// Search hidden files and directories.
+pub const GRRS_HIDDEN: u32 = 1 << 3;
+/// Follow symbolic links found in directories.
+pub const GRRS_FOLLOW: u32 = 1 << 4;
+
+/// A line matched.
+pub const GRRS_MATCHED: c_int = 0;
+/// No line matched.
+pub const GRRS_NO_MATCH: c_int = 1;
+/// The search could not be made, or some files could not be searched: see `grrs_last_error`.
+pub const GRRS_ERROR: c_int = 2;
+/// The callback stopped the search.
+pub const GRRS_STOPPED: c_int = 3;
+
+/// A set-up search, which `grrs_searcher_search` runs. Only handled through pointers.
+pub struct GrrsSearcher(Searcher);
+
+/// An occurrence of the pattern in a line: the byte offsets of its start and of its end,
+/// which is excluded.
+#[repr(C)]
+pub struct GrrsSpan {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A matching line, as handed to the callback.
+#[repr(C)]
+pub struct GrrsMatch {
+    /// The path of the file, NUL-terminated.
+    pub path: *const c_char,
+    /// 1-based.
+    pub line_number: u64,
+    /// Offset of the first byte of the line in the file.
+    pub offset: u64,
+    /// The line, without its terminator, in UTF-8. It is NUL-terminated, but `line_len` is
+    /// its length: a line may contain NUL bytes.
+    pub line: *const c_char,
+    pub line_len: usize,
+    /// The occurrences of the pattern in `line`.
+    pub spans: *const GrrsSpan,
+    pub spans_len: usize,
+}
+
+/// Called with each matching line, and the `user_data` given along. Everything `match`
+/// points to is only valid until the callback returns: copy what is to be kept. Returning
+/// anything but 0 stops the search.
+pub type GrrsCallback = Option<unsafe extern "C" fn(*const GrrsMatch, *mut c_void) -> c_int>;
+
+thread_local! {
+    // The message of the last error of a call on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Set up a search for `pattern`, with the `GRRS_*` `flags` or'ed together. Returns NULL if
+/// the pattern or the flags are invalid.
+///
+/// # Safety
+///
+/// `pattern` is a NUL-terminated string, which is not used after the call. The searcher
+/// returned belongs to the caller, who frees it with `grrs_searcher_free`.
+#[no_mangle]
+pub unsafe extern "C" fn grrs_searcher_new(pattern: *const c_char, flags: u32)
+    -> *mut GrrsSearcher
+{
+    guard(ptr::null_mut(), || {
+        let pattern = string(pattern, "pattern")?;
+        let case = if flags & GRRS_SMART_CASE != 0 {
+            Case::Smart
+        } else if flags & GRRS_IGNORE_CASE != 0 {
+            Case::Insensitive
+        } else {
+            Case::Sensitive
+        };
+        let config = SearchConfig::builder()
+            .pattern(pattern)
+            .regex(flags & GRRS_REGEX != 0)
+            .case(case)
+            .hidden(flags & GRRS_HIDDEN != 0)
+            .follow(flags & GRRS_FOLLOW != 0)
+            .build()?;
+        let searcher = GrrsSearcher(Searcher::new(config)?);
+        Ok(Box::into_raw(Box::new(searcher)))
+    })
+}
+
+/// Free a searcher made by `grrs_searcher_new`. Does nothing with NULL.
+///
+/// # Safety
+///
+/// `searcher` is NULL, or was returned by `grrs_searcher_new` and is not used after the call.
+#[no_mangle]
+pub unsafe extern "C" fn grrs_searcher_free(searcher: *mut GrrsSearcher) {
+    if !searcher.is_null() {
+        drop(Box::from_raw(searcher));
+    }
+}
+
+/// Search the file at `path`, or the files under it if it is a directory, calling `callback`
+/// with each matching line, file after file in the order of their paths. Files that cannot
+/// be searched are skipped, and `GRRS_ERROR` returned once the others were. Returns one of
+/// the `GRRS_*` statuses.
+///
+/// # Safety
+///
+/// `searcher` was returned by `grrs_searcher_new` and not freed. It may be used by several
+/// threads at once. `path` is a NUL-terminated string, which is not used after the call.
+/// `callback` may be NULL, to only tell whether anything matches.
+#[no_mangle]
+pub unsafe extern "C" fn grrs_searcher_search(searcher: *const GrrsSearcher,
+    path: *const c_char, callback: GrrsCallback, user_data: *mut c_void) -> c_int
+{
+    guard(GRRS_ERROR, || {
+        if searcher.is_null() {
+            return Err(SearchError::Config("The searcher is NULL".into()));
+        }
+        let path = string(path, "path")?;
+        let mut sink = CallbackSink {
+            callback,
+            user_data,
+            path: CString::default(),
+            line: Vec::new(),
+            spans: Vec::new(),
+            matched: false,
+            stopped: false,
+            error: None,
+        };
+        let result = (*searcher).0.search_into(path, &mut sink);
+        if sink.stopped {
+            return Ok(GRRS_STOPPED);
+        }
+        result?;
+        match sink.error {
+            Some(error) => Err(error),
+            None if sink.matched => Ok(GRRS_MATCHED),
+            None => Ok(GRRS_NO_MATCH),
+        }
+    })
+}
+
+/// Search `path` for the literal `pattern`, as `grrs_searcher_search` does with a searcher
+/// made without flags.
+///
+/// # Safety
+///
+/// As for `grrs_searcher_new` and `grrs_searcher_search`.
+#[no_mangle]
+pub unsafe extern "C" fn grrs_search_path(pattern: *const c_char, path: *const c_char,
+    callback: GrrsCallback, user_data: *mut c_void) -> c_int
+{
+    let searcher = grrs_searcher_new(pattern, 0);
+    if searcher.is_null() {
+        return GRRS_ERROR;
+    }
+    let status = grrs_searcher_search(searcher, path, callback, user_data);
+    grrs_searcher_free(searcher);
+    status
+}
+
+/// The message of the last error of a call made on this thread, or NULL if it did not fail.
+/// The message belongs to the library. It is valid until the next call made on this thread
+/// to any of these functions.
+#[no_mangle]
+pub extern "C" fn grrs_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |error| error.as_ptr()))
+}
+
+// Run `call`, keeping the error it fails with, or a panic, for `grrs_last_error` and
+// returning `failed` instead. Unwinding into C is undefined behavior.
+fn guard<T>(failed: T, call: impl FnOnce() -> std::result::Result<T, SearchError>) -> T {
+    LAST_ERROR.with(|last| last.borrow_mut().take());
+    let error = match std::panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(error)) => format!("{:#}", anyhow::Error::from(error)),
+        Err(_) => "The search panicked".to_string(),
+    };
+    // A message cannot have a NUL byte in C.
+    let error = CString::new(error.replace('\0', "\u{fffd}")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+    failed
+}
+
+// The UTF-8 string at `string`, which is called `name` in errors.
+unsafe fn string<'a>(string: *const c_char, name: &str)
+    -> std::result::Result<&'a str, SearchError>
+{
+    if string.is_null() {
+        return Err(SearchError::Config(format!("The {} is NULL", name).into()));
+    }
+    CStr::from_ptr(string).to_str()
+        .map_err(|err| SearchError::Encoding { path: None, source: err.into() })
+}
+
+// Hands the matching lines to the callback, keeping the strings it points to alive during
+// the call.
+struct CallbackSink {
+    callback: GrrsCallback,
+    user_data: *mut c_void,
+    path: CString,
+    line: Vec<u8>,
+    spans: Vec<GrrsSpan>,
+    matched: bool,
+    // Set when the callback stopped the search, which the sink fails to stop it.
+    stopped: bool,
+    // The last file that could not be searched.
+    error: Option<SearchError>,
+}
+
+impl Sink for CallbackSink {
+    fn on_file_start(&mut self, name: &str) {
+        self.path = CString::new(name.replace('\0', "\u{fffd}")).unwrap_or_default();
+    }
+
+    fn on_match(&mut self, line: &MatchedLine) -> Result<()> {
+        self.matched = true;
+        let callback = match self.callback {
+            Some(callback) => callback,
+            None => return Ok(()),
+        };
+        self.line.clear();
+        self.line.extend_from_slice(line.text.as_bytes());
+        self.line.push(0);
+        self.spans.clear();
+        let spans = line.spans.iter().map(|span| GrrsSpan { start: span.start, end: span.end });
+        self.spans.extend(spans);
+        let matched = GrrsMatch {
+            path: self.path.as_ptr(),
+            line_number: line.number,
+            offset: line.offset,
+            line: self.line.as_ptr().cast(),
+            line_len: line.text.len(),
+            spans: self.spans.as_ptr(),
+            spans_len: self.spans.len(),
+        };
+        // SAFETY: the callback was given by the caller, with its `user_data`, and `matched`
+        // points to what this sink keeps alive until the next line.
+        if unsafe { callback(&matched, self.user_data) } != 0 {
+            self.stopped = true;
+            return Err(std::io::Error::other("Stopped by the callback"));
+        }
+        Ok(())
+    }
+
+    fn on_error(&mut self, error: SearchError) {
+        self.error = Some(error);
+    }
+}
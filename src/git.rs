@@ -0,0 +1,60 @@
+use anyhow::{anyhow, Context, Result as AnyhowResult};
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::walk::Entry;
+
+/// The files git tracks below a set of directories, as the walker would name them.
+pub struct TrackedFiles {
+    files: HashSet<PathBuf>,
+    // Every directory containing a tracked file, so the walker only descends where needed.
+    dirs: HashSet<PathBuf>,
+}
+
+impl TrackedFiles {
+    /// Ask git for the tracked files below each of the `roots` that is a directory.
+    pub fn new(roots: &[PathBuf]) -> AnyhowResult<TrackedFiles> {
+        let mut tracked = TrackedFiles { files: HashSet::new(), dirs: HashSet::new() };
+        for root in roots.iter().filter(|root| root.is_dir()) {
+            for file in ls_files(root)? {
+                let path = root.join(file);
+                for dir in path.ancestors().skip(1) {
+                    if !tracked.dirs.insert(dir.to_path_buf()) {
+                        // The rest of the ancestors were added along with a previous file.
+                        break;
+                    }
+                }
+                tracked.files.insert(path);
+            }
+        }
+        Ok(tracked)
+    }
+
+    pub fn contains(&self, entry: &Entry) -> bool {
+        if entry.is_dir() {
+            self.dirs.contains(&entry.path())
+        } else {
+            self.files.contains(&entry.path())
+        }
+    }
+}
+
+// List the files tracked below `dir`, relative to it. Shelling out keeps libgit2 out of
+// the build and respects whatever git configuration the user has.
+fn ls_files(dir: &Path) -> AnyhowResult<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["ls-files", "-z"])
+        .output()
+        .context("Could not run git, is it installed?")?;
+    if !output.status.success() {
+        return Err(anyhow!("Could not list the git-tracked files in {:?}: {}",
+            dir, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(output.stdout.split(|&byte| byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(crate::walk::path_from_bytes)
+        .collect())
+}
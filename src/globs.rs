@@ -0,0 +1,77 @@
+use anyhow::{Context, Result as AnyhowResult};
+
+#[cfg(feature = "compression")]
+use std::path::Path;
+
+use crate::walk::Entry;
+
+/// A single `--include`, `--exclude` or `--archive-glob` glob.
+pub struct Glob {
+    pattern: glob::Pattern,
+    // Globs without a slash, like `*.min.js`, are matched against the file name only.
+    // The others, like `vendor/**`, are matched against the whole path.
+    match_path: bool,
+}
+
+impl Glob {
+    pub fn new(glob: &str) -> AnyhowResult<Glob> {
+        let match_path = glob.contains('/');
+        // A path glob may match at any depth, as long as it starts at a path component.
+        let anchored = if match_path && !glob.starts_with('/') && !glob.starts_with("**") {
+            format!("**/{}", glob)
+        } else {
+            glob.to_string()
+        };
+        let pattern = glob::Pattern::new(&anchored)
+            .with_context(|| format!("Invalid glob: {:?}", glob))?;
+        Ok(Glob { pattern, match_path })
+    }
+
+    fn is_match(&self, entry: &Entry) -> bool {
+        if self.match_path {
+            self.pattern.matches_path(&entry.path())
+        } else {
+            self.pattern.matches(&entry.file_name().to_string_lossy())
+        }
+    }
+
+    /// Whether the glob matches `path`, such as the path of a member inside an archive.
+    #[cfg(feature = "compression")]
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.match_path {
+            self.pattern.matches_path(path)
+        } else {
+            path.file_name().is_some_and(|name| self.pattern.matches(&name.to_string_lossy()))
+        }
+    }
+}
+
+/// Keeps or drops entries according to the `--include` and `--exclude` globs.
+pub struct GlobFilter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+}
+
+impl GlobFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> AnyhowResult<GlobFilter> {
+        Ok(GlobFilter {
+            include: include.iter().map(|glob| Glob::new(glob)).collect::<AnyhowResult<_>>()?,
+            exclude: exclude.iter().map(|glob| Glob::new(glob)).collect::<AnyhowResult<_>>()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Excluded directories are not descended into. Include globs only select files,
+    /// otherwise `--include '*.rs'` would never get past the first directory.
+    pub fn is_match(&self, entry: &Entry) -> bool {
+        if self.exclude.iter().any(|glob| glob.is_match(entry)) {
+            return false;
+        }
+        entry.is_dir()
+            || self.include.is_empty()
+            || self.include.iter().any(|glob| glob.is_match(entry))
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Context, Result as AnyhowResult};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+// The mode git records for submodules.
+const SUBMODULE_MODE: &str = "160000";
+
+/// Call `search` with a display name and the contents of every file version introduced by
+/// the commits in `range` (anything `git log` accepts, e.g. `v1.0..HEAD`), newest first.
+/// Each version is searched once, at the commit that introduced it, and named `commit:path`.
+/// Only history touching `path` is searched, `path` being a directory or file in the repository.
+/// The walk through history stops early once `search` returns `false`.
+pub fn for_each_blob<F>(path: &Path, range: &str, mut search: F) -> AnyhowResult<()>
+    where F: FnMut(&str, &mut dyn BufRead) -> AnyhowResult<bool>
+{
+    let (dir, pathspec) = if path.is_dir() {
+        (path, Path::new("."))
+    } else {
+        let file_name = path.file_name().ok_or_else(|| anyhow!("Not a file: {:?}", path))?;
+        (path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new(".")),
+            Path::new(file_name))
+    };
+
+    // `--raw` lists, for every commit, the blob each changed file points to afterwards.
+    // Merges list nothing by default, their changes are already listed by their parents.
+    let mut log = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["log", "--raw", "-z", "--no-abbrev", "--no-renames", "--format=format:%H"])
+        .arg(range)
+        .arg("--")
+        .arg(pathspec)
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Could not run git, is it installed?")?;
+    let mut blobs = BlobReader::new(dir)?;
+
+    // The output is a sequence of NUL-terminated fields: a commit starts with its hash and a
+    // newline, followed by one `:modes ids status` field and one path field per changed file.
+    let mut commit = String::new();
+    // Id of the blob whose path comes next, `None` when the next path is to be skipped.
+    let mut pending: Option<String> = None;
+    let mut expecting_path = false;
+    let log_output = log.stdout.take().expect("stdout is piped");
+    for field in BufReader::new(log_output).split(0) {
+        let field = field.context("Could not read the output of git log")?;
+        let field = String::from_utf8_lossy(&field);
+        if expecting_path {
+            expecting_path = false;
+            if let Some(id) = pending.take() {
+                let name = format!("{}:{}", &commit[..commit.len().min(8)], field);
+                let content = blobs.read(&id)?;
+                if !search(&name, &mut &content[..])? {
+                    // Nobody reads the rest of the log, git would block on a full pipe.
+                    let _ = log.kill();
+                    let _ = log.wait();
+                    return Ok(());
+                }
+            }
+            continue;
+        }
+        let mut raw = field.as_ref();
+        if !raw.starts_with(':') {
+            if raw.is_empty() {
+                continue;
+            }
+            let (hash, rest) = raw.split_once('\n').unwrap_or((raw, ""));
+            commit = hash.to_string();
+            raw = rest;
+            if raw.is_empty() {
+                continue;
+            }
+        }
+        // `:old_mode new_mode old_id new_id status`
+        let fields: Vec<_> = raw.trim_start_matches(':').split(' ').collect();
+        if fields.len() != 5 {
+            return Err(anyhow!("Unexpected output from git log: {:?}", raw));
+        }
+        expecting_path = true;
+        // Deleted files have no new version, submodules are commits of another repository.
+        let skipped = fields[4].starts_with('D') || fields[1] == SUBMODULE_MODE;
+        pending = if skipped { None } else { Some(fields[3].to_string()) };
+    }
+
+    let status = log.wait().context("Could not run git log")?;
+    if !status.success() {
+        return Err(anyhow!("git log {} failed in {:?}", range, dir));
+    }
+    Ok(())
+}
+
+// Reads blobs through one long-running `git cat-file --batch` instead of one process each.
+struct BlobReader {
+    process: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+}
+
+impl BlobReader {
+    fn new(dir: &Path) -> AnyhowResult<BlobReader> {
+        let mut process = Command::new("git")
+            .arg("-C").arg(dir)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Could not run git, is it installed?")?;
+        let input = process.stdin.take().expect("stdin is piped");
+        let output = BufReader::new(process.stdout.take().expect("stdout is piped"));
+        Ok(BlobReader { process, input, output })
+    }
+
+    fn read(&mut self, id: &str) -> AnyhowResult<Vec<u8>> {
+        writeln!(self.input, "{}", id)?;
+        self.input.flush()?;
+        // The contents are preceded by `id type size` and followed by a newline.
+        let mut header = String::new();
+        self.output.read_line(&mut header).context("Could not read from git cat-file")?;
+        let size: usize = header.split_whitespace().nth(2)
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| anyhow!("Could not read blob {}: {}", id, header.trim()))?;
+        let mut content = vec![0; size + 1];
+        self.output.read_exact(&mut content).context("Could not read from git cat-file")?;
+        content.pop();
+        Ok(content)
+    }
+}
+
+impl Drop for BlobReader {
+    fn drop(&mut self) {
+        // Killing is fine: by now every requested blob has been read.
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
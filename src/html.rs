@@ -0,0 +1,103 @@
+//! Matches as a standalone HTML page, for `--format html`: a collapsible section for each
+//! input with its lines and the occurrences of the pattern highlighted, to share results
+//! with people who do not use a terminal.
+
+use std::io::{Result, Write};
+
+use crate::event;
+use crate::printer::format_size;
+use crate::RunStats;
+
+const HEADER: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Search results</title>
+<style>
+body { font-family: sans-serif; margin: 2em; color: #222; }
+details { margin-bottom: 1em; border: 1px solid #ddd; border-radius: 4px; }
+summary { padding: 0.5em; background: #f5f5f5; cursor: pointer; font-family: monospace; }
+pre { margin: 0; padding: 0.5em; overflow-x: auto; }
+p { margin: 0; padding: 0.5em; }
+.number { color: #2a7a2a; }
+.context { color: #888; }
+mark { background: #ffe066; font-weight: bold; }
+footer { color: #555; }
+</style>
+</head>
+<body>
+<h1>Search results</h1>
+"#;
+
+/// Start the page, up to the first section.
+pub fn write_header(writer: &mut impl Write) -> Result<()> {
+    writer.write_all(HEADER.as_bytes())
+}
+
+/// Start the section of the input at `path`.
+pub fn write_begin(writer: &mut impl Write, path: &str) -> Result<()> {
+    writeln!(writer, "<details open>")?;
+    writeln!(writer, "<summary>{}</summary>", escape(path))?;
+    write!(writer, "<pre>")
+}
+
+/// End the section started by `write_begin`.
+pub fn write_end(writer: &mut impl Write) -> Result<()> {
+    writeln!(writer, "</pre>")?;
+    writeln!(writer, "</details>")
+}
+
+/// Write a matching line with the occurrences of the pattern highlighted.
+pub fn write_match(writer: &mut impl Write, event: &event::Match) -> Result<()> {
+    write!(writer, "<span class=\"number\">{}</span>:", event.line_number)?;
+    let mut end = 0;
+    for submatch in &event.submatches {
+        write!(writer, "{}", escape(&event.text[end..submatch.start]))?;
+        write!(writer, "<mark>{}</mark>", escape(submatch.text))?;
+        end = submatch.end;
+    }
+    writeln!(writer, "{}", escape(&event.text[end..]))
+}
+
+pub fn write_context(writer: &mut impl Write, event: &event::Context) -> Result<()> {
+    writeln!(writer, "<span class=\"context\"><span class=\"number\">{}</span>-{}</span>",
+        event.line_number, escape(event.text))
+}
+
+/// Write a section for a binary input, whose lines are not shown.
+pub fn write_binary(writer: &mut impl Write, path: &str, lines: Option<usize>) -> Result<()> {
+    writeln!(writer, "<details open>")?;
+    writeln!(writer, "<summary>{}</summary>", escape(path))?;
+    match lines {
+        Some(lines) => writeln!(writer, "<p>Binary file matches ({} lines).</p>", lines)?,
+        None => writeln!(writer, "<p>Binary file matches.</p>")?,
+    }
+    writeln!(writer, "</details>")
+}
+
+/// End the page with the totals of the search.
+pub fn write_footer(writer: &mut impl Write, summary: &RunStats) -> Result<()> {
+    writeln!(writer, "<footer>")?;
+    writeln!(writer, "<p>{} matching lines in {} inputs searched, {} read in {:.3} seconds.</p>",
+        summary.matched_lines, summary.searched, format_size(summary.bytes_read),
+        summary.elapsed_seconds)?;
+    writeln!(writer, "</footer>")?;
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")
+}
+
+// `text` with the characters that have a meaning in HTML replaced by entities.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,59 @@
+//! Terminal hyperlinks (OSC 8) on file names, so that results open with a click.
+//!
+//! See https://gist.github.com/egmontkob/eb114294efbcd5adb1944c9f3cb5feda.
+
+use std::io::{Result, Write};
+use std::path::Path;
+
+/// Whether the terminal is known to show hyperlinks. Others may print the escape sequences
+/// as garbage, so they get none.
+pub fn supported() -> bool {
+    let var = |name| std::env::var(name).unwrap_or_default();
+    let term = var("TERM");
+    if term == "dumb" {
+        return false;
+    }
+    let program = matches!(var("TERM_PROGRAM").as_str(),
+        "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper");
+    // GNOME Terminal, Tilix and the other VTE based terminals since 0.50.
+    let vte = var("VTE_VERSION").parse::<u32>().is_ok_and(|version| version >= 5000);
+    let windows_terminal = std::env::var_os("WT_SESSION").is_some();
+    let term = ["xterm-kitty", "wezterm", "foot", "alacritty"]
+        .iter()
+        .any(|prefix| term.starts_with(prefix));
+    program || vte || windows_terminal || term
+}
+
+/// The `file://` URL of the file at `path`, or `None` if it is not a file on disk, like an
+/// archive entry or a URL.
+pub fn file_url(path: &str) -> Option<String> {
+    let absolute = Path::new(path).canonicalize().ok()?;
+    Some(format!("file://{}", encode_path(absolute.to_str()?)))
+}
+
+/// Start a link to `url`, at `line` if there is one, as a `#line` fragment.
+pub fn write_start(writer: &mut impl Write, url: &str, line: Option<u64>) -> Result<()> {
+    match line {
+        Some(line) => write!(writer, "\x1b]8;;{}#{}\x1b\\", url, line),
+        None => write!(writer, "\x1b]8;;{}\x1b\\", url),
+    }
+}
+
+/// End the link started by `write_start`.
+pub fn write_end(writer: &mut impl Write) -> Result<()> {
+    write!(writer, "\x1b]8;;\x1b\\")
+}
+
+/// `path`, with slashes, percent-encoded for use in a URL.
+pub fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char);
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,434 @@
+//! A trigram index of a directory, for `grrs index`, so that repeated searches of a large,
+//! mostly static tree only read the files that may contain the pattern.
+//!
+//! `index build DIR` records, for every three-byte sequence, the files it occurs in, and
+//! keeps the index up to date when run again by only reading the files that changed.
+//! `index search PATTERN -p DIR` looks up the trigrams of the literals the pattern requires
+//! and searches the files that contain all of them, as any other search would.
+//!
+//! The index is the file `.grrs-index` in the indexed directory:
+//!
+//! ```text
+//! "GRRSIDX1"
+//! file count: u32, then for each file:
+//!     path length: u32, path relative to the directory: bytes,
+//!     size: u64, modification time: u64 seconds and u32 nanoseconds, flags: u8
+//! trigram count: u32, then for each trigram, in ascending order:
+//!     trigram: u32, file count: u32, offset of its files in the postings: u64
+//! postings length: u64, then the postings: the ascending file numbers of each trigram,
+//!     each as the difference to the previous one, in LEB128
+//! ```
+//!
+//! Numbers are little-endian.
+
+use anyhow::{anyhow, Context, Result as AnyhowResult};
+use structopt::StructOpt;
+
+use std::collections::BTreeMap;
+use std::fs::Metadata;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::encoding::Encoding;
+use crate::matcher::Matcher;
+use crate::walk::{self, Walker};
+use crate::Cli;
+
+/// The name of the index file in the indexed directory.
+pub const FILE_NAME: &str = ".grrs-index";
+
+const MAGIC: &[u8] = b"GRRSIDX1";
+
+// Files that are searched whatever the pattern, as their bytes are not what is searched.
+const FLAG_UNINDEXED: u8 = 1;
+
+/// Maintain and search a trigram index of a directory.
+#[derive(StructOpt)]
+#[structopt(name = "index")]
+// Parsed once and taken apart right away, its size does not matter.
+#[allow(clippy::large_enum_variant)]
+pub enum Command {
+    /// Build the index of a directory, or bring it up to date, reading only the files that
+    /// changed since. Hidden files are left out, as a search leaves them out by default.
+    Build {
+        /// The directory to index.
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+    },
+    /// Search the indexed directory given as `--path`, only reading the files whose trigrams
+    /// contain those of the pattern, and those that changed since the index was built. Takes
+    /// the same arguments as a search. Files added since are not searched until the index is
+    /// built again.
+    Search(Cli),
+}
+
+/// What the files to search must contain, as worked out from the pattern.
+enum Query {
+    // Nothing narrows the search down.
+    All,
+    // Files with all the trigrams of one of the lists.
+    Any(Vec<Vec<u32>>),
+}
+
+struct IndexedFile {
+    path: PathBuf,
+    size: u64,
+    modified: (u64, u32),
+    flags: u8,
+}
+
+impl IndexedFile {
+    fn is_unchanged(&self, metadata: &Metadata) -> bool {
+        metadata.len() == self.size && modified(metadata) == self.modified
+    }
+}
+
+/// An index read back from its file.
+struct Index {
+    files: Vec<IndexedFile>,
+    // `(trigram, file count, offset)`, in ascending trigram order.
+    trigrams: Vec<(u32, u32, u64)>,
+    postings: Vec<u8>,
+}
+
+/// Build the index of `dir`, or bring it up to date, and report what was done to `writer`.
+pub fn build(dir: &Path, writer: &mut impl Write) -> AnyhowResult<()> {
+    let previous = match Index::read(dir) {
+        Ok(index) => Some(index),
+        Err(_) if !dir.join(FILE_NAME).exists() => None,
+        Err(err) => {
+            eprintln!("Warning: {:#}, building the index from scratch", err);
+            None
+        }
+    };
+    // The trigrams of the unchanged files are taken from the previous index.
+    let mut previous_trigrams = match &previous {
+        Some(index) => index.trigrams_by_file()?,
+        None => Vec::new(),
+    };
+    let previous_files: BTreeMap<_, _> = previous.iter()
+        .flat_map(|index| index.files.iter().enumerate())
+        .map(|(number, file)| (file.path.clone(), (number, file)))
+        .collect();
+
+    let mut files = Vec::new();
+    let mut postings: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    let mut trigrams = Trigrams::new();
+    let mut read = 0;
+    // Sorted, so that the files are numbered the same way from one build to the next.
+    let walker = Walker::new(vec![dir.to_path_buf()])
+        .sort_by_path(true)
+        .hidden(false);
+    for path in walker {
+        let path = path?;
+        let relative = path.strip_prefix(dir).unwrap_or(&path).to_path_buf();
+        let metadata = std::fs::metadata(&path)
+            .with_context(|| format!("Could not read metadata: {:?}", path))?;
+        let number = files.len() as u32;
+        let unchanged = previous_files.get(&relative)
+            .filter(|(_, file)| file.is_unchanged(&metadata));
+        let flags = match unchanged {
+            Some(&(previous_number, file)) => {
+                for &trigram in &previous_trigrams[previous_number] {
+                    postings.entry(trigram).or_default().push(number);
+                }
+                previous_trigrams[previous_number].clear();
+                file.flags
+            }
+            None => {
+                let content = std::fs::read(&path)
+                    .with_context(|| format!("Could not read file: {:?}", path))?;
+                read += 1;
+                if is_utf16(&content) {
+                    FLAG_UNINDEXED
+                } else {
+                    for trigram in trigrams.of(&content) {
+                        postings.entry(trigram).or_default().push(number);
+                    }
+                    0
+                }
+            }
+        };
+        files.push(IndexedFile {
+            path: relative,
+            size: metadata.len(),
+            modified: modified(&metadata),
+            flags,
+        });
+    }
+
+    write(dir, &files, &postings)?;
+    writeln!(writer, "Indexed {} files ({} read, {} unchanged), {} distinct trigrams.",
+        files.len(), read, files.len() - read, postings.len())?;
+    Ok(())
+}
+
+/// The files of the index of the directory `args.path` that may contain the pattern, along
+/// with those that changed since the index was built. Files gone since are left out.
+pub fn candidates(args: &Cli, matcher: &dyn Matcher) -> AnyhowResult<Vec<PathBuf>> {
+    let dir = args.path.as_deref().ok_or_else(|| anyhow!("`index search` needs --path"))?;
+    let index = Index::read(dir)?;
+    let query = plan(args, matcher)?;
+    let mut selected = vec![matches!(query, Query::All); index.files.len()];
+    if let Query::Any(alternatives) = &query {
+        for trigrams in alternatives {
+            for number in index.files_with_all(trigrams)? {
+                if let Some(selected) = selected.get_mut(number as usize) {
+                    *selected = true;
+                }
+            }
+        }
+    }
+    let mut paths = Vec::new();
+    for (file, selected) in index.files.iter().zip(selected) {
+        let path = dir.join(&file.path);
+        let metadata = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if selected || file.flags & FLAG_UNINDEXED != 0 || !file.is_unchanged(&metadata) {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+// Work out the trigrams the files to search must have. The index holds the raw bytes of the
+// files, so searches that decode them first cannot be narrowed down.
+fn plan(args: &Cli, matcher: &dyn Matcher) -> AnyhowResult<Query> {
+    if args.search_zip || args.archive || !matches!(args.encoding, Encoding::Auto) {
+        return Ok(Query::All);
+    }
+    let literals = if args.regex {
+        match matcher.literal() {
+            Some(literal) => vec![literal.needle().to_vec()],
+            None => return Ok(Query::All),
+        }
+    } else {
+        args.patterns()?.into_iter().map(String::into_bytes).collect()
+    };
+    let mut alternatives = Vec::new();
+    for literal in literals {
+        // Too short to have a trigram, any file may contain it.
+        if literal.len() < 3 {
+            return Ok(Query::All);
+        }
+        let mut trigrams = Trigrams::new().of(&literal);
+        trigrams.sort_unstable();
+        alternatives.push(trigrams);
+    }
+    Ok(Query::Any(alternatives))
+}
+
+// Collects the distinct trigrams of some bytes, in a set that is reused from one file to the
+// next to not allocate it again.
+struct Trigrams {
+    // One bit for each of the 2^24 trigrams.
+    seen: Vec<u64>,
+}
+
+impl Trigrams {
+    fn new() -> Trigrams {
+        Trigrams { seen: vec![0; 1 << 18] }
+    }
+
+    fn of(&mut self, bytes: &[u8]) -> Vec<u32> {
+        let mut found = Vec::new();
+        for window in bytes.windows(3) {
+            let trigram = trigram(window);
+            let (word, bit) = ((trigram >> 6) as usize, 1 << (trigram & 63));
+            if self.seen[word] & bit == 0 {
+                self.seen[word] |= bit;
+                found.push(trigram);
+            }
+        }
+        for &trigram in &found {
+            self.seen[(trigram >> 6) as usize] = 0;
+        }
+        found
+    }
+}
+
+fn trigram(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2])
+}
+
+// Whether `content` starts with a UTF-16 byte-order mark, in which case it is decoded before
+// being searched and its raw bytes say nothing about what it contains.
+fn is_utf16(content: &[u8]) -> bool {
+    content.starts_with(&[0xff, 0xfe]) || content.starts_with(&[0xfe, 0xff])
+}
+
+fn modified(metadata: &Metadata) -> (u64, u32) {
+    metadata.modified().ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or((0, 0), |since| (since.as_secs(), since.subsec_nanos()))
+}
+
+fn write(dir: &Path, files: &[IndexedFile], postings: &BTreeMap<u32, Vec<u32>>)
+    -> AnyhowResult<()>
+{
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&(files.len() as u32).to_le_bytes());
+    for file in files {
+        let path = walk::path_to_bytes(&file.path);
+        bytes.extend_from_slice(&(path.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&path);
+        bytes.extend_from_slice(&file.size.to_le_bytes());
+        bytes.extend_from_slice(&file.modified.0.to_le_bytes());
+        bytes.extend_from_slice(&file.modified.1.to_le_bytes());
+        bytes.push(file.flags);
+    }
+    let mut encoded = Vec::new();
+    bytes.extend_from_slice(&(postings.len() as u32).to_le_bytes());
+    for (trigram, numbers) in postings {
+        bytes.extend_from_slice(&trigram.to_le_bytes());
+        bytes.extend_from_slice(&(numbers.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(encoded.len() as u64).to_le_bytes());
+        let mut previous = 0;
+        for &number in numbers {
+            write_varint(&mut encoded, number - previous);
+            previous = number;
+        }
+    }
+    bytes.extend_from_slice(&(encoded.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&encoded);
+
+    // Written next to the index first, so that a search never reads half an index.
+    let path = dir.join(FILE_NAME);
+    let temporary = dir.join(format!("{}.tmp", FILE_NAME));
+    std::fs::write(&temporary, &bytes)
+        .with_context(|| format!("Could not write the index: {:?}", temporary))?;
+    std::fs::rename(&temporary, &path)
+        .with_context(|| format!("Could not write the index: {:?}", path))
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+impl Index {
+    fn read(dir: &Path) -> AnyhowResult<Index> {
+        let path = dir.join(FILE_NAME);
+        let bytes = std::fs::read(&path).with_context(|| {
+            format!("Could not read the index {:?}, build it with `grrs index build`", path)
+        })?;
+        Index::parse(bytes).with_context(|| format!("Invalid index: {:?}", path))
+    }
+
+    fn parse(bytes: Vec<u8>) -> AnyhowResult<Index> {
+        let mut reader = Reader { bytes: &bytes, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(anyhow!("not an index of this version"));
+        }
+        let file_count = reader.u32()?;
+        let mut files = Vec::new();
+        for _ in 0..file_count {
+            let length = reader.u32()? as usize;
+            let path = walk::path_from_bytes(reader.take(length)?);
+            files.push(IndexedFile {
+                path,
+                size: reader.u64()?,
+                modified: (reader.u64()?, reader.u32()?),
+                flags: reader.take(1)?[0],
+            });
+        }
+        let trigram_count = reader.u32()?;
+        let mut trigrams = Vec::new();
+        for _ in 0..trigram_count {
+            trigrams.push((reader.u32()?, reader.u32()?, reader.u64()?));
+        }
+        let length = reader.u64()? as usize;
+        let postings = reader.take(length)?.to_vec();
+        Ok(Index { files, trigrams, postings })
+    }
+
+    // The numbers of the files containing `trigram`, in ascending order.
+    fn files_with(&self, trigram: u32) -> AnyhowResult<Vec<u32>> {
+        let (count, offset) = match self.trigrams.binary_search_by_key(&trigram, |entry| entry.0) {
+            Ok(position) => (self.trigrams[position].1, self.trigrams[position].2),
+            Err(_) => return Ok(Vec::new()),
+        };
+        let mut reader = Reader { bytes: &self.postings, position: offset as usize };
+        let mut numbers = Vec::with_capacity(count as usize);
+        let mut number = 0;
+        for _ in 0..count {
+            number += reader.varint()?;
+            numbers.push(number);
+        }
+        Ok(numbers)
+    }
+
+    // The numbers of the files containing every one of `trigrams`, in ascending order.
+    fn files_with_all(&self, trigrams: &[u32]) -> AnyhowResult<Vec<u32>> {
+        let mut lists = trigrams.iter()
+            .map(|&trigram| self.files_with(trigram))
+            .collect::<AnyhowResult<Vec<_>>>()?;
+        // Starting from the rarest trigram keeps the intersections small.
+        lists.sort_by_key(Vec::len);
+        let mut lists = lists.into_iter();
+        let mut numbers = lists.next().unwrap_or_default();
+        for list in lists {
+            numbers.retain(|number| list.binary_search(number).is_ok());
+        }
+        Ok(numbers)
+    }
+
+    // The trigrams of each file, by file number.
+    fn trigrams_by_file(&self) -> AnyhowResult<Vec<Vec<u32>>> {
+        let mut by_file = vec![Vec::new(); self.files.len()];
+        for &(trigram, _, _) in &self.trigrams {
+            for number in self.files_with(trigram)? {
+                by_file.get_mut(number as usize)
+                    .ok_or_else(|| anyhow!("file number out of range"))?
+                    .push(trigram);
+            }
+        }
+        Ok(by_file)
+    }
+}
+
+// Reads the numbers of an index, failing rather than panicking on truncated ones.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> AnyhowResult<&'a [u8]> {
+        let end = self.position.checked_add(length).filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| anyhow!("truncated"))?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> AnyhowResult<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> AnyhowResult<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn varint(&mut self) -> AnyhowResult<u32> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u32::from(byte & 0x7f).checked_shl(shift).unwrap_or(0);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(anyhow!("invalid number"))
+    }
+}
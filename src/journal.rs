@@ -0,0 +1,204 @@
+//! `--journal` and `grrs report`: a run is recorded in a directory, with the arguments it was
+//! started with and its results as the events of `--json`, so that `grrs report` can print
+//! them again, in another format if need be, without searching again.
+//!
+//! Each run is a file of JSON lines, named after the time it started: a `run` event with its
+//! arguments, then the events `--json` would have written.
+
+use anyhow::{anyhow, Context, Result as AnyhowResult};
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::printer::{self, Printer};
+use crate::sink::MatchedLine;
+use crate::{config, event, Cli, OutputError};
+
+// How many runs a journal keeps. Recording another one removes the oldest.
+const KEPT_RUNS: usize = 100;
+
+/// Print the results of a run recorded with `--journal` again, without searching again.
+#[derive(StructOpt)]
+#[structopt(name = "grrs report")]
+pub struct Command {
+    /// The run: `LAST`, the latest one, `LAST~N`, the one N runs before it, or the path of
+    /// its record.
+    #[structopt(default_value = "LAST")]
+    run: String,
+    /// The journal the run was recorded in. By default, the one `--journal` names in the
+    /// config file.
+    #[structopt(long = "journal", parse(from_os_str))]
+    journal: Option<PathBuf>,
+    /// The format to print the results in, as `--format` takes it, e.g. `csv` or `html`.
+    /// By default, that of the run.
+    #[structopt(long = "format")]
+    format: Option<String>,
+}
+
+/// Start recording a run with `args`, looking for `patterns`, in the journal at `dir`.
+/// Returns where the events of its results go.
+pub fn record(dir: &Path, args: &Cli, patterns: &[String]) -> AnyhowResult<Box<dyn Write>> {
+    // Tells apart the runs a process starts within the same millisecond.
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    fs::create_dir_all(dir).with_context(|| format!("Could not create the journal {:?}", dir))?;
+    let runs = runs(dir)?;
+    for oldest in &runs[..runs.len().saturating_sub(KEPT_RUNS - 1)] {
+        // Another run may have removed it first.
+        let _ = fs::remove_file(oldest);
+    }
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let path = dir.join(format!("{:016}-{}-{}.jsonl", started.as_millis(), std::process::id(),
+        RUNS.fetch_add(1, Ordering::Relaxed)));
+    let file = File::create(&path)
+        .with_context(|| format!("Could not record the run in {:?}", path))?;
+    let mut file = BufWriter::new(file);
+    let run = json!({
+        "version": event::VERSION,
+        "type": "run",
+        "data": {
+            "args": args.argv,
+            "patterns": patterns,
+            "with_filename": crate::with_filename(args),
+            "started": started.as_secs(),
+        },
+    });
+    writeln!(file, "{}", run)?;
+    Ok(Box::new(file))
+}
+
+// The records of the runs in the journal at `dir`, oldest first.
+fn runs(dir: &Path) -> AnyhowResult<Vec<PathBuf>> {
+    let mut runs = Vec::new();
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("Could not read the journal {:?}", dir))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "jsonl") {
+            runs.push(path);
+        }
+    }
+    // The names start with the time, zero-padded.
+    runs.sort();
+    Ok(runs)
+}
+
+/// Print the results of the run `command` picks to `writer`.
+pub fn report(command: Command, writer: &mut impl Write) -> AnyhowResult<()> {
+    let path = find(&command)?;
+    let file = File::open(&path).with_context(|| format!("Could not open the run {:?}", path))?;
+    let mut lines = BufReader::new(file).lines();
+    let run: Value = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => Value::Null,
+    };
+    if run["type"] != "run" || run["version"] != event::VERSION {
+        return Err(anyhow!("{:?} is not the record of a run of this version of grrs", path));
+    }
+    let data = &run["data"];
+    let strings = |value: &Value| -> Option<Vec<String>> {
+        value.as_array()?.iter().map(|value| value.as_str().map(String::from)).collect()
+    };
+    let (argv, patterns) = strings(&data["args"]).zip(strings(&data["patterns"]))
+        .ok_or_else(|| anyhow!("The record of {:?} has no arguments", path))?;
+    let mut args = Cli::from_iter_safe(std::iter::once("grrs".to_string()).chain(argv))
+        .map_err(|err| anyhow!(err.message))?;
+    // Whatever the paths hold now, the results are those of the run.
+    args.with_filename = data["with_filename"] == true;
+    args.no_filename = !args.with_filename;
+    if let Some(format) = &command.format {
+        args.format = Some(printer::parse_format(format)?);
+        args.json = false;
+    }
+    // The patterns are those of the run too: a `-f` file may have changed since.
+    let matcher = crate::pattern_matcher(&args, &patterns)?;
+    let mut printer = crate::printer(&args, &*matcher, &patterns, writer)?;
+    for line in lines {
+        let event = serde_json::from_str(&line?)
+            .with_context(|| format!("Could not read the run {:?}", path))?;
+        replay(&mut printer, &event)?;
+    }
+    printer.finish().map_err(OutputError)?;
+    printer.get_mut().flush().map_err(OutputError)?;
+    Ok(())
+}
+
+// The record of the run `command` picks.
+fn find(command: &Command) -> AnyhowResult<PathBuf> {
+    let back = match command.run.strip_prefix("LAST") {
+        Some("") => 0,
+        Some(back) => back.strip_prefix('~').and_then(|back| back.parse().ok())
+            .ok_or_else(|| anyhow!("Invalid run {:?}, expected LAST or LAST~N", command.run))?,
+        None => return Ok(PathBuf::from(&command.run)),
+    };
+    let journal = match &command.journal {
+        Some(journal) => journal.clone(),
+        None => configured_journal()?,
+    };
+    let runs = runs(&journal)?;
+    runs.len().checked_sub(back + 1).map(|index| runs[index].clone())
+        .ok_or_else(|| anyhow!("The journal {:?} holds {} run(s)", journal, runs.len()))
+}
+
+// The journal `--journal` names in the config file, where it is put to record every run.
+fn configured_journal() -> AnyhowResult<PathBuf> {
+    let args = config::args()?;
+    args.iter().position(|arg| arg == "--journal")
+        .and_then(|index| args.get(index + 1))
+        .map(PathBuf::from)
+        .or_else(|| {
+            args.iter().find_map(|arg| arg.to_str()?.strip_prefix("--journal=").map(PathBuf::from))
+        })
+        .ok_or_else(|| anyhow!("No --journal given, and none in the config file"))
+}
+
+// Hand `event`, of a recorded run, to `printer`.
+fn replay<W: Write>(printer: &mut Printer<W>, event: &Value) -> AnyhowResult<()> {
+    let data = &event["data"];
+    let invalid = || anyhow!("Invalid event in the run: {}", event);
+    let text = |field| data[field].as_str().ok_or_else(invalid);
+    let number = |field| data[field].as_u64().ok_or_else(invalid);
+    match event["type"].as_str() {
+        Some("begin") => printer.begin_file(text("path")?),
+        Some("match") => {
+            let spans = data["submatches"].as_array().ok_or_else(invalid)?.iter()
+                .map(|submatch| {
+                    let start = submatch["start"].as_u64().ok_or_else(invalid)? as usize;
+                    let end = submatch["end"].as_u64().ok_or_else(invalid)? as usize;
+                    Ok(start..end)
+                })
+                .collect::<AnyhowResult<Vec<Range<usize>>>>()?;
+            printer.matched_line(&MatchedLine {
+                number: number("line_number")?,
+                offset: number("absolute_offset")?,
+                text: text("text")?,
+                spans: &spans,
+                groups: &[],
+            }).map_err(OutputError)?;
+        }
+        Some("context") => {
+            printer.context_line(number("line_number")?, number("absolute_offset")?,
+                text("text")?).map_err(OutputError)?;
+        }
+        Some("end") => {
+            if data["binary"] == true {
+                // Lines are only counted in binary files for `-c`.
+                let lines = number("matched_lines")? as usize;
+                printer.binary_match(Some(lines).filter(|lines| *lines > 1))
+                    .map_err(OutputError)?;
+            }
+            printer.end_file().map_err(OutputError)?;
+        }
+        Some("summary") => {
+            printer.summary(serde_json::from_value(data.clone())?).map_err(OutputError)?;
+        }
+        _ => return Err(invalid()),
+    }
+    Ok(())
+}
@@ -0,0 +1,1819 @@
+//! `grrs`, a small grep: the search behind the command line, and `Searcher`, to search from
+//! other programs without running it.
+
+// A wasm32 build has no file system, and so no command line: only the search of buffers is
+// used there, the printing of results is built but left unused.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use structopt::StructOpt;
+
+use anyhow::{Context, Result as AnyhowResult};
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Result};
+// @todo Why is `Write` needed for accessing `BufWriter`'s implementation of the trait's methods?
+// @todo Why is importing `BufWriter` not enough to call its implementation of the `Writer` trait?
+// See: https://github.com/flowreenLZR/rust-cli-book/issues/1
+use std::io::{BufRead, Read};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{IsTerminal, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{mpsc, Arc, Mutex};
+
+#[cfg(not(target_arch = "wasm32"))]
+use printer::Printer;
+pub use cancel::CancellationToken;
+pub use decode::{Decoder, DecoderRegistry, Identity, Transcoder};
+pub use error::SearchError;
+pub use searcher::{Case, FileSummary, Match, RunStats, SearchConfig, SearchConfigBuilder, Searcher};
+pub use sink::{Counter, MatchedLine, Sink, Summaries};
+pub use source::{Buffer, Source};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod archive;
+mod batch;
+mod bench;
+mod cancel;
+#[cfg(not(target_arch = "wasm32"))]
+mod chunk;
+mod collect;
+mod color;
+#[cfg(not(target_arch = "wasm32"))]
+mod config;
+mod decode;
+mod encoding;
+mod error;
+#[cfg(not(target_arch = "wasm32"))]
+mod estimate;
+mod event;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(not(target_arch = "wasm32"))]
+mod git;
+#[cfg(not(target_arch = "wasm32"))]
+mod globs;
+#[cfg(not(target_arch = "wasm32"))]
+mod history;
+mod html;
+mod hyperlink;
+#[cfg(not(target_arch = "wasm32"))]
+mod index;
+#[cfg(all(feature = "json", not(target_arch = "wasm32")))]
+mod journal;
+mod lines;
+mod markdown;
+mod matcher;
+#[cfg(not(target_arch = "wasm32"))]
+mod mmap;
+mod net;
+mod options;
+#[cfg(not(target_arch = "wasm32"))]
+mod pager;
+mod printer;
+#[cfg(feature = "python")]
+pub mod python;
+mod record;
+#[cfg(feature = "json")]
+mod sarif;
+mod searcher;
+#[cfg(all(feature = "json", not(target_arch = "wasm32")))]
+mod server;
+mod sink;
+mod source;
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(not(target_arch = "wasm32"))]
+mod stdout;
+mod table;
+mod template;
+#[cfg(not(target_arch = "wasm32"))]
+mod types;
+#[cfg(not(target_arch = "wasm32"))]
+mod uring;
+#[cfg(not(target_arch = "wasm32"))]
+mod walk;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "async")]
+mod webhook;
+
+/// Search for a pattern in a file and display the lines that contain it.
+///
+/// Default arguments can be stored in the file named by the GRRS_CONFIG_PATH environment
+/// variable, one per line.
+///
+/// `grrs index build DIR` indexes a directory, which `grrs index search PATTERN -p DIR` then
+/// searches faster. See `grrs index --help`.
+///
+/// `grrs report` prints the results of a run recorded with `--journal` again, e.g. as HTML
+/// with `grrs report LAST --format html`. See `grrs report --help`.
+#[derive(StructOpt)]
+#[derive(Debug, Clone)]
+// Lets the command line override the config file.
+#[structopt(global_settings = &[structopt::clap::AppSettings::AllArgsOverrideSelf])]
+struct Cli {
+    /// The pattern to look for.
+    #[structopt(
+        required_unless_one = &["stdio-server", "json-schema", "regexp", "file", "files"])]
+    pattern: Option<String>,
+    /// Another pattern to look for. Lines containing any of the patterns match. Repeat it to
+    /// give several, e.g. patterns starting with `-`.
+    #[structopt(short = "e", long = "regexp", number_of_values = 1)]
+    regexp: Vec<String>,
+    /// Look for each line of this file as a pattern too.
+    #[structopt(short = "f", long = "file", parse(from_os_str))]
+    file: Option<std::path::PathBuf>,
+    /// Treat the pattern as a regular expression, in the syntax of the `regex` crate, rather
+    /// than as literal text.
+    #[structopt(long = "regex")]
+    regex: bool,
+    /// Look for literal patterns with a single Aho-Corasick automaton, even when there are
+    /// few of them. By default, it is built from 8 patterns on.
+    #[structopt(long = "aho-corasick", overrides_with = "no-aho-corasick")]
+    aho_corasick: bool,
+    /// Look for each literal pattern in turn, even when there are many of them.
+    #[structopt(long = "no-aho-corasick", overrides_with = "aho-corasick")]
+    no_aho_corasick: bool,
+    /// The path to the file to read. Directories are searched recursively.
+    /// With the `net` feature, an `http://` or `https://` URL is fetched and searched.
+    #[structopt(parse(from_os_str))]
+    #[structopt(short = "p", long = "path")]
+    #[structopt(
+        required_unless_one = &["files-from", "files-from0", "stdio-server", "json-schema"])]
+    path: Option<std::path::PathBuf>,
+    /// Search the newline-separated list of files in this file instead of `--path`.
+    /// Use `-` to read the list from stdin.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "files-from", conflicts_with_all = &["path", "files-from0"])]
+    files_from: Option<std::path::PathBuf>,
+    /// Like `--files-from`, but the list is NUL-separated, as printed by `find -print0`.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "files-from0", conflicts_with = "path")]
+    files_from0: Option<std::path::PathBuf>,
+    /// Search hidden files and directories when recursing.
+    #[structopt(long = "hidden")]
+    hidden: bool,
+    /// Follow symbolic links found when recursing. Links given as the path are always followed.
+    #[structopt(long = "follow")]
+    follow: bool,
+    /// Also search FIFOs, sockets and device files found when recursing. They are skipped by
+    /// default since reading them can block forever. Paths given explicitly are always read.
+    #[structopt(long = "include-special")]
+    include_special: bool,
+    /// The order to search and report files in: `path`, `mtime` (oldest first), `size`
+    /// (smallest first) or `none`, which is fastest. `mtime` and `size` walk every
+    /// directory before the first file is searched.
+    #[structopt(long = "sort", default_value = "none", parse(try_from_str = parse_sort))]
+    sort: Sort,
+    /// Descend at most this many directories below the given path.
+    #[structopt(long = "max-depth")]
+    max_depth: Option<usize>,
+    /// Only search files of this type (e.g. `rust`, `md`). Can be repeated.
+    #[structopt(short = "t", long = "type", number_of_values = 1)]
+    types: Vec<String>,
+    /// Do not search files of this type. Can be repeated.
+    #[structopt(short = "T", long = "type-not", number_of_values = 1)]
+    types_not: Vec<String>,
+    /// Define a file type as `name:glob` (e.g. `web:*.vue`). Can be repeated.
+    #[structopt(long = "type-add", number_of_values = 1)]
+    type_add: Vec<String>,
+    /// Only search files matching this glob. Globs containing `/` match the path,
+    /// the others the file name. Can be repeated.
+    #[structopt(long = "include", number_of_values = 1)]
+    include: Vec<String>,
+    /// Skip files and directories matching this glob. Can be repeated.
+    #[structopt(long = "exclude", number_of_values = 1)]
+    exclude: Vec<String>,
+    /// Skip files larger than this size when recursing. Accepts K, M and G suffixes (e.g. `10M`).
+    #[structopt(long = "max-filesize", parse(try_from_str = parse_size))]
+    max_filesize: Option<u64>,
+    /// Only search files tracked by the enclosing git repository, like `git ls-files`.
+    #[structopt(long = "git-tracked")]
+    git_tracked: bool,
+    /// Search every version of the files below the path committed in this git revision range
+    /// (e.g. `v1.0..HEAD`) instead of the working tree. Matches are named `commit:path`.
+    #[structopt(long = "git-log", value_name = "REV_RANGE")]
+    git_log: Option<String>,
+    /// Search inside compressed files (gzip, plus bzip2, xz, zstd and lz4 when enabled).
+    #[structopt(short = "z", long = "search-zip")]
+    search_zip: bool,
+    /// Do not search: report how much data would be searched and estimate how long it takes.
+    #[structopt(long = "estimate")]
+    estimate: bool,
+    /// Do not search: print the path of every file that would be searched, one per line.
+    /// Takes no pattern.
+    #[structopt(long = "files")]
+    files: bool,
+    /// Run the search this many times, or for this long (e.g. `30s` or `500ms`), throwing the
+    /// results away, and report how fast each run and the fastest and median ones went.
+    #[structopt(long = "bench", value_name = "RUNS|DURATION")]
+    #[structopt(parse(try_from_str = bench::parse))]
+    bench: Option<bench::Bench>,
+    /// Search the files inside tar (`.tar`, `.tar.gz`, ...) and zip (`.zip`, `.jar`, ...)
+    /// archives instead of their raw bytes.
+    #[structopt(long = "archive")]
+    archive: bool,
+    /// Only search the members of archives matching this glob, e.g. `*.log`, without
+    /// decompressing the others. Globs containing `/` match the path inside the archive, the
+    /// others the file name. Can be repeated.
+    #[structopt(long = "archive-glob", number_of_values = 1, parse(try_from_str = parse_glob))]
+    archive_glob: Vec<String>,
+    /// Also search the archives found inside archives, down to this many levels.
+    #[structopt(long = "archive-depth", default_value = "0")]
+    archive_depth: usize,
+    /// Give up an archive once more than this much was unpacked from it, nested archives
+    /// included, e.g. `500M`. Guards against zip bombs.
+    #[structopt(long = "archive-max-size", default_value = "1G")]
+    #[structopt(parse(try_from_str = parse_size))]
+    archive_max_size: u64,
+    /// Give up an archive once more than this many times its own size was unpacked from it,
+    /// nested archives included.
+    #[structopt(long = "archive-max-ratio", default_value = "1000")]
+    archive_max_ratio: u64,
+    /// Search binary files as if they were text and print their matching lines.
+    #[structopt(short = "a", long = "text")]
+    text: bool,
+    /// Search binary files to the end and report how many lines matched, without printing them.
+    #[structopt(long = "binary", conflicts_with = "text")]
+    binary: bool,
+    /// The text encoding of the files, e.g. `utf-16le` or `latin1`. With `auto` it is picked
+    /// from the byte-order mark, if any, and files without one are read as UTF-8.
+    #[structopt(short = "E", long = "encoding", default_value = "auto")]
+    #[structopt(parse(try_from_str = encoding::parse))]
+    encoding: encoding::Encoding,
+    /// Read at most this many bytes of each line, so that huge single-line files such as
+    /// minified JavaScript never have to fit in memory. Accepts K, M and G suffixes.
+    #[structopt(long = "max-line-length", parse(try_from_str = parse_size))]
+    max_line_length: Option<u64>,
+    /// Skip the lines longer than `--max-line-length` instead of searching their beginning.
+    #[structopt(long = "skip-long-lines", requires = "max-line-length")]
+    skip_long_lines: bool,
+    /// Prefix each printed line with the name of its file. The default when searching a
+    /// directory, a list of files, an archive or the git history.
+    #[structopt(short = "H", long = "with-filename", conflicts_with = "no-filename")]
+    with_filename: bool,
+    /// Never prefix printed lines with the name of their file.
+    #[structopt(short = "h", long = "no-filename")]
+    no_filename: bool,
+    /// Print the name of each file once, above its lines, rather than on every line.
+    /// The default on terminals.
+    #[structopt(long = "heading", conflicts_with = "no-heading")]
+    heading: bool,
+    /// Print the name of the file on every line, even on terminals.
+    #[structopt(long = "no-heading")]
+    no_heading: bool,
+    /// Prefix each printed line with its 1-based line number.
+    #[structopt(short = "n", long = "line-number")]
+    line_number: bool,
+    /// Prefix each printed line with the 1-based column, in bytes, of its first match.
+    #[structopt(long = "column")]
+    column: bool,
+    /// Prefix each printed line with the offset of its first byte. Offsets count the bytes
+    /// after decompression and transcoding, which is the file offset for plain UTF-8 files.
+    #[structopt(short = "b", long = "byte-offset")]
+    byte_offset: bool,
+    /// Shorten printed lines longer than this many characters, around their first match.
+    /// With `auto`, the width of the terminal, if the results go to one.
+    #[structopt(long = "max-columns", value_name = "NUM", parse(try_from_str = parse_max_columns))]
+    max_columns: Option<MaxColumns>,
+    /// Print only the matched parts of each line, every occurrence on its own line.
+    #[structopt(short = "o", long = "only-matching")]
+    only_matching: bool,
+    /// Print the matching lines once the search is over, sorted by `path` and line number,
+    /// by `line` number, or by `frequency`: the lines with the most matches first. Unlike
+    /// `--sort`, this orders lines across files, whatever order the files were searched in.
+    #[structopt(long = "sort-results", parse(try_from_str = printer::parse_sort_results))]
+    sort_results: Option<printer::SortResults>,
+    /// Print each distinct occurrence of the pattern once, when the search is over, instead
+    /// of every line. Implies `-o`.
+    #[structopt(long = "unique")]
+    unique: bool,
+    /// Like `--unique`, with the number of occurrences of each, most frequent first.
+    #[structopt(long = "unique-counts", conflicts_with = "unique")]
+    unique_counts: bool,
+    /// Print `file:line:column:text` once for each occurrence of the pattern, for Vim's
+    /// `grepprg`. Implies `-H`, `-n` and `--column`.
+    #[structopt(long = "vimgrep", conflicts_with_all = &["format", "json"])]
+    vimgrep: bool,
+    /// Print the number of matching lines of each file instead of the lines. Files without
+    /// any are left out.
+    #[structopt(short = "c", long = "count")]
+    count: bool,
+    /// Like `--count`, but count every occurrence of the pattern rather than lines.
+    #[structopt(long = "count-matches", conflicts_with = "count")]
+    count_matches: bool,
+    /// Print this many lines after each match.
+    #[structopt(short = "A", long = "after-context", value_name = "NUM")]
+    after_context: Option<usize>,
+    /// Print this many lines before each match.
+    #[structopt(short = "B", long = "before-context", value_name = "NUM")]
+    before_context: Option<usize>,
+    /// Print this many lines before and after each match, unless set apart by -A or -B.
+    /// Groups of lines that are not adjacent are separated by `--`.
+    #[structopt(short = "C", long = "context", value_name = "NUM")]
+    context: Option<usize>,
+    /// Stop reading a file after this many matching lines. The context after the last one
+    /// is still printed.
+    #[structopt(short = "m", long = "max-count", value_name = "NUM")]
+    max_count: Option<usize>,
+    /// Print nothing and exit with status 0 at the first match, or 1 if there is none.
+    #[structopt(short = "q", long = "quiet")]
+    quiet: bool,
+    /// Print only the names of the files containing a match.
+    #[structopt(short = "l", long = "files-with-matches")]
+    files_with_matches: bool,
+    /// Print only the names of the files without any match.
+    #[structopt(short = "L", long = "files-without-match", conflicts_with = "files-with-matches")]
+    files_without_match: bool,
+    /// Replace invalid UTF-8 with U+FFFD instead of giving up on the file at the first
+    /// invalid line to print.
+    #[structopt(long = "lossy")]
+    lossy: bool,
+    /// Split the input into records ending with a NUL byte instead of lines, e.g. to search
+    /// the output of `find -print0`. NUL bytes then no longer mark the input as binary, and
+    /// the records printed end with one too, as with `grep -z`.
+    #[structopt(long = "null-data")]
+    null_data: bool,
+    /// Search every file through a memory map instead of reading it. By default, only files
+    /// of 4M or more on local filesystems are mapped, where it is faster. Inputs that cannot
+    /// be mapped are read as usual.
+    #[structopt(long = "mmap", overrides_with = "no-mmap")]
+    mmap: bool,
+    /// Read every file through a buffer, never through a memory map.
+    #[structopt(long = "no-mmap", overrides_with = "mmap")]
+    no_mmap: bool,
+    /// Size of the buffer files are read through. Larger buffers mean fewer reads, which
+    /// pays off for large files on spinning disks and network mounts. Accepts K, M and G
+    /// suffixes.
+    #[structopt(long = "read-buffer", default_value = "64K")]
+    #[structopt(parse(try_from_str = parse_buffer_size))]
+    read_buffer: usize,
+    /// Size of the buffer the results are written through. Accepts K, M and G suffixes.
+    #[structopt(long = "write-buffer", default_value = "64K")]
+    #[structopt(parse(try_from_str = parse_buffer_size))]
+    write_buffer: usize,
+    /// Number of files searched at the same time. 0, the default, picks a number from the CPUs
+    /// and what is searched: one thread for a single file of a usual size, as many as there
+    /// are CPUs for a directory, and twice that on network filesystems, whose reads mostly
+    /// wait. `--stats` shows the number picked. Files found while walking directories are
+    /// searched in parallel, other inputs one after the other. Directories are read on as
+    /// many threads, unless `--sort` is used.
+    #[structopt(short = "j", long = "threads", default_value = "0")]
+    threads: usize,
+    /// Print the results of each file as soon as it has been searched, rather than in the
+    /// order the files were found, which may hold results back behind a large file.
+    #[structopt(long = "no-order")]
+    no_order: bool,
+    /// Read every input at the same time on an async runtime and print each matching line as
+    /// soon as it is read, so that a URL, a FIFO or a file followed with `--tail` that has
+    /// nothing to give holds up none of the others. Inputs are searched as plain text, and
+    /// the lines of different inputs are printed as they come. Needs the `async` feature.
+    #[structopt(long = "async")]
+    async_mode: bool,
+    /// Keep reading files once their end is reached, like `tail -f`, and search the lines
+    /// added to them until Ctrl-C. On Linux, FIFOs are read on after their writers close them.
+    #[structopt(long = "tail", requires = "async-mode")]
+    // Only read by the search of `--async`, which is rejected without its feature.
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    tail: bool,
+    /// POST the matches `--tail` finds to this URL as JSON, those found within a second in
+    /// one request, and try again if the server fails. Needs the `net` and `json` features.
+    #[structopt(long = "webhook", value_name = "URL", requires = "tail")]
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    webhook: Option<String>,
+    /// When to color matches, file names, line numbers and errors: `auto` on terminals,
+    /// unless the `NO_COLOR` environment variable is set, `always` or `never`.
+    #[structopt(long = "color", default_value = "auto", parse(try_from_str = color::parse))]
+    color: color::ColorChoice,
+    /// Change the colors, e.g. `match:fg:yellow`, `path:style:bold`, `line:bg:0,0,128` or
+    /// `column:none`. The parts are `path`, `line`, `column` and `match`. Can be repeated.
+    #[structopt(long = "colors", number_of_values = 1, parse(try_from_str = color::parse_spec))]
+    colors: Vec<color::ColorSpec>,
+    /// Do not make file names links to the file and line, which they are on terminals known
+    /// to support them, e.g. iTerm2, WezTerm, kitty and the VS Code terminal.
+    #[structopt(long = "no-hyperlinks")]
+    no_hyperlinks: bool,
+    /// Write the results to this file instead of stdout. It is left out of the search.
+    #[structopt(short = "O", long = "output", parse(from_os_str))]
+    output: Option<std::path::PathBuf>,
+    /// Show the results in `$PAGER`, or `less`, when writing to a terminal.
+    #[structopt(long = "pager", overrides_with = "no-pager")]
+    pager: bool,
+    /// Write the results to the terminal directly, e.g. to turn off a `--pager` set in the
+    /// config file.
+    #[structopt(long = "no-pager", overrides_with = "pager")]
+    no_pager: bool,
+    /// How to print the results: `text`, grep style; `json`, JSON Lines with an object for
+    /// each match and context line, `begin` and `end` objects around the results of each file
+    /// and a final `summary`; `csv` or `tsv`, a `path,line,column,matched_text,line_text` row
+    /// for each occurrence of the pattern, after a header row; `markdown`, a section for each
+    /// file with its lines in a code block, to paste into an issue; `html`, a standalone page
+    /// with a collapsible section for each file, best written to `--output`; `sarif`, a SARIF
+    /// log for code scanning with the pattern as its rule and a result for each occurrence.
+    /// Anything else is a template such as `{path}:{line_no}\t{text}`, written for each
+    /// matching line. Its placeholders are `path`, `line_no`, `offset`, `text` and, to write
+    /// it for each occurrence of the pattern instead, `column`, `match` and capture groups by
+    /// number or name, as in `{1}`. `{{`, `}}`, `\t`, `\n` and `\\` are escapes.
+    #[structopt(long = "format", parse(try_from_str = printer::parse_format))]
+    format: Option<printer::Format>,
+    /// Short for `--format json`.
+    #[structopt(long = "json", conflicts_with = "format")]
+    json: bool,
+    /// Print the JSON Schema of the lines written by `--json` and exit.
+    #[structopt(long = "json-schema")]
+    json_schema: bool,
+    /// Print the totals of the search after the results: inputs searched, binary and
+    /// ignored, errors, lines and bytes read, elapsed time and throughput.
+    #[structopt(long = "stats")]
+    stats: bool,
+    /// Do not print errors about files that could not be searched.
+    #[structopt(long = "no-messages")]
+    no_messages: bool,
+    /// Print to stderr what the search leaves out and why, such as the members of archives
+    /// that no `--archive-glob` matches.
+    #[structopt(long = "debug")]
+    debug: bool,
+    /// Record the run in this directory: its arguments, and the lines it prints as the events
+    /// of `--json`, whatever the format. `grrs report` prints them again. Put it in the
+    /// config file to record every run. Needs the `json` feature.
+    #[structopt(long = "journal", parse(from_os_str))]
+    #[cfg_attr(not(feature = "json"), allow(dead_code))]
+    journal: Option<std::path::PathBuf>,
+    /// Start as fast as possible, for editor plugins running a search on every keystroke:
+    /// the config file is not read, the output is never taken for a terminal, so nothing is
+    /// colored unless `--color always` says so, and the file types are only looked up for
+    /// `--type` and `--type-not`.
+    #[structopt(long = "editor-mode")]
+    editor_mode: bool,
+    /// Serve newline-delimited JSON search requests on stdin, for editor plugins.
+    #[structopt(long = "stdio-server")]
+    stdio_server: bool,
+    /// Set when searching through `grrs index search`: the files searched are those of the
+    /// index of `--path` that may contain the pattern.
+    #[structopt(skip)]
+    indexed: bool,
+    /// The number of threads picked when `--threads` is 0, worked out once.
+    #[structopt(skip)]
+    picked_threads: std::sync::OnceLock<usize>,
+    /// The decoders added by the library, which the command line has none of.
+    #[structopt(skip)]
+    decoders: decode::DecoderRegistry,
+    /// The arguments of the command line, those of the config file first, which `--journal`
+    /// records.
+    #[structopt(skip)]
+    #[cfg_attr(not(feature = "json"), allow(dead_code))]
+    argv: Vec<String>,
+    /// The matchers `--stdio-server` keeps from one search to the next.
+    #[structopt(skip)]
+    matchers: Option<std::sync::Arc<matcher::Cache>>,
+}
+
+impl Cli {
+    /// The pattern to look for. Only missing with `--stdio-server` and `--json-schema`, which never
+    /// search.
+    fn pattern(&self) -> &str {
+        self.pattern.as_deref().unwrap_or_default()
+    }
+
+    /// Every pattern to look for: the positional one, then those of `-e` and of `-f`.
+    fn patterns(&self) -> AnyhowResult<Vec<String>> {
+        let mut patterns: Vec<_> = self.pattern.iter().chain(&self.regexp).cloned().collect();
+        if let Some(file) = &self.file {
+            let content = std::fs::read_to_string(file)
+                .with_context(|| format!("Could not read patterns from {:?}", file))?;
+            patterns.extend(content.lines().map(String::from));
+        }
+        Ok(patterns)
+    }
+
+    /// Whether files are searched through memory maps.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn mmap_mode(&self) -> mmap::Mode {
+        if self.mmap {
+            mmap::Mode::Always
+        } else if self.no_mmap {
+            mmap::Mode::Never
+        } else {
+            mmap::Mode::Auto
+        }
+    }
+
+    /// How several literal patterns are searched for.
+    fn strategy(&self) -> matcher::Strategy {
+        if self.aho_corasick {
+            matcher::Strategy::AhoCorasick
+        } else if self.no_aho_corasick {
+            matcher::Strategy::EachPattern
+        } else {
+            matcher::Strategy::Auto
+        }
+    }
+
+    /// How `--unique` or `--unique-counts` reports the occurrences, if at all.
+    fn unique(&self) -> Option<printer::Unique> {
+        if self.unique_counts {
+            Some(printer::Unique::Counts)
+        } else if self.unique {
+            Some(printer::Unique::Values)
+        } else {
+            None
+        }
+    }
+
+    /// How to print the results.
+    fn format(&self) -> printer::Format {
+        if self.json {
+            return printer::Format::Json;
+        }
+        self.format.clone().unwrap_or(printer::Format::Text)
+    }
+
+    /// The number of lines to print before and after each match, if any context was asked for.
+    fn context(&self) -> Option<(usize, usize)> {
+        if self.before_context.is_none() && self.after_context.is_none() && self.context.is_none() {
+            return None;
+        }
+        let context = self.context.unwrap_or(0);
+        Some((self.before_context.unwrap_or(context), self.after_context.unwrap_or(context)))
+    }
+}
+
+/// Check a glob, which a wasm32 build, without globs, takes as it is.
+fn parse_glob(glob: &str) -> AnyhowResult<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    globs::Glob::new(glob)?;
+    Ok(glob.to_string())
+}
+
+/// Parse a size such as `512`, `64K`, `10M` or `2G` into a number of bytes.
+fn parse_size(size: &str) -> AnyhowResult<u64> {
+    let (digits, multiplier) = match size.chars().last() {
+        Some('K') | Some('k') => (&size[..size.len() - 1], 1 << 10),
+        Some('M') | Some('m') => (&size[..size.len() - 1], 1 << 20),
+        Some('G') | Some('g') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    let number: u64 = digits.parse()
+        .with_context(|| format!("Invalid size: {:?}", size))?;
+    number.checked_mul(multiplier)
+        .with_context(|| format!("Size is too large: {:?}", size))
+}
+
+/// Parse the size of a buffer, like `parse_size`. An empty buffer could not hold anything.
+fn parse_buffer_size(size: &str) -> AnyhowResult<usize> {
+    match usize::try_from(parse_size(size)?) {
+        Ok(0) => Err(anyhow::anyhow!("A buffer cannot be empty: {:?}", size)),
+        Ok(size) => Ok(size),
+        Err(_) => Err(anyhow::anyhow!("Size is too large: {:?}", size)),
+    }
+}
+
+/// A `--max-columns` value.
+#[derive(Debug, Clone, Copy)]
+enum MaxColumns {
+    /// The width of the terminal.
+    Auto,
+    Count(usize),
+}
+
+fn parse_max_columns(value: &str) -> AnyhowResult<MaxColumns> {
+    if value == "auto" {
+        return Ok(MaxColumns::Auto);
+    }
+    let count = value.parse()
+        .with_context(|| format!("Invalid number of columns: {:?} (expected a number or auto)",
+            value))?;
+    Ok(MaxColumns::Count(count))
+}
+
+/// The order in which files are visited, as picked with `--sort`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sort {
+    /// Whatever order the file system lists directories in.
+    None,
+    Path,
+    /// Least recently modified first.
+    Mtime,
+    /// Smallest first.
+    Size,
+}
+
+fn parse_sort(sort: &str) -> AnyhowResult<Sort> {
+    match sort {
+        "none" => Ok(Sort::None),
+        "path" => Ok(Sort::Path),
+        "mtime" => Ok(Sort::Mtime),
+        "size" => Ok(Sort::Size),
+        _ => Err(anyhow::anyhow!(
+            "Unknown sort order: {} (expected path, mtime, size or none)", sort)),
+    }
+}
+
+impl Display for Cli {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(formatter, "This is the pattern: {} and this is the path: {:?}",
+            self.pattern(), self.path)
+    }
+}
+
+/// Running totals of a search, shared by every input it reads.
+#[derive(Default)]
+struct Stats {
+    /// Matching lines printed so far.
+    matches: usize,
+    /// Inputs searched: files, archive entries or file versions.
+    searched: usize,
+    /// Searched inputs that turned out to be binary.
+    binary: usize,
+    /// Entries the walker left out, and archive members `--archive-glob` left out.
+    ignored: usize,
+    lines: u64,
+    /// Bytes read, after decompression and transcoding.
+    bytes: u64,
+    /// Lines longer than `--max-line-length`.
+    long_lines: usize,
+    /// Files and directories that could not be searched.
+    errors: usize,
+}
+
+impl Stats {
+    /// Add the totals of `other`, kept apart while searching on another thread.
+    fn add(&mut self, other: &Stats) {
+        self.matches += other.matches;
+        self.searched += other.searched;
+        self.binary += other.binary;
+        self.ignored += other.ignored;
+        self.lines += other.lines;
+        self.bytes += other.bytes;
+        self.long_lines += other.long_lines;
+        self.errors += other.errors;
+    }
+
+    /// The totals as the library reports them, for a search on `threads` threads that took
+    /// `elapsed`.
+    fn run_stats(&self, threads: usize, elapsed: std::time::Duration) -> RunStats {
+        RunStats {
+            matched_lines: self.matches,
+            searched: self.searched,
+            binary: self.binary,
+            ignored: self.ignored,
+            errors: self.errors,
+            lines_read: self.lines,
+            bytes_read: self.bytes,
+            long_lines: self.long_lines,
+            threads,
+            elapsed_seconds: elapsed.as_secs_f64(),
+            matcher_cache_hits: None,
+        }
+    }
+}
+
+/// Failure to write the results. Unlike a file that cannot be read, this ends the search:
+/// nothing written after it would reach the reader either.
+#[derive(Debug)]
+struct OutputError(std::io::Error);
+
+impl Display for OutputError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(formatter, "Could not write the results: {}", self.0)
+    }
+}
+
+impl std::error::Error for OutputError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// Run the `grrs` command line: parse the arguments, search, and return the exit status.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_cli() -> std::result::Result<i32, SearchError> {
+    // Use the *from_args* method provided by *derive(StructOpt)"
+    // to parse the input arguments.
+    let mut argv = std::env::args_os().peekable();
+    let program = argv.next();
+    // `report` and `index` are only subcommands as the first argument, anywhere else they are
+    // patterns.
+    #[cfg(feature = "json")]
+    if argv.peek().is_some_and(|arg| arg == "report") {
+        argv.next();
+        let command = std::iter::once("grrs report".into()).chain(argv);
+        journal::report(journal::Command::from_iter(command), &mut std::io::stdout())?;
+        return Ok(0);
+    }
+    let args = if argv.peek().is_some_and(|arg| arg == "index") {
+        argv.next();
+        let subcommand = argv.next();
+        // The config file holds search arguments, which `index build` does not take.
+        let config_args = if subcommand.as_deref() == Some("search".as_ref()) {
+            config::args()?
+        } else {
+            Vec::new()
+        };
+        let argv: Vec<_> = config_args.into_iter().chain(argv).collect();
+        let command = std::iter::once("grrs index".into()).chain(subcommand)
+            .chain(argv.iter().cloned());
+        match index::Command::from_iter(command) {
+            index::Command::Build { dir } => {
+                index::build(&dir, &mut std::io::stdout())?;
+                return Ok(0);
+            }
+            index::Command::Search(mut args) => {
+                args.indexed = true;
+                args.argv = argv.iter().map(|arg| arg.to_string_lossy().into_owned()).collect();
+                args
+            }
+        }
+    } else {
+        let argv: Vec<_> = argv.collect();
+        let config_args = if argv.iter().any(|arg| arg == "--editor-mode") {
+            Vec::new()
+        } else {
+            config::args()?
+        };
+        let argv: Vec<_> = config_args.into_iter().chain(argv).collect();
+        let mut args = Cli::from_iter(program.into_iter().chain(argv.iter().cloned()));
+        args.argv = argv.iter().map(|arg| arg.to_string_lossy().into_owned()).collect();
+        args
+    };
+    let config = SearchConfig::try_from(args)?;
+    for warning in config.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+    let args = &config.args;
+    #[cfg(feature = "json")]
+    if args.stdio_server {
+        server::serve()?;
+        return Ok(0);
+    }
+    #[cfg(feature = "json")]
+    if args.json_schema {
+        print!("{}", event::SCHEMA);
+        return Ok(0);
+    }
+    if let Some(bench) = args.bench {
+        bench::run(&config, bench, &mut std::io::stdout())?;
+        return Ok(0);
+    }
+    if args.files {
+        return Ok(list_files(args)?);
+    }
+
+    let stats = match &args.output {
+        Some(output) => search_to_file(&config, output)?,
+        None if paged(args) => match search_to_pager(&config)? {
+            Some(stats) => stats,
+            // The pager was quit early, the user has seen what they were looking for.
+            None => return Ok(0),
+        },
+        None => search_to_stdout(&config)?,
+    };
+    Ok(exit_status(args, &stats))
+}
+
+/// Search and write the results to stdout.
+#[cfg(not(target_arch = "wasm32"))]
+fn search_to_stdout(config: &SearchConfig) -> AnyhowResult<Stats> {
+    // @todo SOLVED Create custom `BufferedStdout` that locks when flushing the internal buffer.
+    // Holding a `StdoutLock` for the whole search kept every other thread from printing,
+    // and the `Stdout` it borrows from had to be kept alive next to it. `BufferedStdout`
+    // owns its buffer and only takes the lock while writing it out.
+    let mut buf_writer = stdout::BufferedStdout::with_capacity(config.args.write_buffer);
+
+    let stats = run(config, &mut buf_writer, cancel::interrupt().flag())?;
+    // Although "BufferedStdout" flushes when it's dropped, it's better to manually call it.
+    // The reason for this is that if there are any errors during the dropping, they will be
+    // ignored. Also, if the buffer is empty, the flush will not be performed.
+    // @todo Test the attempt flush on drop behaviour.
+    // See: https://github.com/flowreenLZR/rust-cli-book/issues/2
+    buf_writer.flush().map_err(OutputError)?;
+    Ok(stats)
+}
+
+/// Whether to show the results in a pager.
+#[cfg(not(target_arch = "wasm32"))]
+fn paged(args: &Cli) -> bool {
+    args.pager && !args.no_pager && writes_to_terminal(args)
+}
+
+/// Search and show the results in a pager. Returns `None` if the pager was quit before
+/// everything was written. Without a pager the results go to stdout.
+#[cfg(not(target_arch = "wasm32"))]
+fn search_to_pager(config: &SearchConfig) -> AnyhowResult<Option<Stats>> {
+    let mut pager = match pager::Pager::spawn() {
+        Ok(Some(pager)) => pager,
+        Ok(None) => return search_to_stdout(config).map(Some),
+        Err(err) => {
+            eprintln!("Warning: {:#}", err);
+            return search_to_stdout(config).map(Some);
+        }
+    };
+    let mut writer = std::io::BufWriter::with_capacity(config.args.write_buffer, pager.input());
+    let result = run(config, &mut writer, &AtomicBool::new(false))
+        .and_then(|stats| Ok(writer.flush().map(|()| stats).map_err(OutputError)?));
+    drop(writer);
+    pager.wait()?;
+    match result {
+        Err(err) if err.downcast_ref::<OutputError>()
+            .is_some_and(|err| err.0.kind() == std::io::ErrorKind::BrokenPipe) => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+/// Search and write the results to the file at `output`, created or truncated first.
+#[cfg(not(target_arch = "wasm32"))]
+fn search_to_file(config: &SearchConfig, output: &Path) -> AnyhowResult<Stats> {
+    let file = std::fs::File::create(output)
+        .with_context(|| format!("Could not create the output file: {:?}", output))?;
+    let mut writer = std::io::BufWriter::with_capacity(config.args.write_buffer, file);
+    let stats = run(config, &mut writer, cancel::interrupt().flag())?;
+    writer.flush().map_err(OutputError)?;
+    Ok(stats)
+}
+
+/// Print the path of every file a search with `args` would read, as `--files` does. The exit
+/// status is that of a search in which every file listed matched.
+#[cfg(not(target_arch = "wasm32"))]
+fn list_files(args: &Cli) -> AnyhowResult<i32> {
+    let mut walker = walk::from_args(args, &*pattern_matcher(args, &args.patterns()?)?)?;
+    let mut writer = stdout::BufferedStdout::with_capacity(args.write_buffer);
+    let mut stats = Stats::default();
+    for path in walk::sorted(&mut walker, args.sort) {
+        match path {
+            Ok(path) => {
+                stats.matches += 1;
+                writer.write_all(&walk::path_to_bytes(&path))
+                    .and_then(|()| writer.write_all(b"\n"))
+                    .map_err(OutputError)?;
+            }
+            Err(err) => {
+                stats.errors += 1;
+                if !args.no_messages {
+                    eprintln!("Error: {:#}", err);
+                }
+            }
+        }
+    }
+    writer.flush().map_err(OutputError)?;
+    Ok(exit_status(args, &stats))
+}
+
+/// The exit status of a search, as in grep: 0 if anything matched, 1 if nothing did and 2 if
+/// some file could not be searched. With `--quiet` a match wins over errors.
+#[cfg(not(target_arch = "wasm32"))]
+fn exit_status(args: &Cli, stats: &Stats) -> i32 {
+    if args.estimate {
+        0
+    } else if stats.errors > 0 && !(args.quiet && stats.matches > 0) {
+        2
+    } else if stats.matches > 0 {
+        0
+    } else {
+        1
+    }
+}
+
+/// The matcher for `patterns`, read from `args` already, searched for as `args` says.
+fn pattern_matcher(args: &Cli, patterns: &[String]) -> AnyhowResult<Box<dyn matcher::Matcher>> {
+    if args.regex {
+        matcher::regexes(patterns)
+    } else {
+        matcher::literals(patterns, args.strategy())
+    }
+}
+
+// How often the cancellation of a search on several threads is checked.
+const CANCEL_POLL: std::time::Duration = std::time::Duration::from_millis(20);
+
+// How many lines of results are handed to the writer at once.
+const BATCH_LINES: usize = 64;
+
+/// The printer of the results of a search with `args` for `patterns`, compiled as `matcher`.
+#[cfg(not(target_arch = "wasm32"))]
+fn printer<W: Write>(args: &Cli, matcher: &dyn matcher::Matcher, patterns: &[String], writer: W)
+    -> AnyhowResult<Printer<W>>
+{
+    let format = match args.format() {
+        printer::Format::Template(template) => {
+            printer::Format::Template(template.resolve(matcher)?)
+        }
+        printer::Format::Text if args.vimgrep => printer::Format::Vimgrep,
+        format => format,
+    };
+    Ok(Printer::new(writer)
+        .with_filename(with_filename(args))
+        .heading(heading(args))
+        .line_number(args.line_number || args.vimgrep)
+        .column(args.column || args.vimgrep)
+        .byte_offset(args.byte_offset)
+        .only_matching(args.only_matching)
+        .unique(args.unique())
+        .sort_results(args.sort_results)
+        .max_columns(max_columns(args))
+        .null_data(args.null_data)
+        .context(args.context().is_some())
+        .theme(theme(args))
+        .hyperlinks(hyperlinks(args))
+        .format(format)
+        .stats(args.stats)
+        .messages(!args.no_messages)
+        .pattern(&patterns.join("|")))
+}
+
+/// Search everything `config` selects and write the results to `writer`.
+/// The search stops, in the middle of a file if need be, once `cancelled` is set.
+/// Files that cannot be searched are reported to stderr and skipped, and counted in the
+/// returned statistics.
+#[cfg(not(target_arch = "wasm32"))]
+fn run(config: &SearchConfig, writer: &mut impl Write, cancelled: &AtomicBool)
+    -> AnyhowResult<Stats>
+{
+    let start = std::time::Instant::now();
+    let args = &config.args;
+    let patterns = args.patterns()?;
+    // Shared with the tasks of `--async`.
+    let shared: Arc<dyn matcher::Matcher> = match (&config.matcher, &args.matchers) {
+        (Some(matcher), _) => matcher.clone(),
+        (None, Some(cache)) => {
+            let key = matcher::Key {
+                patterns: patterns.clone(),
+                regex: args.regex,
+                strategy: args.strategy(),
+            };
+            cache.get_or_compile(key, || pattern_matcher(args, &patterns))?
+        }
+        (None, None) => pattern_matcher(args, &patterns)?.into(),
+    };
+    let matcher = &*shared;
+    let mut printer = printer(args, matcher, &patterns,
+        batch::Batched::new(writer, BATCH_LINES))?;
+    #[cfg(feature = "json")]
+    if let Some(journal) = &args.journal {
+        printer = printer.journal(journal::record(journal, args, &patterns)?);
+    }
+    let mut stats = Stats::default();
+    if args.async_mode {
+        #[cfg(feature = "async")]
+        stream::search(args, shared, &patterns.join("|"), &mut printer, &mut stats, cancelled)?;
+    } else if let Some(range) = &args.git_log {
+        let path = args.path.as_deref().unwrap_or(Path::new("."));
+        history::for_each_blob(path, range, |name, reader| {
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(SearchError::Cancelled.into());
+            }
+            if args.quiet && stats.matches > 0 {
+                return Ok(false);
+            }
+            let result = decode::reader(Path::new(name), Box::new(reader), args.search_zip,
+                &args.decoders)
+                .and_then(|mut reader| {
+                    search_reader(name, &mut reader, args, matcher, &mut printer, &mut stats,
+                        cancelled)
+                });
+            match result {
+                Err(err) if err.is::<OutputError>() => Err(err),
+                Err(err) => {
+                    let err = err.context(format!("Could not search {}", name));
+                    report(err, &mut printer, &mut stats);
+                    Ok(true)
+                }
+                Ok(()) => Ok(true),
+            }
+        })?;
+    } else if let Some(url) = args.path.as_deref().filter(|path| net::is_url(path)) {
+        let url = url.to_string_lossy();
+        match search_url(&url, args, matcher, &mut printer, &mut stats, cancelled) {
+            Ok(()) => {}
+            Err(err) if err.is::<OutputError>() => return Err(err),
+            Err(err) => {
+                let err = err.context(format!("Could not search {}", url));
+                report(err, &mut printer, &mut stats);
+            }
+        }
+    } else {
+        let mut walker = walk::from_args(args, matcher)?;
+        if args.estimate {
+            estimate::estimate(walker, matcher, printer.get_mut())?;
+            return Ok(stats);
+        }
+        let paths = walk::sorted(&mut walker, args.sort);
+        let threads = threads(args);
+        if threads > 1 {
+            search_parallel(paths, threads, args, matcher, &mut printer, &mut stats, cancelled)?;
+        } else {
+            let mut ring = uring::Ring::new();
+            let batch_files = if ring.is_some() { uring::BATCH_FILES } else { 1 };
+            let mut paths = paths;
+            'search: loop {
+                let batch: Vec<_> = paths.by_ref().take(batch_files).collect();
+                if batch.is_empty() {
+                    break;
+                }
+                let readable: Vec<_> = batch.iter().flatten().map(PathBuf::as_path).collect();
+                let mut contents = read_ahead(ring.as_mut(), &readable, args).into_iter();
+                for path in batch {
+                    if cancelled.load(Ordering::Relaxed) {
+                        return Err(SearchError::Cancelled.into());
+                    }
+                    // With `--quiet` the first match settles the exit status.
+                    if args.quiet && stats.matches > 0 {
+                        break 'search;
+                    }
+                    let path = match path {
+                        Ok(path) => path,
+                        Err(err) => {
+                            report(err, &mut printer, &mut stats);
+                            continue;
+                        }
+                    };
+                    let contents = contents.next().flatten();
+                    match search_path(&path, contents, args, matcher, &mut printer, &mut stats,
+                        cancelled)
+                    {
+                        Ok(()) => {}
+                        Err(err) if err.is::<OutputError>() => return Err(err),
+                        Err(err) => {
+                            let err = err.context(format!("Could not search {}",
+                                path.display()));
+                            report(err, &mut printer, &mut stats);
+                        }
+                    }
+                }
+            }
+        }
+        stats.ignored += walker.ignored();
+    }
+    // Set in the middle of the last input, which was given up.
+    if cancelled.load(Ordering::Relaxed) {
+        return Err(SearchError::Cancelled.into());
+    }
+    if stats.long_lines > 0 && !args.no_messages {
+        let action = if args.skip_long_lines { "skipped" } else { "truncated" };
+        eprintln!("{} line(s) longer than --max-line-length were {}.", stats.long_lines, action);
+    }
+    printer.finish().map_err(OutputError)?;
+    if stats.errors > 0 && !args.no_messages {
+        eprintln!("{} error(s) occurred while searching.", stats.errors);
+    }
+    if !args.quiet {
+        let mut summary = stats.run_stats(threads(args), start.elapsed());
+        summary.matcher_cache_hits = args.matchers.as_ref().map(|cache| cache.hits());
+        printer.summary(summary).map_err(OutputError)?;
+    }
+    printer.get_mut().flush().map_err(OutputError)?;
+    Ok(stats)
+}
+
+/// Hand `err`, about an input that could not be searched, to `sink` and count it. The search
+/// goes on without the input.
+fn report(err: anyhow::Error, sink: &mut impl Sink, stats: &mut Stats) {
+    stats.errors += 1;
+    sink.on_error(err.into());
+}
+
+/// What a worker thread found in a file, for the main thread to print.
+struct Searched {
+    recorder: record::Recorder,
+    stats: Stats,
+    result: AnyhowResult<()>,
+}
+
+/// Search the files of `paths` on `threads` worker threads. Only the main thread prints: the
+/// results of each file are printed as a whole once it has been searched, in the order of
+/// `paths` or, with `--no-order`, in the order the files are done with.
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(clippy::too_many_arguments)]
+fn search_parallel(paths: impl Iterator<Item = AnyhowResult<PathBuf>>, threads: usize,
+    args: &Cli, matcher: &dyn matcher::Matcher, printer: &mut impl Sink,
+    stats: &mut Stats, cancelled: &AtomicBool) -> AnyhowResult<()>
+{
+    // A few files are queued for each thread so that none of them waits for the walker.
+    let (path_sender, path_receiver) = mpsc::sync_channel::<(usize, PathBuf)>(threads * 4);
+    let path_receiver = Mutex::new(path_receiver);
+    let (result_sender, result_receiver) = mpsc::channel::<(usize, Searched)>();
+    let mut collector = collect::Collector::new(!args.no_order);
+    // Set once the files left need not be searched. They are still taken off the queue.
+    let stop = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        // Gives up the files being searched once the search is cancelled, which the threads
+        // busy with a file or waiting for the queue would not see otherwise.
+        let watcher = scope.spawn(|| {
+            while !stop.load(Ordering::Relaxed) {
+                if cancelled.load(Ordering::Relaxed) {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                std::thread::park_timeout(CANCEL_POLL);
+            }
+        });
+        for _ in 0..threads {
+            let result_sender = result_sender.clone();
+            let (path_receiver, stop) = (&path_receiver, &stop);
+            scope.spawn(move || {
+                // With a ring, the files queued are taken and read a batch at a time.
+                let mut ring = uring::Ring::new();
+                let batch_files = if ring.is_some() { uring::BATCH_FILES } else { 1 };
+                loop {
+                    let batch: Vec<_> = {
+                        let path_receiver =
+                            path_receiver.lock().expect("the queue is never poisoned");
+                        match path_receiver.recv() {
+                            Ok(next) => std::iter::once(next)
+                                .chain(path_receiver.try_iter().take(batch_files - 1))
+                                .collect(),
+                            Err(_) => break,
+                        }
+                    };
+                    let paths: Vec<_> = batch.iter().map(|(_, path)| path.as_path()).collect();
+                    let contents = if stop.load(Ordering::Relaxed) {
+                        vec![None; paths.len()]
+                    } else {
+                        read_ahead(ring.as_mut(), &paths, args)
+                    };
+                    for ((index, path), contents) in batch.into_iter().zip(contents) {
+                        let mut searched = Searched {
+                            recorder: record::Recorder::default(),
+                            stats: Stats::default(),
+                            result: Ok(()),
+                        };
+                        // Skipped files are still collected, or the ones after them would wait
+                        // forever.
+                        if !stop.load(Ordering::Relaxed) {
+                            searched.result = search_path(&path, contents, args, matcher,
+                                &mut searched.recorder, &mut searched.stats, stop)
+                                .with_context(|| format!("Could not search {}", path.display()));
+                            // With `--quiet` the first match settles the exit status, whichever
+                            // file it is in: the files being searched on the other threads are
+                            // given up.
+                            if args.quiet && searched.stats.matches > 0 {
+                                stop.store(true, Ordering::Relaxed);
+                            }
+                        }
+                        if result_sender.send((index, searched)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        drop(result_sender);
+        let mut result = Ok(());
+        for (index, path) in paths.enumerate() {
+            for (index, searched) in result_receiver.try_iter() {
+                collector.push(index, searched);
+            }
+            result = print_collected(&mut collector, args, printer, stats);
+            if result.is_ok() && cancelled.load(Ordering::Relaxed) {
+                result = Err(SearchError::Cancelled.into());
+            }
+            // With `--quiet` the first match settles the exit status.
+            if result.is_err() || args.quiet && stats.matches > 0 {
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+            // A worker thread found a match for `--quiet`.
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            match path {
+                Ok(path) => {
+                    if path_sender.send((index, path)).is_err() {
+                        break;
+                    }
+                }
+                // Reported in turn, like the errors of the files.
+                Err(err) => collector.push(index, Searched {
+                    recorder: record::Recorder::default(),
+                    stats: Stats::default(),
+                    result: Err(err),
+                }),
+            }
+        }
+        // The workers stop once the queue is empty.
+        drop(path_sender);
+        if result.is_ok() {
+            result = result_receiver.iter().try_for_each(|(index, searched)| {
+                collector.push(index, searched);
+                print_collected(&mut collector, args, printer, stats)
+            });
+        }
+        if result.is_ok() && cancelled.load(Ordering::Relaxed) {
+            result = Err(SearchError::Cancelled.into());
+        }
+        // Whatever is still being searched is of no use after an error.
+        stop.store(true, Ordering::Relaxed);
+        watcher.thread().unpark();
+        result
+    })
+}
+
+// Print the results of the files searched by worker threads that are next in turn, and
+// report their errors.
+fn print_collected(collector: &mut collect::Collector<Searched>, args: &Cli,
+    printer: &mut impl Sink, stats: &mut Stats) -> AnyhowResult<()>
+{
+    while let Some(searched) = collector.pop() {
+        if args.quiet && stats.matches > 0 {
+            continue;
+        }
+        searched.recorder.replay(printer).map_err(OutputError)?;
+        stats.add(&searched.stats);
+        if let Err(err) = searched.result {
+            report(err, printer, stats);
+        }
+    }
+    Ok(())
+}
+
+/// The colors of the output, or `None` if it is not to be colored.
+#[cfg(not(target_arch = "wasm32"))]
+fn theme(args: &Cli) -> Option<color::Theme> {
+    let text = args.format() == printer::Format::Text;
+    if !text || !args.color.enabled(writes_to_terminal(args)) {
+        return None;
+    }
+    color::theme(&args.colors)
+}
+
+/// Whether file names are links, which is only for text shown on supporting terminals.
+/// Pagers are not known to pass them through.
+#[cfg(not(target_arch = "wasm32"))]
+fn hyperlinks(args: &Cli) -> bool {
+    let text = args.format() == printer::Format::Text;
+    !args.no_hyperlinks && text && writes_to_terminal(args) && !paged(args)
+        && hyperlink::supported()
+}
+
+/// Whether to print file names as headings: explicitly with `--heading` or `--no-heading`,
+/// and otherwise when writing to a terminal.
+#[cfg(not(target_arch = "wasm32"))]
+fn heading(args: &Cli) -> bool {
+    // With `--async`, the lines of different files come mixed.
+    if args.vimgrep || args.no_heading || args.async_mode {
+        return false;
+    }
+    args.heading || writes_to_terminal(args)
+}
+
+/// The number of characters printed lines are shortened to, if any.
+#[cfg(not(target_arch = "wasm32"))]
+fn max_columns(args: &Cli) -> Option<usize> {
+    match args.max_columns? {
+        MaxColumns::Count(count) => Some(count),
+        MaxColumns::Auto if writes_to_terminal(args) => {
+            terminal_size::terminal_size().map(|(width, _)| usize::from(width.0))
+        }
+        MaxColumns::Auto => None,
+    }
+}
+
+/// Whether the results go to a terminal rather than a file or a pipe.
+#[cfg(not(target_arch = "wasm32"))]
+fn writes_to_terminal(args: &Cli) -> bool {
+    args.output.is_none() && !args.editor_mode && std::io::stdout().is_terminal()
+}
+
+/// The number of files to search at the same time: `--threads`, or the number picked for
+/// what is searched.
+#[cfg(not(target_arch = "wasm32"))]
+fn threads(args: &Cli) -> usize {
+    if args.threads > 0 {
+        return args.threads;
+    }
+    *args.picked_threads.get_or_init(|| pick_threads(args))
+}
+
+// Searches on network filesystems get at least this many threads, however few CPUs there are.
+#[cfg(not(target_arch = "wasm32"))]
+const MIN_REMOTE_THREADS: usize = 4;
+
+// The number of threads for `--threads 0`.
+#[cfg(not(target_arch = "wasm32"))]
+fn pick_threads(args: &Cli) -> usize {
+    let cpus = std::thread::available_parallelism().map_or(1, usize::from);
+    // The history of a repository and URLs are read one input after the other.
+    if args.git_log.is_some() || args.path.as_deref().is_some_and(net::is_url) {
+        return 1;
+    }
+    let path = match &args.path {
+        Some(path) if args.files_from.is_none() && args.files_from0.is_none() => path,
+        // A list of files.
+        _ => return cpus,
+    };
+    // Not opened unless it is a directory: opening a FIFO would wait for a writer, and take
+    // what it writes away from the search.
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        // The error is reported when searching.
+        Err(_) => return 1,
+    };
+    if !metadata.is_dir() {
+        // A single file only keeps more than one thread busy when it is searched in chunks.
+        return if metadata.len() >= chunk::MIN_SIZE as u64 { cpus } else { 1 };
+    }
+    // Each thread mostly waits for the network: more of them keep more reads in flight.
+    if std::fs::File::open(path).is_ok_and(|file| mmap::is_remote(&file)) {
+        return (cpus * 2).max(MIN_REMOTE_THREADS);
+    }
+    cpus
+}
+
+/// Whether to prefix lines with the name of their file: explicitly with `-H` or `-h`, and
+/// otherwise, like grep, when more than one file is searched.
+#[cfg(not(target_arch = "wasm32"))]
+fn with_filename(args: &Cli) -> bool {
+    if args.vimgrep {
+        return true;
+    }
+    if args.with_filename || args.no_filename {
+        return args.with_filename;
+    }
+    match &args.path {
+        Some(path) => {
+            let archive = args.archive && (archive::is_tar(path) || archive::is_zip(path));
+            args.git_log.is_some() || path.is_dir() || archive
+        }
+        // A list of files.
+        None => true,
+    }
+}
+
+/// Print every line of the response body of `url` that contains the pattern.
+#[cfg(not(target_arch = "wasm32"))]
+fn search_url(url: &str, args: &Cli, matcher: &dyn matcher::Matcher,
+    printer: &mut impl Sink, stats: &mut Stats, stop: &AtomicBool)
+    -> AnyhowResult<()>
+{
+    let body = net::open(url)?;
+    let mut reader = decode::reader(Path::new(url), body, args.search_zip, &args.decoders)?;
+    search_reader(url, &mut reader, args, matcher, printer, stats, stop)
+}
+
+/// Search the file at `path`, from `contents` if it was read already.
+#[cfg(not(target_arch = "wasm32"))]
+fn search_path(path: &Path, contents: Option<Vec<u8>>, args: &Cli, matcher: &dyn matcher::Matcher,
+    printer: &mut impl Sink, stats: &mut Stats, stop: &AtomicBool)
+    -> AnyhowResult<()>
+{
+    let contents = match contents {
+        Some(contents) => contents,
+        None => return search_file(path, args, matcher, printer, stats, stop),
+    };
+    let mut reader = decode::reader(path, Box::new(contents.as_slice()), args.search_zip,
+        &args.decoders)?;
+    search_reader(&path.display().to_string(), &mut reader, args, matcher, printer, stats, stop)
+}
+
+// Read the small files among `paths` in one batch through `ring`, if there is one. The files
+// that are not, like the archives of `--archive`, which are read entry by entry, or any file
+// when `--mmap` is set, get `None`.
+#[cfg(not(target_arch = "wasm32"))]
+fn read_ahead(ring: Option<&mut uring::Ring>, paths: &[&Path], args: &Cli)
+    -> Vec<Option<Vec<u8>>>
+{
+    let ring = match ring {
+        Some(ring) if args.mmap_mode() != mmap::Mode::Always => ring,
+        _ => return vec![None; paths.len()],
+    };
+    let archive = |path: &Path| args.archive && (archive::is_tar(path) || archive::is_zip(path));
+    let small: Vec<_> = paths.iter().copied().filter(|path| !archive(path)).collect();
+    let mut contents = uring::read_small_files(ring, &small, args.read_buffer as u64).into_iter();
+    paths.iter().map(|path| if archive(path) { None } else { contents.next().flatten() }).collect()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn search_file(path: &Path, args: &Cli, matcher: &dyn matcher::Matcher,
+    printer: &mut impl Sink, stats: &mut Stats, stop: &AtomicBool)
+    -> AnyhowResult<()>
+{
+    // "read_to_string" returns a "Result" struct which may contain
+    // an OK value or an Err value.
+    // "expect" then consumes the "self" object, returning the OK value
+    // or panicking if the the result is an "Err".
+    // let content = std::fs::read_to_string(&args.path)
+    //     .expect("Could not read the file!");
+
+    // Iterate over content and match pattern.
+    // for line in content.lines() {
+    //     if line.contains(&args.pattern) {
+    //         println!("{}", line);
+    //     }
+    // }
+
+    // Using std::fs::read_to_string is memory expensive because it reads the whole file
+    // into memory.
+    // BufReader should solve that.
+
+    let file = std::fs::File::open(path);
+    // Option 1.
+    // "File::open" returns a "Result" which can be evaluated using "expect".
+    // let file = file.expect("File could not be opened!");
+    // let buf_reader = std::io::BufReader::new(file);
+
+    // Option 2.
+    // It also can be evaluated using a match.
+    // let buf_reader : std::io::BufReader<std::fs::File>;
+    // match file {
+    //     Ok(handle) => {
+    //         buf_reader = std::io::BufReader::new(handle);
+    //         println!("File was opened properly!")
+    //     },
+    //     Err(msg) => {
+    //         println!("File was not opened properly, error is: {}", msg);
+    //         return; // This was needed otherwise rustc would report that
+    //                 // buf_reader might be used uninitialized.
+    //                 // @todo Is there a better way to halt execution?
+    //     }
+    // }
+
+    // Option 3.
+    // Just like option 2 but instead of print and return, panic.
+    // let buf_reader = match file {
+    //     Ok(handle) => {
+    //         println!("File was opened properly!");
+    //         std::io::BufReader::new(handle)
+    //     },
+    //     Err(msg) => {
+    //         panic!("File was not opened properly, error is: {}", msg);
+    //     }
+    // };
+
+    // Option 4.
+    // Use "Result::unwrap". Also panics.
+    // Shortcut for option 3.
+    // let buf_reader = std::io::BufReader::new(file.unwrap());
+
+    // Option 5.
+    // Just like option 2 but with nicer return. This changes signature of main
+    // and the return value.
+    // let buf_reader = match file {
+    //     Ok(file) => { std::io::BufReader::new(file) },
+    //     Err(msg) => { return Err(msg.into()); }
+    // };
+
+    // Option 6.
+    // Shortcut for option 5.
+    // Although File::open may return a std::io::Error and main returns std::error::Error,
+    // "?" expands to code that converts between error types. Kind of what the "Err" branch does
+    // in "Option 5"?
+    // let buf_reader = std::io::BufReader::new(file?);
+
+    // Option 7.
+    // Use a custom, user-defined error to provide a much more specific message.
+    // All "?" operators must be prefixed with the "map_err" call.
+    // That is unless the custom error implements the "From<"Error_Type">" trait where "Error_Type"
+    // is the error type "?" was handling before. This is because "?" expands to code that does
+    // error conversions as long as the necessary "From<E>" traits.
+    // let file = file.map_err(|err| CustomError(
+    //     format!("Error reading `{:?}`: {}", args.path, err)
+    // ))?;
+    // let buf_reader = std::io::BufReader::new(file);
+
+    // Option 8.
+    // @todo SOLVED Why "with_context", which is part of "anyhow::Context" can be invoked on "file", which is a "std::io::Result"?
+    // Are there some type conversions being made?
+    // No type conversions. "Context", a trait from "anyhow", is implemented by the "anyhow" library
+    // for "std::result::Result".
+    // @todo SOLVED "anyhow" implements "Context" for "std::result::Result" and not for "std::io::Result".
+    // "std::io::Result<T>" is an alias for "std::result::Result<T, std::io::Error". Which means
+    // that the "Context" implementation for "std::result::Result"
+    // also applies to "std::io::Result".
+    // This made me realize how cool Rust's Trait system is compared to C++'s inheritance system.
+    // The trait system in rust is similar to the "extension" feature of C# in a way.
+
+    let mut error_message = format!("Could not open file: {:?}", path);
+    // This will return an ANSIString that, when it's Display-ed, surrounds the text
+    // with the required ANSI sequence that would make it red.
+    // Errors end up on stderr, so that is what decides whether to color them.
+    if args.color.enabled(!args.editor_mode && std::io::stderr().is_terminal()) {
+        error_message = color::error(error_message);
+    }
+    let file = file.with_context(|| error_message)?;
+    if args.archive && (archive::is_tar(path) || archive::is_zip(path)) {
+        let size = file.metadata().with_context(|| format!("Could not read {:?}", path))?.len();
+        let members = archive::Members::new(&args.archive_glob, args.debug)?
+            .depth(args.archive_depth, &args.decoders)
+            .limits(args.archive_max_size, args.archive_max_ratio);
+        let search = |name: &str, entry: &mut dyn BufRead| {
+            search_reader(name, entry, args, matcher, printer, stats, stop)
+        };
+        let skipped = if archive::is_tar(path) {
+            // A compressed archive is always unpacked, there is nothing to search otherwise.
+            let reader = decode::reader(path,
+                Box::new(std::io::BufReader::with_capacity(args.read_buffer, file)), true,
+                &args.decoders)?;
+            archive::for_each_tar_entry(path, reader, size, &members, search)?
+        } else {
+            archive::for_each_zip_entry(path, file, size, &members, search)?
+        };
+        stats.ignored += skipped;
+        return Ok(());
+    }
+    let mapped = mmap::map(&file, args.mmap_mode());
+    // A huge file is searched on every thread, however many other files are being searched.
+    let threads = threads(args);
+    if let Some(mapped) = mapped.as_ref().filter(|mapped| chunk::applies(mapped, args, threads)) {
+        return chunk::search(&path.display().to_string(), mapped, threads, args, matcher,
+            printer, stats, stop);
+    }
+    // A slice of bytes is a `BufRead` of its own, so the mapped file needs no extra buffer.
+    let source: Box<dyn BufRead> = match &mapped {
+        Some(mapped) => Box::new(&mapped[..]),
+        None => Box::new(std::io::BufReader::with_capacity(args.read_buffer, file)),
+    };
+    let mut buf_reader = decode::reader(path, source, args.search_zip, &args.decoders)?;
+    search_reader(&path.display().to_string(), &mut buf_reader, args, matcher, printer, stats,
+        stop)
+}
+
+// How much of each input is looked at to tell binary files from text.
+const BINARY_CHECK_SIZE: u64 = 8 << 10;
+
+/// Print every line read from `reader` that contains the pattern.
+/// `name` is how the input is shown, e.g. `archive.tar!file` for archive entries.
+/// Reading stops early, with whatever has been found so far, once `stop` is set.
+#[allow(clippy::too_many_arguments)]
+fn search_reader(name: &str, reader: &mut dyn BufRead, args: &Cli, matcher: &dyn matcher::Matcher,
+    printer: &mut impl Sink, stats: &mut Stats, stop: &AtomicBool) -> AnyhowResult<()>
+{
+    printer.on_file_start(name);
+    stats.searched += 1;
+    // Transcoding comes first: UTF-16 text is full of NUL bytes and would look binary.
+    let mut decoded = encoding::decoder(reader, args.encoding);
+
+    // Only the beginning is checked: a NUL byte this early is a strong hint that the
+    // input is not text, and looking further would mean reading the whole file twice.
+    // It is read up front rather than peeked at with `fill_buf`, since the decoder may hand
+    // out as little as the few bytes it sniffed for a byte-order mark at first.
+    let mut head = Vec::new();
+    decoded.by_ref().take(BINARY_CHECK_SIZE).read_to_end(&mut head)
+        .context("Could not read from file!")?;
+    let reader = &mut head.as_slice().chain(decoded);
+    // With `--null-data` NUL bytes are merely the line separators.
+    if !args.text && !args.null_data && head.contains(&0) {
+        stats.binary += 1;
+        search_binary(reader, args, matcher, printer, stats, stop)?;
+        return printer.on_file_end().map_err(|err| OutputError(err).into());
+    }
+
+    let mut lines = lines::LineReader::new(reader, line_terminator(args), max_line_length(args));
+    let (count, found) = search_lines(&mut lines, 0, args, matcher, printer, stats, stop)?;
+    if count > 0 {
+        printer.on_count(count).map_err(OutputError)?;
+    }
+    if (args.files_with_matches || args.files_without_match) && !args.quiet {
+        list_file(found, args, printer)?;
+    }
+    printer.on_file_end().map_err(OutputError)?;
+
+    Ok(())
+}
+
+/// Print the lines read from `lines` that contain the pattern, and their context. Lines are
+/// numbered from 1, and `offset` is the offset in the input where `lines` starts. Returns
+/// what `--count` counts, and whether a line matched for `-l`, `-L` and `-q`, which stop at
+/// the first one.
+#[allow(clippy::too_many_arguments)]
+fn search_lines(lines: &mut lines::LineReader, offset: u64, args: &Cli,
+    matcher: &dyn matcher::Matcher, printer: &mut impl Sink, stats: &mut Stats, stop: &AtomicBool)
+    -> AnyhowResult<(usize, bool)>
+{
+    let counting = args.count || args.count_matches;
+    let listing = args.files_with_matches || args.files_without_match;
+    // A literal is counted in whole buffers, without splitting them into lines, unless some
+    // lines are left out of the count.
+    let terminator = line_terminator(args);
+    let countable = matcher.as_literal().filter(|literal| {
+        let needle = literal.needle();
+        counting && !listing && !args.quiet && !needle.is_empty() && !needle.contains(&terminator)
+            && args.max_count.is_none() && args.max_line_length.is_none()
+    });
+    if let Some(literal) = countable {
+        let mut counts = lines::Counts::default();
+        while !stop.load(Ordering::Relaxed)
+            && lines.count(literal, args.count_matches, &mut counts)
+                .context("Could not read line from file!")? {}
+        stats.lines += counts.lines;
+        stats.matches += counts.matching_lines as usize;
+        stats.bytes += lines.position();
+        let count = if args.count_matches { counts.occurrences } else { counts.matching_lines };
+        return Ok((count as usize, count > 0));
+    }
+
+    let mut line_number = 0;
+    let mut count = 0;
+    let mut found = false;
+    let (before, after) = args.context().unwrap_or((0, 0));
+    // The last lines that did not match nor follow a match: line number, offset and bytes.
+    // Their buffers are reused as lines go by, and only decoded if they are printed.
+    let mut before_lines: VecDeque<(u64, u64, Vec<u8>)> = VecDeque::with_capacity(before);
+    // Buffers of context lines that were printed, for the next ones.
+    let mut spare_lines: Vec<Vec<u8>> = Vec::new();
+    // Number of lines left to print after the last match.
+    let mut after_left = 0;
+    let mut matching_lines = 0;
+    // A literal is looked for in whole buffers, skipping the lines without it at once. Lines
+    // that may be printed as context, or be too long, need looking at one by one.
+    let skippable = matcher.literal().filter(|literal| {
+        let needle = literal.needle();
+        !needle.is_empty() && !needle.contains(&terminator) && before == 0
+            && args.max_line_length.is_none()
+    });
+    loop {
+        let limit_reached = args.max_count.is_some_and(|max| matching_lines >= max);
+        if limit_reached && after_left == 0 || stop.load(Ordering::Relaxed) {
+            break;
+        }
+        if let (Some(literal), 0) = (skippable, after_left) {
+            let skipped = lines.skip_to(literal)
+                .context("Could not read line from file!")?;
+            line_number += skipped;
+            stats.lines += skipped;
+        }
+
+        // ^Option7
+        // let line = line?;
+
+        // Option 7.
+        // Either this or implement "From<std::io::Error>" for "CustomError".
+        // let line = line.map_err(|_| CustomError(
+        //     format!("Could not read line from file!")
+        // ))?;
+
+        // Option 8.
+        let line = match lines.next_line().context("Could not read line from file!")? {
+            Some(line) => line,
+            None => break,
+        };
+        // Counted whether or not it is printed, skipped lines included.
+        line_number += 1;
+        stats.lines += 1;
+        let offset = offset + line.offset;
+        let mut bytes = line.bytes;
+        if line.truncated {
+            stats.long_lines += 1;
+            if args.skip_long_lines {
+                continue;
+            }
+            // The cut may have split the last character in two.
+            if let Err(err) = std::str::from_utf8(bytes) {
+                if err.error_len().is_none() {
+                    bytes = &bytes[..err.valid_up_to()];
+                }
+            }
+        }
+
+        // Lines are matched as bytes, and only decoded if they are printed: most lines of a
+        // large file are neither, and checking them all for valid UTF-8 would cost as much
+        // as the search itself.
+        if listing || args.quiet {
+            // The first match settles whether the file is listed, the rest need not be read.
+            if matcher.is_match(bytes) {
+                found = true;
+                stats.matches += 1;
+                break;
+            }
+            continue;
+        }
+        if counting {
+            // Nothing is printed for the line, so there is no need to collect spans.
+            let found = occurrences(matcher, bytes, args);
+            if found > 0 {
+                count += found;
+                matching_lines += 1;
+                stats.matches += 1;
+            }
+            continue;
+        }
+        // Past the limit, matches in the remaining context are printed as plain context.
+        if !limit_reached && matcher.is_match(bytes) {
+            let line = decode(bytes, args)?;
+            let spans: Vec<_> = matcher.find_iter(line.as_bytes()).collect();
+            let groups = if needs_groups(args) {
+                matcher.captures(line.as_bytes())
+            } else {
+                Vec::new()
+            };
+            let matched = sink::MatchedLine {
+                number: line_number,
+                offset,
+                text: &line,
+                spans: &spans,
+                groups: &groups,
+            };
+            for (number, offset, text) in &before_lines {
+                printer.on_context(*number, *offset, &decode(text, args)?)
+                    .map_err(OutputError)?;
+            }
+            spare_lines.extend(before_lines.drain(..).map(|(_, _, text)| text));
+            printer.on_match(&matched).map_err(OutputError)?;
+            matching_lines += 1;
+            stats.matches += 1;
+            after_left = after;
+        } else if after_left > 0 {
+            printer.on_context(line_number, offset, &decode(bytes, args)?)
+                .map_err(OutputError)?;
+            after_left -= 1;
+        } else if before > 0 {
+            let mut text = if before_lines.len() == before {
+                before_lines.pop_front().map(|(_, _, text)| text)
+            } else {
+                spare_lines.pop()
+            }.unwrap_or_default();
+            text.clear();
+            text.extend_from_slice(bytes);
+            before_lines.push_back((line_number, offset, text));
+        }
+    }
+    stats.bytes += lines.position();
+    Ok((count, found))
+}
+
+// Print the name of the current file if it is to be listed by `-l` or `-L`.
+fn list_file(found: bool, args: &Cli, printer: &mut impl Sink) -> AnyhowResult<()> {
+    if found == args.files_with_matches {
+        printer.on_file_name().map_err(OutputError)?;
+    }
+    Ok(())
+}
+
+// Whether the capture groups of the matches are printed, by a `--format` template.
+fn needs_groups(args: &Cli) -> bool {
+    matches!(&args.format, Some(printer::Format::Template(template)) if template.has_groups())
+}
+
+// Decode a line to print it: lines that are not valid UTF-8 fail, unless `--lossy` is set.
+fn decode<'a>(bytes: &'a [u8], args: &Cli) -> AnyhowResult<Cow<'a, str>> {
+    if args.lossy {
+        Ok(String::from_utf8_lossy(bytes))
+    } else {
+        Ok(std::str::from_utf8(bytes).context("Could not read line from file!")?.into())
+    }
+}
+
+// What `--count` or `--count-matches` counts for `line`: whether it matches, or how often.
+fn occurrences(matcher: &dyn matcher::Matcher, line: &[u8], args: &Cli) -> usize {
+    if args.count_matches {
+        matcher.count(line)
+    } else {
+        usize::from(matcher.is_match(line))
+    }
+}
+
+// The byte separating the lines of the input.
+fn line_terminator(args: &Cli) -> u8 {
+    if args.null_data { b'\0' } else { b'\n' }
+}
+
+// `--max-line-length` as a buffer size. Lengths past the address space cannot be reached anyway.
+fn max_line_length(args: &Cli) -> Option<usize> {
+    args.max_line_length.map(|length| std::convert::TryFrom::try_from(length).unwrap_or(usize::MAX))
+}
+
+/// Report whether a binary input matches instead of printing its lines, which would only
+/// garble the terminal. Unless `--binary` is set, reading stops at the first match.
+fn search_binary(reader: &mut dyn BufRead, args: &Cli, matcher: &dyn matcher::Matcher,
+    printer: &mut impl Sink, stats: &mut Stats, stop: &AtomicBool) -> AnyhowResult<()>
+{
+    let mut matching_lines = 0;
+    // Counts are printed as for text files, which needs reading to the end as well.
+    let counting = args.count || args.count_matches;
+    let mut count = 0;
+    let listing = args.files_with_matches || args.files_without_match;
+    // Its "lines" are arbitrary, cutting them short is not worth reporting.
+    let mut lines = lines::LineReader::new(reader, line_terminator(args), max_line_length(args));
+    while let Some(line) = lines.next_line().context("Could not read line from file!")? {
+        stats.lines += 1;
+        if args.max_count.is_some_and(|max| matching_lines >= max) || stop.load(Ordering::Relaxed) {
+            break;
+        }
+        let found = occurrences(matcher, line.bytes, args);
+        if found > 0 {
+            matching_lines += 1;
+            count += found;
+            if listing || args.quiet || !(args.binary || counting) {
+                break;
+            }
+        }
+    }
+    stats.matches += matching_lines;
+    stats.bytes += lines.position();
+    if args.quiet {
+        return Ok(());
+    }
+    if listing {
+        list_file(matching_lines > 0, args, printer)?;
+    } else if counting {
+        if count > 0 {
+            printer.on_count(count).map_err(OutputError)?;
+        }
+    } else if matching_lines > 0 {
+        let counted = if args.binary { Some(matching_lines) } else { None };
+        printer.on_binary(counted).map_err(OutputError)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use memchr::memmem::Finder;
+
+use std::io::{BufRead, Result};
+
+/// Splits a reader into lines without ever holding more than `max_length` bytes of one,
+/// unlike `BufRead::lines`, which buffers a whole line however long it gets.
+pub struct LineReader<'a> {
+    reader: &'a mut dyn BufRead,
+    // The byte ending each line: `\n`, or NUL for `--null-data`.
+    terminator: u8,
+    max_length: Option<usize>,
+    line: Vec<u8>,
+    // Number of bytes consumed so far, which is where the next line starts.
+    position: u64,
+}
+
+/// Totals of `LineReader::count`.
+#[derive(Default)]
+pub struct Counts {
+    pub lines: u64,
+    /// Lines containing the needle.
+    pub matching_lines: u64,
+    /// Non-overlapping occurrences of the needle, if they were asked for.
+    pub occurrences: u64,
+}
+
+/// A line as returned by `LineReader`, without its terminator.
+pub struct Line<'a> {
+    pub bytes: &'a [u8],
+    /// Offset of the first byte of the line in the input.
+    pub offset: u64,
+    /// Whether the line was longer than the maximum length and the rest was dropped.
+    pub truncated: bool,
+}
+
+impl<'a> LineReader<'a> {
+    pub fn new(reader: &'a mut dyn BufRead, terminator: u8, max_length: Option<usize>)
+        -> LineReader<'a>
+    {
+        LineReader { reader, terminator, max_length, line: Vec::new(), position: 0 }
+    }
+
+    /// Number of bytes read so far, terminators included.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Skip the lines before the next one containing `needle`, looking for it in whole
+    /// buffers rather than line by line, and return how many were skipped. Stops before a
+    /// line that does not end in the buffer, for `next_line` to read.
+    pub fn skip_to(&mut self, needle: &Finder) -> Result<u64> {
+        let mut skipped = 0;
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            let found = needle.find(buffer);
+            // Up to the start of the line with the needle, or of the last, unfinished line.
+            let searched = &buffer[..found.unwrap_or(buffer.len())];
+            let end = match memchr::memrchr(self.terminator, searched) {
+                Some(terminator) => terminator + 1,
+                None => return Ok(skipped),
+            };
+            skipped += memchr::memchr_iter(self.terminator, &buffer[..end]).count() as u64;
+            self.reader.consume(end);
+            self.position += end as u64;
+            if found.is_some() {
+                return Ok(skipped);
+            }
+        }
+    }
+
+    /// Count the lines of the next buffer and those containing `needle`, and with
+    /// `occurrences` the occurrences of `needle` as well, without splitting the buffer into
+    /// lines. `needle` must not be empty nor contain the
+    /// terminator, so that each occurrence is within a line. A line that does not end in the
+    /// buffer is read whole, like `next_line` does, so that occurrences straddling two
+    /// buffers are counted too. Returns false at the end of the input.
+    pub fn count(&mut self, needle: &Finder, occurrences: bool, counts: &mut Counts)
+        -> Result<bool>
+    {
+        let buffer = self.reader.fill_buf()?;
+        let end = match memchr::memrchr(self.terminator, buffer) {
+            Some(terminator) => terminator + 1,
+            None => {
+                let line = match self.next_line()? {
+                    Some(line) => line,
+                    None => return Ok(false),
+                };
+                let found = needle.find_iter(line.bytes).count() as u64;
+                counts.lines += 1;
+                counts.matching_lines += u64::from(found > 0);
+                counts.occurrences += found;
+                return Ok(true);
+            }
+        };
+        let buffer = &buffer[..end];
+        counts.lines += memchr::memchr_iter(self.terminator, buffer).count() as u64;
+        let length = needle.needle().len();
+        let mut position = 0;
+        while let Some(found) = needle.find(&buffer[position..]) {
+            position += found + length;
+            // The line ends within the buffer, which ends with a terminator.
+            let line_end = memchr::memchr(self.terminator, &buffer[position..])
+                .map_or(end, |terminator| position + terminator + 1);
+            counts.matching_lines += 1;
+            counts.occurrences += 1;
+            // Only the first occurrence counts for the line, unless `occurrences` is set.
+            if occurrences {
+                while let Some(found) = needle.find(&buffer[position..line_end]) {
+                    position += found + length;
+                    counts.occurrences += 1;
+                }
+            }
+            position = line_end;
+        }
+        self.reader.consume(end);
+        self.position += end as u64;
+        Ok(true)
+    }
+
+    /// Read the next line, or return `None` at the end of the input.
+    pub fn next_line(&mut self) -> Result<Option<Line<'_>>> {
+        self.line.clear();
+        let offset = self.position;
+        let mut truncated = false;
+        let mut terminated = false;
+        let mut found_any = false;
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            found_any = true;
+            let (end, consumed, done) = match memchr::memchr(self.terminator, buffer) {
+                Some(position) => (position, position + 1, true),
+                None => (buffer.len(), buffer.len(), false),
+            };
+            let length = self.line.len();
+            let room = self.max_length.map_or(end, |max| max.saturating_sub(length));
+            self.line.extend_from_slice(&buffer[..end.min(room)]);
+            truncated |= end > room;
+            self.reader.consume(consumed);
+            self.position += consumed as u64;
+            if done {
+                terminated = true;
+                break;
+            }
+        }
+        if !found_any {
+            return Ok(None);
+        }
+        // Like `BufRead::lines`, a `\r\n` terminator is removed as a whole.
+        let crlf = self.terminator == b'\n' && self.line.last() == Some(&b'\r');
+        if terminated && !truncated && crlf {
+            self.line.pop();
+        }
+        Ok(Some(Line { bytes: &self.line, offset, truncated }))
+    }
+}
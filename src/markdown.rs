@@ -0,0 +1,46 @@
+//! Matches as a Markdown report, for `--format markdown`: a section for each input with its
+//! lines in a fenced code block, ready to be pasted into an issue or a pull request.
+
+use std::io::{Result, Write};
+
+use crate::event;
+
+/// Start the section of the input at `path`: its name as a heading, then the code block.
+pub fn write_begin(writer: &mut impl Write, path: &str) -> Result<()> {
+    write_heading(writer, path)?;
+    writeln!(writer, "```")
+}
+
+/// Close the code block of the section.
+pub fn write_end(writer: &mut impl Write) -> Result<()> {
+    writeln!(writer, "```")
+}
+
+// Lines are prefixed with their number, so none of them can close the code block early.
+
+pub fn write_match(writer: &mut impl Write, event: &event::Match) -> Result<()> {
+    writeln!(writer, "{}: {}", event.line_number, event.text)
+}
+
+pub fn write_context(writer: &mut impl Write, event: &event::Context) -> Result<()> {
+    writeln!(writer, "{}- {}", event.line_number, event.text)
+}
+
+/// Write a section for a binary input, whose lines are not shown.
+pub fn write_binary(writer: &mut impl Write, path: &str, lines: Option<usize>) -> Result<()> {
+    write_heading(writer, path)?;
+    match lines {
+        Some(lines) => writeln!(writer, "Binary file matches ({} lines).", lines),
+        None => writeln!(writer, "Binary file matches."),
+    }
+}
+
+// Write `path` as a heading, in a code span so that Markdown in file names is left alone.
+fn write_heading(writer: &mut impl Write, path: &str) -> Result<()> {
+    // The span is delimited by more backticks than the longest run of them in the path.
+    let longest = path.split(|character| character != '`').map(str::len).max().unwrap_or(0);
+    let ticks = "`".repeat(longest + 1);
+    let padding = if path.starts_with('`') || path.ends_with('`') { " " } else { "" };
+    writeln!(writer, "### {}{}{}{}{}", ticks, padding, path, padding, ticks)?;
+    writeln!(writer)
+}
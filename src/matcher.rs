@@ -0,0 +1,422 @@
+use aho_corasick::{AhoCorasick, MatchKind};
+use anyhow::{anyhow, Context, Result as AnyhowResult};
+use memchr::memmem::Finder;
+use regex_syntax::hir::{Hir, HirKind};
+
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Formatter};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The byte range of an occurrence of the pattern in a line.
+pub type Span = Range<usize>;
+
+/// Finds the pattern in lines and reports where it occurs. Lines are bytes, which need not
+/// be valid UTF-8, so that they are only decoded when they are printed.
+///
+/// A search compiles it once, in `run`, and every file and worker thread borrows that one:
+/// whatever is derived from the patterns, like the automaton of many literals or the literal
+/// a regular expression requires, is worked out when it is built and never again per file.
+/// The worker threads of a parallel search share it, hence `Send` and `Sync`.
+pub trait Matcher: Send + Sync {
+    /// The leftmost occurrence of the pattern in `line`.
+    fn find(&self, line: &[u8]) -> Option<Span>;
+
+    /// Whether the pattern occurs in `line`.
+    fn is_match(&self, line: &[u8]) -> bool {
+        self.find(line).is_some()
+    }
+
+    /// The non-overlapping occurrences of the pattern in `line`, from left to right. An
+    /// empty occurrence is only found once, at the start of the line.
+    fn find_iter<'a>(&'a self, line: &'a [u8]) -> Box<dyn Iterator<Item = Span> + 'a> {
+        let mut position = 0;
+        Box::new(std::iter::from_fn(move || {
+            let found = self.find(line.get(position..)?)?;
+            let span = position + found.start..position + found.end;
+            position = if span.is_empty() { line.len() + 1 } else { span.end };
+            Some(span)
+        }))
+    }
+
+    /// Number of non-overlapping occurrences of the pattern in `line`.
+    fn count(&self, line: &[u8]) -> usize {
+        self.find_iter(line).count()
+    }
+
+    /// The capture groups of every occurrence of the pattern in `line`, in the order of
+    /// `find_iter`. Without groups, only the whole occurrence.
+    fn captures(&self, line: &[u8]) -> Vec<Groups> {
+        self.find_iter(line).map(|span| vec![Some(span)]).collect()
+    }
+
+    /// Number of capture groups, the whole occurrence included.
+    fn group_count(&self) -> usize {
+        1
+    }
+
+    /// The index of the capture group called `name`, if there is one.
+    fn group_index(&self, _name: &str) -> Option<usize> {
+        None
+    }
+
+    /// A literal every occurrence of the pattern contains, to look for in whole buffers
+    /// before splitting them into lines: the pattern itself, or a literal that a regular
+    /// expression requires, like `ERROR` in `ERROR.*timeout`. `None` for several patterns
+    /// and for regular expressions without one.
+    fn literal(&self) -> Option<&Finder<'static>> {
+        None
+    }
+
+    /// The pattern itself when it is a single literal, whose occurrences can be counted
+    /// without looking at anything else. `None` for every other pattern.
+    fn as_literal(&self) -> Option<&Finder<'static>> {
+        None
+    }
+}
+
+/// How several literal patterns are searched for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// An Aho-Corasick automaton from `AHO_CORASICK_MIN_PATTERNS` patterns on, each pattern
+    /// in turn below that.
+    Auto,
+    /// Always an Aho-Corasick automaton.
+    AhoCorasick,
+    /// Always each pattern in turn.
+    EachPattern,
+}
+
+/// From how many literal patterns on `Strategy::Auto` builds an Aho-Corasick automaton.
+/// Below that, searching for each of them with SIMD instructions is as fast.
+pub const AHO_CORASICK_MIN_PATTERNS: usize = 8;
+
+/// The byte ranges of the capture groups of one occurrence of the pattern, the whole
+/// occurrence first. Groups that did not take part in the match are `None`.
+pub type Groups = Vec<Option<Range<usize>>>;
+
+/// A matcher for the literal `pattern`.
+pub fn literal(pattern: &str) -> Box<dyn Matcher> {
+    Box::new(Literal(Finder::new(pattern).into_owned()))
+}
+
+/// A matcher for the occurrences of any of the literal `patterns`. Where several of them
+/// occur at the same place, the first one in `patterns` wins. A single pattern is always
+/// searched for on its own, and an empty one matches every line, as it does on its own.
+pub fn literals(patterns: &[String], strategy: Strategy) -> AnyhowResult<Box<dyn Matcher>> {
+    if let [pattern] = patterns {
+        return Ok(literal(pattern));
+    }
+    if patterns.iter().any(String::is_empty) {
+        return Ok(literal(""));
+    }
+    let automaton = match strategy {
+        Strategy::Auto => patterns.len() >= AHO_CORASICK_MIN_PATTERNS,
+        Strategy::AhoCorasick => true,
+        Strategy::EachPattern => false,
+    };
+    if automaton {
+        let automaton = AhoCorasick::builder()
+            .match_kind(MatchKind::LeftmostFirst)
+            .build(patterns)
+            .context("Could not build the automaton for the patterns")?;
+        Ok(Box::new(Automaton(automaton)))
+    } else {
+        Ok(Box::new(Literals(patterns.iter()
+            .map(|pattern| Finder::new(pattern).into_owned())
+            .collect())))
+    }
+}
+
+/// A matcher for the regular expression `pattern`, in the syntax of the `regex` crate.
+pub fn regex(pattern: &str) -> AnyhowResult<Box<dyn Matcher>> {
+    let regex = regex::bytes::Regex::new(pattern)
+        .with_context(|| format!("Invalid regular expression: {:?}", pattern))?;
+    Ok(Box::new(Regex::new(regex)))
+}
+
+/// A matcher for the occurrences of any of the regular expressions `patterns`, which are
+/// joined into one. No pattern matches nothing.
+pub fn regexes(patterns: &[String]) -> AnyhowResult<Box<dyn Matcher>> {
+    match patterns {
+        [] => Ok(Box::new(Literals(Vec::new()))),
+        [pattern] => regex(pattern),
+        patterns => {
+            let joined = patterns.iter()
+                .map(|pattern| format!("(?:{})", pattern))
+                .collect::<Vec<_>>()
+                .join("|");
+            match regex::bytes::Regex::new(&joined) {
+                Ok(regex) => Ok(Box::new(Regex::new(regex))),
+                // Only look for the culprit once something is wrong, to name it.
+                Err(err) => {
+                    match patterns.iter().find(|pattern| regex::bytes::Regex::new(pattern).is_err())
+                    {
+                        Some(pattern) => regex(pattern),
+                        None => Err(anyhow!(err).context("Invalid regular expressions")),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A literal, searched for with SIMD instructions where the CPU has them.
+struct Literal(Finder<'static>);
+
+impl Matcher for Literal {
+    fn find(&self, line: &[u8]) -> Option<Span> {
+        let start = self.0.find(line)?;
+        Some(start..start + self.0.needle().len())
+    }
+
+    fn find_iter<'a>(&'a self, line: &'a [u8]) -> Box<dyn Iterator<Item = Span> + 'a> {
+        let length = self.0.needle().len();
+        Box::new(self.0.find_iter(line)
+            .map(move |start| start..start + length)
+            .take(if length == 0 { 1 } else { usize::MAX }))
+    }
+
+    fn literal(&self) -> Option<&Finder<'static>> {
+        Some(&self.0)
+    }
+
+    fn as_literal(&self) -> Option<&Finder<'static>> {
+        Some(&self.0)
+    }
+}
+
+/// A few literals, each searched for in turn.
+struct Literals(Vec<Finder<'static>>);
+
+impl Matcher for Literals {
+    fn find(&self, line: &[u8]) -> Option<Span> {
+        // The leftmost occurrence, of the first pattern among those starting there.
+        self.0.iter()
+            .filter_map(|finder| {
+                finder.find(line).map(|start| start..start + finder.needle().len())
+            })
+            .min_by_key(|span| span.start)
+    }
+
+    fn is_match(&self, line: &[u8]) -> bool {
+        self.0.iter().any(|finder| finder.find(line).is_some())
+    }
+}
+
+/// Many literals, searched for all at once in a single pass over the line.
+struct Automaton(AhoCorasick);
+
+impl Matcher for Automaton {
+    fn find(&self, line: &[u8]) -> Option<Span> {
+        self.0.find(line).map(|found| found.range())
+    }
+
+    fn is_match(&self, line: &[u8]) -> bool {
+        self.0.is_match(line)
+    }
+
+    fn find_iter<'a>(&'a self, line: &'a [u8]) -> Box<dyn Iterator<Item = Span> + 'a> {
+        Box::new(self.0.find_iter(line).map(|found| found.range()))
+    }
+}
+
+/// A regular expression, matched on bytes so that lines need not be decoded, which finds
+/// the same as on the text of valid UTF-8 lines.
+struct Regex {
+    regex: regex::bytes::Regex,
+    // A literal every match contains, if there is one, to rule lines out before running the
+    // regular expression on them.
+    required: Option<Finder<'static>>,
+}
+
+impl Regex {
+    fn new(regex: regex::bytes::Regex) -> Regex {
+        // The regular expression was just compiled from the same syntax, it parses.
+        let required = regex_syntax::parse(regex.as_str()).ok()
+            .and_then(|hir| required_literal(&hir))
+            .map(|literal| Finder::new(&literal).into_owned());
+        Regex { regex, required }
+    }
+}
+
+impl Matcher for Regex {
+    fn find(&self, line: &[u8]) -> Option<Span> {
+        self.regex.find(line).map(|found| found.range())
+    }
+
+    fn is_match(&self, line: &[u8]) -> bool {
+        self.required.as_ref().is_none_or(|required| required.find(line).is_some())
+            && self.regex.is_match(line)
+    }
+
+    fn find_iter<'a>(&'a self, line: &'a [u8]) -> Box<dyn Iterator<Item = Span> + 'a> {
+        Box::new(self.regex.find_iter(line).map(|found| found.range()))
+    }
+
+    fn captures(&self, line: &[u8]) -> Vec<Groups> {
+        self.regex.captures_iter(line)
+            .map(|captures| captures.iter().map(|group| group.map(|group| group.range()))
+                .collect())
+            .collect()
+    }
+
+    fn group_count(&self) -> usize {
+        self.regex.captures_len()
+    }
+
+    fn group_index(&self, name: &str) -> Option<usize> {
+        self.regex.capture_names().position(|group| group == Some(name))
+    }
+
+    fn literal(&self) -> Option<&Finder<'static>> {
+        self.required.as_ref()
+    }
+}
+
+// The longest literal that every match of `hir` contains, if there is one. Only literals
+// on the way down through concatenations, groups and repetitions that happen at least once
+// are sure to be in every match: alternatives, classes and optional parts may not be.
+fn required_literal(hir: &Hir) -> Option<Vec<u8>> {
+    match hir.kind() {
+        HirKind::Literal(literal) => Some(literal.0.to_vec()),
+        HirKind::Capture(capture) => required_literal(&capture.sub),
+        HirKind::Repetition(repetition) if repetition.min > 0 => {
+            required_literal(&repetition.sub)
+        }
+        HirKind::Concat(parts) => {
+            parts.iter().filter_map(required_literal).max_by_key(|literal| literal.len())
+        }
+        _ => None,
+    }
+}
+
+/// What a matcher is compiled from: two searches with the same key can share one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Key {
+    pub patterns: Vec<String>,
+    pub regex: bool,
+    pub strategy: Strategy,
+}
+
+/// The matchers compiled last, for a long-lived process whose searches often look for the
+/// same patterns again. Once it is full, the least recently used one is dropped.
+pub struct Cache {
+    capacity: usize,
+    // Most recently used last.
+    entries: Mutex<VecDeque<(Key, Arc<dyn Matcher>)>>,
+    hits: AtomicUsize,
+}
+
+impl Cache {
+    // Only `--stdio-server` keeps matchers.
+    #[cfg_attr(not(feature = "json"), allow(dead_code))]
+    pub fn new(capacity: usize) -> Cache {
+        Cache { capacity, entries: Mutex::default(), hits: Default::default() }
+    }
+
+    /// The matcher of `key`, compiled by `compile` unless it is cached. Compiling does not
+    /// hold the cache, so that other searches can use it meanwhile.
+    pub fn get_or_compile(&self, key: Key, compile: impl FnOnce() -> AnyhowResult<Box<dyn Matcher>>)
+        -> AnyhowResult<Arc<dyn Matcher>>
+    {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(index) = entries.iter().position(|(cached, _)| *cached == key) {
+                let entry = entries.remove(index).unwrap();
+                let matcher = entry.1.clone();
+                entries.push_back(entry);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(matcher);
+            }
+        }
+        let matcher: Arc<dyn Matcher> = compile()?.into();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back((key, matcher.clone()));
+        Ok(matcher)
+    }
+
+    /// How many matchers were taken from the cache rather than compiled.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+impl Debug for Cache {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.debug_list()
+            .entries(self.entries.lock().unwrap().iter().map(|(key, _)| key))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    fn spans(matcher: &dyn Matcher, line: &str) -> Vec<(usize, usize)> {
+        matcher.find_iter(line.as_bytes()).map(|span| (span.start, span.end)).collect()
+    }
+
+    #[test]
+    fn every_engine_finds_the_same_occurrences() {
+        let line = "an apple, a banana and an apricot";
+        let words = patterns(&["an", "apple", "a"]);
+        let expected = spans(&*regexes(&words).unwrap(), line);
+        assert_eq!(expected.len(), 9);
+        for strategy in [Strategy::AhoCorasick, Strategy::EachPattern] {
+            let matcher = literals(&words, strategy).unwrap();
+            assert_eq!(spans(&*matcher, line), expected, "{:?}", strategy);
+            assert_eq!(matcher.count(line.as_bytes()), expected.len());
+        }
+    }
+
+    #[test]
+    fn empty_literals_match_once() {
+        assert_eq!(spans(&*literal(""), "abc"), [(0, 0)]);
+        let matcher = literals(&patterns(&["x", ""]), Strategy::Auto).unwrap();
+        assert_eq!(spans(&*matcher, "x"), [(0, 0)]);
+    }
+
+    #[test]
+    fn regular_expressions_see_the_whole_line() {
+        let matcher = regex(r"^\w+|\bb").unwrap();
+        assert_eq!(spans(&*matcher, "ab b"), [(0, 2), (3, 4)]);
+        assert_eq!(matcher.captures(b"ab").len(), 1);
+        assert!(regexes(&[]).unwrap().find(b"anything").is_none());
+    }
+
+    #[test]
+    fn the_cache_drops_the_least_recently_used_matcher() {
+        let cache = Cache::new(2);
+        let key = |pattern: &str| Key {
+            patterns: patterns(&[pattern]),
+            regex: false,
+            strategy: Strategy::Auto,
+        };
+        let compiled = std::cell::Cell::new(0);
+        let get = |pattern: &str| {
+            cache.get_or_compile(key(pattern), || {
+                compiled.set(compiled.get() + 1);
+                Ok(literal(pattern))
+            }).unwrap()
+        };
+        assert_eq!(spans(&*get("a"), "ba"), [(1, 2)]);
+        get("b");
+        get("a");
+        get("c");
+        assert_eq!((compiled.get(), cache.hits()), (3, 1));
+        // "b" was dropped for "c", "a" was used since.
+        get("a");
+        get("b");
+        assert_eq!((compiled.get(), cache.hits()), (4, 2));
+    }
+}
@@ -0,0 +1,68 @@
+use memmap2::Mmap;
+
+use std::fs::File;
+
+/// Whether files are searched through memory maps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Map the files where it is expected to be faster, see `map`.
+    Auto,
+    /// Map every file that can be mapped.
+    Always,
+    /// Read every file through a buffer.
+    Never,
+}
+
+// From this size on, a file is mapped in `Mode::Auto`. Below it, setting up and tearing down
+// the mapping costs more than the copies into the read buffer that it saves.
+const MIN_AUTO_SIZE: u64 = 4 << 20;
+
+/// Map `file` into memory, or return `None` when it has to be read the usual way instead.
+/// In `Mode::Auto`, only large files on local filesystems are mapped.
+pub fn map(file: &File, mode: Mode) -> Option<Mmap> {
+    let metadata = file.metadata().ok()?;
+    // Pipes, sockets and devices cannot be mapped. Files on virtual filesystems like /proc
+    // report a size of 0 even though reading them yields data, so mapping them shows nothing.
+    if !metadata.is_file() || metadata.len() == 0 {
+        return None;
+    }
+    match mode {
+        Mode::Never => return None,
+        Mode::Auto if metadata.len() < MIN_AUTO_SIZE || is_remote(file) => return None,
+        Mode::Auto | Mode::Always => {}
+    }
+    // SAFETY: the mapping is only read. If another process truncates the file while it is
+    // being searched, reading past the new end raises SIGBUS, the same risk every mmap-based
+    // search tool accepts for the speedup. Any other failure falls back to buffered reads.
+    unsafe { Mmap::map(file) }.ok()
+}
+
+/// Whether `file` is on a network or FUSE filesystem, where every page fault of a mapping
+/// is a round trip, and where the file changing on another machine makes the mapping fault.
+#[cfg(target_os = "linux")]
+pub fn is_remote(file: &File) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    const NFS: u32 = 0x6969;
+    const SMB: u32 = 0x517b;
+    const CIFS: u32 = 0xff53_4d42;
+    const SMB2: u32 = 0xfe53_4d42;
+    const FUSE: u32 = 0x6573_5546;
+    const V9FS: u32 = 0x0102_1997;
+
+    let mut stats = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: `fstatfs` fills `stats` in when it succeeds, and it is only read then.
+    let stats = unsafe {
+        if libc::fstatfs(file.as_raw_fd(), stats.as_mut_ptr()) != 0 {
+            return false;
+        }
+        stats.assume_init()
+    };
+    // The type of `f_type` depends on the architecture, but the magic numbers fit in 32 bits.
+    matches!(stats.f_type as u32, NFS | SMB | CIFS | SMB2 | FUSE | V9FS)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_remote(_file: &File) -> bool {
+    false
+}
@@ -0,0 +1,24 @@
+use anyhow::Result as AnyhowResult;
+
+use std::io::BufRead;
+use std::path::Path;
+
+/// Whether `path` is an `http://` or `https://` URL rather than a local path.
+pub fn is_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// Request `url` and return its body as it arrives, without downloading it first.
+#[cfg(feature = "net")]
+pub fn open(url: &str) -> AnyhowResult<Box<dyn BufRead>> {
+    use anyhow::Context;
+    use std::io::BufReader;
+
+    let response = ureq::get(url).call().with_context(|| format!("Could not fetch {}", url))?;
+    Ok(Box::new(BufReader::new(response.into_reader())))
+}
+
+#[cfg(not(feature = "net"))]
+pub fn open(url: &str) -> AnyhowResult<Box<dyn BufRead>> {
+    Err(anyhow::anyhow!("Cannot fetch {}: searching URLs needs the `net` feature", url))
+}
@@ -0,0 +1,1038 @@
+//! Central resolution of flags that conflict with each other or make no sense together.
+//!
+//! Plain "only one of these" conflicts are declared on `Cli` itself. The rules here cover
+//! combinations that parse fine but would otherwise silently produce surprising output.
+//! They are applied in order and each one either rejects the combination, drops the flag
+//! that loses with a warning, or keeps both flags and warns about how they interact.
+
+use anyhow::{anyhow, Result as AnyhowResult};
+
+use crate::printer::Format;
+use crate::Cli;
+
+// What happens when a rule applies.
+enum Resolution {
+    // The combination is an error.
+    Reject(&'static str),
+    // The losing flag is reset by the function and the message is shown as a warning.
+    Ignore(&'static str, fn(&mut Cli)),
+    // Both flags are kept and the message is shown as a warning.
+    Warn(&'static str),
+}
+
+struct Rule {
+    applies: fn(&Cli) -> bool,
+    resolution: Resolution,
+}
+
+fn has_file_list(args: &Cli) -> bool {
+    args.files_from.is_some() || args.files_from0.is_some()
+}
+
+fn is_url(args: &Cli) -> bool {
+    args.path.as_deref().is_some_and(crate::net::is_url)
+}
+
+// Whether the results are printed as records rather than text.
+fn is_structured(args: &Cli) -> bool {
+    args.format() != Format::Text
+}
+
+// Whether the format has nothing but the matches: no context lines.
+fn is_matches_only(args: &Cli) -> bool {
+    matches!(args.format(), Format::Table(_) | Format::Template(_) | Format::Sarif)
+}
+
+// Whether any option only affecting how directories are walked is set.
+fn has_traversal_flags(args: &Cli) -> bool {
+    args.max_depth.is_some() || !args.types.is_empty() || !args.types_not.is_empty()
+        || !args.type_add.is_empty() || !args.include.is_empty() || !args.exclude.is_empty()
+        || args.max_filesize.is_some() || args.git_tracked || args.hidden || args.follow
+        || args.include_special || args.sort != crate::Sort::None
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        applies: |args| {
+            !cfg!(feature = "json")
+                && (args.json || matches!(args.format(), Format::Json | Format::Sarif)
+                    || args.stdio_server || args.json_schema || args.journal.is_some())
+        },
+        resolution: Resolution::Reject(
+            "--json, --json-schema, --format json, --format sarif, --stdio-server and --journal \
+             need the `json` feature, which this build was made without"),
+    },
+    Rule {
+        applies: |args| {
+            args.webhook.is_some() && !(cfg!(feature = "net") && cfg!(feature = "json"))
+        },
+        resolution: Resolution::Reject(
+            "--webhook needs the `net` and `json` features, which this build was made without"),
+    },
+    Rule {
+        applies: |args| !cfg!(feature = "compression") && (args.search_zip || args.archive),
+        resolution: Resolution::Reject(
+            "-z/--search-zip and --archive need the `compression` feature, which this build \
+             was made without"),
+    },
+    Rule {
+        applies: |args| !cfg!(feature = "async") && args.async_mode,
+        resolution: Resolution::Reject(
+            "--async needs the `async` feature, which this build was made without"),
+    },
+    Rule {
+        applies: |args| {
+            args.async_mode
+                && (args.git_log.is_some() || args.estimate || args.bench.is_some()
+                    || args.stdio_server)
+        },
+        resolution: Resolution::Reject(
+            "--async reads files, FIFOs and URLs and cannot be used with --git-log, --estimate, \
+             --bench or --stdio-server"),
+    },
+    Rule {
+        applies: |args| {
+            args.async_mode
+                && (args.count || args.count_matches || args.files_with_matches
+                    || args.files_without_match || args.context().is_some() || args.heading
+                    || args.sort_results.is_some() || args.unique().is_some()
+                    || is_structured(args))
+        },
+        resolution: Resolution::Ignore(
+            "-c, --count-matches, -l, -L, -A, -B, -C, --heading, --sort-results, --unique, \
+             --unique-counts, --json and --format are ignored with --async, which prints each \
+             matching line as soon as it is read",
+            |args| {
+                args.count = false;
+                args.count_matches = false;
+                args.files_with_matches = false;
+                args.files_without_match = false;
+                args.after_context = None;
+                args.before_context = None;
+                args.context = None;
+                args.heading = false;
+                args.sort_results = None;
+                args.unique = false;
+                args.unique_counts = false;
+                args.json = false;
+                args.format = None;
+            }),
+    },
+    Rule {
+        applies: |args| {
+            args.async_mode
+                && (args.search_zip || args.archive || args.mmap || args.no_mmap
+                    || matches!(args.encoding, crate::encoding::Encoding::Label(_))
+                    || args.max_line_length.is_some())
+        },
+        resolution: Resolution::Ignore(
+            "-z, --archive, --mmap, --no-mmap, --encoding, --max-line-length and \
+             --skip-long-lines are ignored with --async, which reads inputs as plain text",
+            |args| {
+                args.search_zip = false;
+                args.archive = false;
+                args.mmap = false;
+                args.no_mmap = false;
+                args.encoding = crate::encoding::Encoding::Auto;
+                args.max_line_length = None;
+                args.skip_long_lines = false;
+            }),
+    },
+    Rule {
+        applies: |args| {
+            args.indexed
+                && (has_file_list(args) || args.git_log.is_some() || args.stdio_server
+                    || is_url(args))
+        },
+        resolution: Resolution::Reject(
+            "`index search` searches the indexed directory given as --path and cannot be used \
+             with --files-from, --git-log, --stdio-server or a URL"),
+    },
+    Rule {
+        applies: |args| {
+            args.stdio_server
+                && (args.pattern.is_some() || !args.regexp.is_empty() || args.file.is_some()
+                    || args.path.is_some())
+        },
+        resolution: Resolution::Reject(
+            "--stdio-server takes the pattern and paths from its requests, not the command line"),
+    },
+    Rule {
+        applies: |args| {
+            args.json_schema
+                && (args.pattern.is_some() || !args.regexp.is_empty() || args.file.is_some()
+                    || args.path.is_some() || has_file_list(args) || args.stdio_server)
+        },
+        resolution: Resolution::Reject(
+            "--json-schema prints the schema of --json and exits, it does not search"),
+    },
+    Rule {
+        applies: |args| args.stdio_server && has_file_list(args),
+        resolution: Resolution::Reject(
+            "--stdio-server reads requests from stdin and cannot be used with --files-from"),
+    },
+    Rule {
+        applies: |args| args.stdio_server && args.output.is_some(),
+        resolution: Resolution::Reject(
+            "--stdio-server answers on stdout and cannot be used with --output"),
+    },
+    Rule {
+        applies: |args| args.max_depth.is_some() && has_file_list(args),
+        resolution: Resolution::Reject(
+            "--max-depth cannot be used with --files-from: \
+             the listed files are not descended into"),
+    },
+    Rule {
+        applies: |args| {
+            has_file_list(args)
+                && !(args.types.is_empty() && args.types_not.is_empty() && args.type_add.is_empty())
+        },
+        resolution: Resolution::Ignore(
+            "--type, --type-not and --type-add are ignored with --files-from: \
+             listed files are always searched",
+            |args| {
+                args.types.clear();
+                args.types_not.clear();
+                args.type_add.clear();
+            }),
+    },
+    Rule {
+        applies: |args| {
+            has_file_list(args) && !(args.include.is_empty() && args.exclude.is_empty())
+        },
+        resolution: Resolution::Ignore(
+            "--include and --exclude are ignored with --files-from: \
+             listed files are always searched",
+            |args| {
+                args.include.clear();
+                args.exclude.clear();
+            }),
+    },
+    Rule {
+        applies: |args| has_file_list(args) && args.max_filesize.is_some(),
+        resolution: Resolution::Ignore(
+            "--max-filesize is ignored with --files-from: listed files are always searched",
+            |args| args.max_filesize = None),
+    },
+    Rule {
+        applies: |args| has_file_list(args) && args.git_tracked,
+        resolution: Resolution::Ignore(
+            "--git-tracked is ignored with --files-from: listed files are always searched",
+            |args| args.git_tracked = false),
+    },
+    Rule {
+        applies: |args| has_file_list(args) && args.include_special,
+        resolution: Resolution::Ignore(
+            "--include-special is ignored with --files-from: listed files are always searched",
+            |args| args.include_special = false),
+    },
+    Rule {
+        applies: |args| args.git_log.is_some() && has_file_list(args),
+        resolution: Resolution::Reject(
+            "--git-log searches the history of --path and cannot be used with --files-from"),
+    },
+    Rule {
+        applies: |args| args.git_log.is_some() && args.estimate,
+        resolution: Resolution::Reject(
+            "--estimate measures the files on disk and cannot be used with --git-log"),
+    },
+    Rule {
+        applies: |args| args.git_log.is_some() && has_traversal_flags(args),
+        resolution: Resolution::Ignore(
+            "--max-depth, --type, --type-not, --type-add, --include, --exclude, --max-filesize, \
+             --git-tracked, --hidden, --follow, --include-special and --sort are ignored with \
+             --git-log: every committed file is searched, newest commit first",
+            |args| {
+                args.max_depth = None;
+                args.types.clear();
+                args.types_not.clear();
+                args.type_add.clear();
+                args.include.clear();
+                args.exclude.clear();
+                args.max_filesize = None;
+                args.git_tracked = false;
+                args.hidden = false;
+                args.follow = false;
+                args.include_special = false;
+                args.sort = crate::Sort::None;
+            }),
+    },
+    Rule {
+        applies: |args| args.git_log.is_some() && (args.archive || args.mmap || args.no_mmap),
+        resolution: Resolution::Ignore(
+            "--archive, --mmap and --no-mmap are ignored with --git-log, which reads file \
+             versions from git",
+            |args| {
+                args.archive = false;
+                args.mmap = false;
+                args.no_mmap = false;
+            }),
+    },
+    Rule {
+        applies: |args| !args.archive && !args.archive_glob.is_empty(),
+        resolution: Resolution::Ignore(
+            "--archive-glob is ignored without --archive, which searches archives member by \
+             member",
+            |args| args.archive_glob.clear()),
+    },
+    Rule {
+        applies: |args| !args.archive && args.archive_depth > 0,
+        resolution: Resolution::Ignore(
+            "--archive-depth is ignored without --archive, which searches archives member by \
+             member",
+            |args| args.archive_depth = 0),
+    },
+    Rule {
+        applies: |args| is_url(args) && (args.git_log.is_some() || args.estimate),
+        resolution: Resolution::Reject(
+            "--git-log and --estimate need a local path, not a URL"),
+    },
+    Rule {
+        applies: |args| {
+            (args.files_with_matches || args.files_without_match)
+                && (args.count || args.count_matches || args.only_matching || args.line_number
+                    || args.column || args.byte_offset)
+        },
+        resolution: Resolution::Ignore(
+            "-c, --count-matches, -o, -n, --column and -b are ignored with -l and -L, \
+             which only print file names",
+            |args| {
+                args.count = false;
+                args.count_matches = false;
+                args.only_matching = false;
+                args.line_number = false;
+                args.column = false;
+                args.byte_offset = false;
+            }),
+    },
+    Rule {
+        applies: |args| {
+            (args.count || args.count_matches)
+                && (args.only_matching || args.line_number || args.column || args.byte_offset)
+        },
+        resolution: Resolution::Ignore(
+            "-o, -n, --column and -b are ignored with --count and --count-matches, \
+             which print no lines",
+            |args| {
+                args.only_matching = false;
+                args.line_number = false;
+                args.column = false;
+                args.byte_offset = false;
+            }),
+    },
+    Rule {
+        applies: |args| {
+            args.unique().is_some()
+                && (args.count || args.count_matches || args.files_with_matches
+                    || args.files_without_match || args.vimgrep || is_structured(args))
+        },
+        resolution: Resolution::Ignore(
+            "--unique and --unique-counts are ignored with -c, --count-matches, -l, -L, \
+             --vimgrep, --json and --format",
+            |args| {
+                args.unique = false;
+                args.unique_counts = false;
+            }),
+    },
+    Rule {
+        applies: |args| {
+            args.sort_results.is_some()
+                && (args.count || args.count_matches || args.files_with_matches
+                    || args.files_without_match || args.unique().is_some()
+                    || args.format() == Format::Json)
+        },
+        resolution: Resolution::Ignore(
+            "--sort-results is ignored with -c, --count-matches, -l, -L, --unique, \
+             --unique-counts and --json, which do not print matching lines one by one",
+            |args| args.sort_results = None),
+    },
+    Rule {
+        applies: |args| args.sort_results.is_some() && args.context().is_some(),
+        resolution: Resolution::Ignore(
+            "-A, -B and -C are ignored with --sort-results, which separates lines from their \
+             context",
+            |args| {
+                args.after_context = None;
+                args.before_context = None;
+                args.context = None;
+            }),
+    },
+    Rule {
+        applies: |args| {
+            args.unique().is_some() && (args.line_number || args.column || args.byte_offset)
+        },
+        resolution: Resolution::Ignore(
+            "-n, --column and -b are ignored with --unique and --unique-counts, which print \
+             every occurrence once whatever line it is on",
+            |args| {
+                args.line_number = false;
+                args.column = false;
+                args.byte_offset = false;
+            }),
+    },
+    Rule {
+        applies: |args| {
+            args.context().is_some()
+                && (args.only_matching || args.unique().is_some() || args.count
+                    || args.count_matches || args.files_with_matches || args.files_without_match)
+        },
+        resolution: Resolution::Ignore(
+            "-A, -B and -C are ignored with -o, --unique, -c, --count-matches, -l and -L, \
+             which print no whole lines",
+            |args| {
+                args.after_context = None;
+                args.before_context = None;
+                args.context = None;
+            }),
+    },
+    Rule {
+        applies: |args| {
+            args.vimgrep
+                && (args.count || args.count_matches || args.files_with_matches
+                    || args.files_without_match || args.no_filename || args.heading
+                    || args.byte_offset || args.context().is_some())
+        },
+        resolution: Resolution::Ignore(
+            "-c, --count-matches, -l, -L, -h, --heading, -b, -A, -B and -C are ignored with \
+             --vimgrep, which prints file:line:column:text for each match",
+            |args| {
+                args.count = false;
+                args.count_matches = false;
+                args.files_with_matches = false;
+                args.files_without_match = false;
+                args.no_filename = false;
+                args.heading = false;
+                args.byte_offset = false;
+                args.after_context = None;
+                args.before_context = None;
+                args.context = None;
+            }),
+    },
+    Rule {
+        applies: |args| {
+            is_structured(args)
+                && (args.count || args.count_matches || args.files_with_matches
+                    || args.files_without_match || args.only_matching)
+        },
+        resolution: Resolution::Ignore(
+            "-c, --count-matches, -l, -L and -o are ignored with --json and --format, \
+             which report every match with its occurrences",
+            |args| {
+                args.count = false;
+                args.count_matches = false;
+                args.files_with_matches = false;
+                args.files_without_match = false;
+                args.only_matching = false;
+            }),
+    },
+    Rule {
+        applies: |args| is_matches_only(args) && args.context().is_some(),
+        resolution: Resolution::Ignore(
+            "-A, -B and -C are ignored with --format csv, tsv, sarif and templates, which only \
+             have matches",
+            |args| {
+                args.after_context = None;
+                args.before_context = None;
+                args.context = None;
+            }),
+    },
+    Rule {
+        applies: |args| args.estimate && is_structured(args),
+        resolution: Resolution::Ignore(
+            "--json and --format are ignored with --estimate, which prints a report instead \
+             of matches",
+            |args| {
+                args.json = false;
+                args.format = None;
+            }),
+    },
+    Rule {
+        applies: |args| {
+            is_structured(args)
+                && (args.color == crate::color::ColorChoice::Always || !args.colors.is_empty())
+        },
+        resolution: Resolution::Ignore(
+            "--color and --colors are ignored with --json and --format, which are never colored",
+            |args| {
+                args.color = crate::color::ColorChoice::Auto;
+                args.colors.clear();
+            }),
+    },
+    Rule {
+        applies: |args| args.bench.is_some() && (args.estimate || args.stdio_server),
+        resolution: Resolution::Reject(
+            "--bench runs searches and cannot be used with --estimate or --stdio-server"),
+    },
+    Rule {
+        applies: |args| {
+            args.files && (args.pattern.is_some() || !args.regexp.is_empty() || args.file.is_some())
+        },
+        resolution: Resolution::Reject(
+            "--files lists the files that would be searched and takes no pattern"),
+    },
+    Rule {
+        applies: |args| {
+            args.files
+                && (args.estimate || args.bench.is_some() || args.stdio_server
+                    || args.git_log.is_some() || is_url(args))
+        },
+        resolution: Resolution::Reject(
+            "--files lists the files below --path and cannot be used with --estimate, --bench, \
+             --stdio-server, --git-log or a URL"),
+    },
+    Rule {
+        applies: |args| args.bench.is_some() && (args.output.is_some() || args.pager),
+        resolution: Resolution::Ignore(
+            "--output and --pager are ignored with --bench, which throws the results away",
+            |args| {
+                args.output = None;
+                args.pager = false;
+            }),
+    },
+    Rule {
+        applies: |args| args.estimate && (args.search_zip || args.archive),
+        resolution: Resolution::Warn(
+            "--estimate measures files as stored on disk, compressed files and archives are \
+             not unpacked"),
+    },
+    Rule {
+        applies: |args| args.no_order && args.sort != crate::Sort::None,
+        resolution: Resolution::Ignore(
+            "--no-order is ignored with --sort: results are printed in the order asked for",
+            |args| args.no_order = false),
+    },
+    Rule {
+        applies: |args| args.estimate && (args.text || args.binary),
+        resolution: Resolution::Ignore(
+            "--text and --binary are ignored with --estimate, which does not print matches",
+            |args| {
+                args.text = false;
+                args.binary = false;
+            }),
+    },
+    Rule {
+        applies: |args| args.regex && (args.aho_corasick || args.no_aho_corasick),
+        resolution: Resolution::Ignore(
+            "--aho-corasick and --no-aho-corasick are ignored with --regex: they only choose \
+             how literal patterns are searched for",
+            |args| {
+                args.aho_corasick = false;
+                args.no_aho_corasick = false;
+            }),
+    },
+];
+
+/// Check `args` against every rule, resetting the flags that lose a conflict.
+/// Returns the warnings to show the user, or an error for the first rejected combination.
+pub fn resolve(args: &mut Cli) -> AnyhowResult<Vec<String>> {
+    let mut warnings = Vec::new();
+    for rule in RULES {
+        if !(rule.applies)(args) {
+            continue;
+        }
+        match &rule.resolution {
+            Resolution::Reject(message) => return Err(anyhow!(*message)),
+            Resolution::Ignore(message, reset) => {
+                reset(args);
+                warnings.push(message.to_string());
+            }
+            Resolution::Warn(message) => warnings.push(message.to_string()),
+        }
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use structopt::StructOpt;
+
+    fn resolve_args(args: &[&str]) -> AnyhowResult<(Cli, Vec<String>)> {
+        let mut cli = Cli::from_iter_safe(std::iter::once("grrs").chain(args.iter().cloned()))
+            .expect("test arguments parse");
+        let warnings = resolve(&mut cli)?;
+        Ok((cli, warnings))
+    }
+
+    fn rejected(args: &[&str]) -> String {
+        match resolve_args(args) {
+            Ok(_) => panic!("{:?} was not rejected", args),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn plain_search_passes() {
+        let (_, warnings) = resolve_args(&["foo", "-p", "."]).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn every_filter_with_a_path_passes() {
+        let (cli, warnings) = resolve_args(&[
+            "foo", "-p", ".", "--max-depth", "2", "-t", "rust", "-T", "md", "--type-add", "x:*.x",
+            "--include", "*.rs", "--exclude", "target", "--max-filesize", "1M", "--hidden",
+            "--follow", "--git-tracked",
+        ]).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(cli.types, ["rust"]);
+        assert_eq!(cli.max_filesize, Some(1 << 20));
+    }
+
+    #[test]
+    fn index_search_rejects_other_inputs() {
+        for args in &[&["foo", "-p", ".", "--git-log", "HEAD"][..], &["foo", "-p", "https://a.b"]] {
+            let mut cli = Cli::from_iter(std::iter::once("grrs").chain(args.iter().cloned()));
+            cli.indexed = true;
+            let err = resolve(&mut cli).expect_err("rejected");
+            assert!(err.to_string().contains("index search"), "{:?}", args);
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn stdio_server_alone_passes() {
+        let (_, warnings) = resolve_args(&["--stdio-server"]).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_schema_does_not_search() {
+        assert!(resolve_args(&["--json-schema"]).is_ok());
+        for args in [&["--json-schema", "foo"][..], &["--json-schema", "-p", "."],
+            &["--json-schema", "--stdio-server"]]
+        {
+            assert!(rejected(args).contains("--json-schema"), "{:?}", args);
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn stdio_server_rejects_pattern() {
+        assert!(rejected(&["--stdio-server", "foo"]).contains("--stdio-server"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn stdio_server_rejects_extra_patterns() {
+        assert!(rejected(&["--stdio-server", "-e", "foo"]).contains("--stdio-server"));
+        assert!(rejected(&["--stdio-server", "-f", "patterns"]).contains("--stdio-server"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn stdio_server_rejects_path() {
+        assert!(rejected(&["--stdio-server", "-p", "."]).contains("--stdio-server"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn stdio_server_rejects_file_lists() {
+        assert!(rejected(&["--stdio-server", "--files-from", "-"]).contains("--files-from"));
+        assert!(rejected(&["--stdio-server", "--files-from0", "-"]).contains("--files-from"));
+    }
+
+    #[test]
+    fn files_alone_passes() {
+        let (_, warnings) = resolve_args(&["--files", "-p", "."]).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn files_rejects_patterns() {
+        assert!(rejected(&["--files", "foo", "-p", "."]).contains("--files"));
+        assert!(rejected(&["--files", "-e", "foo", "-p", "."]).contains("--files"));
+    }
+
+    #[test]
+    fn files_rejects_other_modes() {
+        for flags in &[&["--estimate"][..], &["--bench", "3"], &["--git-log", "HEAD"]] {
+            let args: Vec<_> = ["--files", "-p", "."].iter().chain(flags.iter()).cloned().collect();
+            assert!(rejected(&args).contains("--files"), "{:?}", flags);
+        }
+    }
+
+    #[test]
+    fn max_depth_rejects_file_lists() {
+        for list in &["--files-from", "--files-from0"] {
+            assert!(rejected(&["foo", list, "-", "--max-depth", "1"]).contains("--max-depth"));
+        }
+    }
+
+    #[test]
+    fn file_lists_ignore_type_filters() {
+        for list in &["--files-from", "--files-from0"] {
+            for flag in &["-t", "-T", "--type-add"] {
+                let value = if *flag == "--type-add" { "x:*.x" } else { "rust" };
+                let (cli, warnings) = resolve_args(&["foo", list, "-", flag, value]).unwrap();
+                assert_eq!(warnings.len(), 1, "{} {}", list, flag);
+                assert!(cli.types.is_empty());
+                assert!(cli.types_not.is_empty());
+                assert!(cli.type_add.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn file_lists_ignore_globs() {
+        for list in &["--files-from", "--files-from0"] {
+            for flag in &["--include", "--exclude"] {
+                let (cli, warnings) = resolve_args(&["foo", list, "-", flag, "*.rs"]).unwrap();
+                assert_eq!(warnings.len(), 1, "{} {}", list, flag);
+                assert!(cli.include.is_empty() && cli.exclude.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn file_lists_ignore_max_filesize() {
+        let (cli, warnings) =
+            resolve_args(&["foo", "--files-from", "-", "--max-filesize", "1K"]).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(cli.max_filesize, None);
+    }
+
+    #[test]
+    fn file_lists_ignore_git_tracked() {
+        let (cli, warnings) =
+            resolve_args(&["foo", "--files-from0", "-", "--git-tracked"]).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(!cli.git_tracked);
+    }
+
+    #[test]
+    fn file_lists_ignore_include_special() {
+        let (cli, warnings) =
+            resolve_args(&["foo", "--files-from", "-", "--include-special"]).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(!cli.include_special);
+    }
+
+    #[test]
+    fn file_lists_warn_once_per_ignored_group() {
+        let (_, warnings) = resolve_args(&[
+            "foo", "--files-from", "-", "-t", "rust", "--include", "*.rs", "--exclude", "x",
+            "--max-filesize", "1K",
+        ]).unwrap();
+        assert_eq!(warnings.len(), 3);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn estimate_warns_about_compressed_inputs() {
+        for flag in &["-z", "--archive"] {
+            let (cli, warnings) = resolve_args(&["foo", "-p", ".", "--estimate", flag]).unwrap();
+            assert_eq!(warnings.len(), 1, "{}", flag);
+            assert!(cli.search_zip || cli.archive);
+        }
+    }
+
+    #[test]
+    fn estimate_ignores_binary_modes() {
+        for flag in &["-a", "--binary"] {
+            let (cli, warnings) = resolve_args(&["foo", "-p", ".", "--estimate", flag]).unwrap();
+            assert_eq!(warnings.len(), 1, "{}", flag);
+            assert!(!cli.text && !cli.binary);
+        }
+    }
+
+    #[test]
+    fn git_log_rejects_file_lists_and_estimate() {
+        assert!(rejected(&["foo", "--files-from", "-", "--git-log", "HEAD"]).contains("--git-log"));
+        assert!(rejected(&["foo", "-p", ".", "--git-log", "HEAD", "--estimate"])
+            .contains("--git-log"));
+    }
+
+    #[test]
+    fn git_log_ignores_traversal_flags() {
+        let (cli, warnings) = resolve_args(&[
+            "foo", "-p", ".", "--git-log", "HEAD", "--max-depth", "1", "-t", "rust",
+            "--include", "*.rs", "--hidden", "--follow", "--git-tracked", "--include-special",
+            "--sort", "path",
+        ]).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(cli.max_depth, None);
+        assert!(cli.types.is_empty() && cli.include.is_empty());
+        assert!(!cli.hidden && !cli.follow && !cli.git_tracked);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn git_log_ignores_archive_and_mmap() {
+        let (cli, warnings) =
+            resolve_args(&["foo", "-p", ".", "--git-log", "HEAD", "--archive", "--mmap"]).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(!cli.archive && !cli.mmap);
+    }
+
+    #[test]
+    fn urls_reject_git_log_and_estimate() {
+        let url = "https://example.com/log.txt";
+        assert!(rejected(&["foo", "-p", url, "--git-log", "HEAD"]).contains("URL"));
+        assert!(rejected(&["foo", "-p", url, "--estimate"]).contains("URL"));
+    }
+
+    #[test]
+    fn counts_ignore_line_output_flags() {
+        for count in &["-c", "--count-matches"] {
+            for flag in &["-o", "-n", "--column", "-b"] {
+                let (cli, warnings) = resolve_args(&["foo", "-p", ".", count, flag]).unwrap();
+                assert_eq!(warnings.len(), 1, "{} {}", count, flag);
+                assert!(!(cli.only_matching || cli.line_number || cli.column || cli.byte_offset));
+            }
+        }
+    }
+
+    #[test]
+    fn file_listing_ignores_counts_and_line_output_flags() {
+        for list in &["-l", "-L"] {
+            let (cli, warnings) =
+                resolve_args(&["foo", "-p", ".", list, "-c", "-o", "-n"]).unwrap();
+            assert_eq!(warnings.len(), 1, "{}", list);
+            assert!(!(cli.count || cli.only_matching || cli.line_number));
+        }
+    }
+
+    #[test]
+    fn context_is_ignored_without_whole_lines() {
+        for flag in &["-o", "-c", "--count-matches", "-l", "-L"] {
+            let (cli, warnings) = resolve_args(&["foo", "-p", ".", "-C", "2", flag]).unwrap();
+            assert_eq!(warnings.len(), 1, "{}", flag);
+            assert_eq!(cli.context(), None);
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_ignores_counts_listings_and_only_matching() {
+        for flag in &["-c", "--count-matches", "-l", "-L", "-o"] {
+            let (cli, warnings) = resolve_args(&["foo", "-p", ".", "--json", flag]).unwrap();
+            assert_eq!(warnings.len(), 1, "{}", flag);
+            assert!(cli.json);
+            assert!(!(cli.count || cli.count_matches || cli.files_with_matches
+                || cli.files_without_match || cli.only_matching));
+        }
+    }
+
+    #[test]
+    fn vimgrep_ignores_counts_listings_and_context() {
+        for flag in &[&["-c"][..], &["--count-matches"], &["-l"], &["-L"], &["-h"],
+            &["--heading"], &["-b"], &["-C", "2"]]
+        {
+            let mut args = vec!["foo", "-p", ".", "--vimgrep"];
+            args.extend_from_slice(flag);
+            let (cli, warnings) = resolve_args(&args).unwrap();
+            assert_eq!(warnings.len(), 1, "{:?}", flag);
+            assert!(cli.vimgrep);
+            assert!(!(cli.count || cli.files_with_matches || cli.no_filename));
+            assert_eq!(cli.context(), None);
+        }
+    }
+
+    #[test]
+    fn tables_and_templates_ignore_context() {
+        for format in &["csv", "tsv", "{path}:{text}"] {
+            let (cli, warnings) =
+                resolve_args(&["foo", "-p", ".", "--format", format, "-C", "2"]).unwrap();
+            assert_eq!(warnings.len(), 1, "{}", format);
+            assert_eq!(cli.context(), None);
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn estimate_ignores_structured_formats() {
+        for format in &[&["--json"][..], &["--format", "csv"]] {
+            let mut args = vec!["foo", "-p", ".", "--estimate"];
+            args.extend_from_slice(format);
+            let (cli, warnings) = resolve_args(&args).unwrap();
+            assert_eq!(warnings.len(), 1, "{:?}", format);
+            assert_eq!(cli.format(), Format::Text);
+        }
+    }
+
+    #[test]
+    fn archive_glob_and_depth_need_archive() {
+        let (cli, warnings) = resolve_args(&["foo", "-p", ".", "--archive-glob", "*.log",
+            "--archive-depth", "2"]).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(cli.archive_glob.is_empty());
+        assert_eq!(cli.archive_depth, 0);
+        #[cfg(feature = "compression")]
+        let (_, warnings) =
+            resolve_args(&["foo", "-p", ".", "--archive", "--archive-glob", "*.log"]).unwrap();
+        #[cfg(feature = "compression")]
+        assert!(warnings.is_empty());
+    }
+
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn async_needs_its_feature() {
+        assert!(rejected(&["foo", "-p", ".", "--async"]).contains("`async` feature"));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_ignores_what_it_cannot_print() {
+        let (_, warnings) = resolve_args(&["foo", "-p", ".", "--async", "--tail", "-n"]).unwrap();
+        assert!(warnings.is_empty());
+        for flags in &[&["-c"][..], &["-l"], &["-C", "2"], &["--heading"], &["--unique"]] {
+            let args: Vec<_> = ["foo", "-p", ".", "--async"].iter().chain(flags.iter()).cloned()
+                .collect();
+            let (cli, warnings) = resolve_args(&args).unwrap();
+            assert_eq!(warnings.len(), 1, "{:?}", flags);
+            assert!(!cli.count && !cli.files_with_matches && !cli.heading);
+            assert!(cli.context().is_none() && cli.unique().is_none());
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_rejects_other_modes() {
+        for flags in &[&["--estimate"][..], &["--bench", "3"], &["--git-log", "HEAD"]] {
+            let args: Vec<_> = ["foo", "-p", ".", "--async"].iter().chain(flags.iter()).cloned()
+                .collect();
+            assert!(rejected(&args).contains("--async"), "{:?}", flags);
+        }
+    }
+
+    #[test]
+    fn tail_needs_async() {
+        assert!(Cli::from_iter_safe(&["grrs", "foo", "-p", ".", "--tail"]).is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn webhook_needs_tail_and_the_net_and_json_features() {
+        let webhook = ["--webhook", "http://localhost/"];
+        assert!(Cli::from_iter_safe(["grrs", "foo", "-p", ".", "--async"].iter().chain(&webhook))
+            .is_err());
+        let args: Vec<_> = ["foo", "-p", ".", "--async", "--tail"].iter().chain(&webhook)
+            .copied().collect();
+        if cfg!(all(feature = "net", feature = "json")) {
+            assert!(resolve_args(&args).is_ok());
+        } else {
+            assert!(rejected(&args).contains("--webhook"));
+        }
+    }
+
+    #[cfg(all(feature = "color", feature = "json"))]
+    #[test]
+    fn structured_formats_ignore_colors() {
+        for format in &[&["--json"][..], &["--format", "csv"], &["--format", "{path}:{text}"]] {
+            for color in &[&["--color", "always"][..], &["--colors", "match:fg:red"]] {
+                let mut args = vec!["foo", "-p", "."];
+                args.extend_from_slice(format);
+                args.extend_from_slice(color);
+                let (cli, warnings) = resolve_args(&args).unwrap();
+                assert_eq!(warnings.len(), 1, "{:?} {:?}", format, color);
+                assert_eq!(cli.color, crate::color::ColorChoice::Auto);
+                assert!(cli.colors.is_empty());
+            }
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn stdio_server_rejects_output() {
+        assert!(rejected(&["--stdio-server", "-O", "out.txt"]).contains("--output"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn unique_is_ignored_with_other_outputs() {
+        for flag in &[&["-c"][..], &["-l"], &["--vimgrep"], &["--json"], &["--format", "csv"]] {
+            for unique in &["--unique", "--unique-counts"] {
+                let mut args = vec!["foo", "-p", ".", unique];
+                args.extend_from_slice(flag);
+                let (cli, warnings) = resolve_args(&args).unwrap();
+                assert_eq!(warnings.len(), 1, "{} {:?}", unique, flag);
+                assert_eq!(cli.unique(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn unique_ignores_line_prefixes_and_context() {
+        let (cli, warnings) =
+            resolve_args(&["foo", "-p", ".", "--unique", "-n", "--column", "-C", "1"]).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(!(cli.line_number || cli.column));
+        assert_eq!(cli.context(), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn sort_results_is_ignored_without_matching_lines() {
+        for flag in &["-c", "--count-matches", "-l", "-L", "--unique", "--json"] {
+            let (cli, warnings) =
+                resolve_args(&["foo", "-p", ".", "--sort-results", "path", flag]).unwrap();
+            assert_eq!(warnings.len(), 1, "{}", flag);
+            assert_eq!(cli.sort_results, None);
+        }
+    }
+
+    #[test]
+    fn sort_results_ignores_context() {
+        let (cli, warnings) =
+            resolve_args(&["foo", "-p", ".", "--sort-results", "line", "-C", "1"]).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(cli.context(), None);
+    }
+
+    #[test]
+    fn sort_keeps_the_order_of_results() {
+        let (cli, warnings) =
+            resolve_args(&["foo", "-p", ".", "--sort", "path", "--no-order"]).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(!cli.no_order);
+    }
+
+    #[test]
+    fn bench_rejects_estimate() {
+        assert!(rejected(&["foo", "-p", ".", "--bench", "3", "--estimate"]).contains("--bench"));
+    }
+
+    #[test]
+    fn bench_ignores_output() {
+        let (cli, warnings) =
+            resolve_args(&["foo", "-p", ".", "--bench", "3", "-O", "out", "--pager"]).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(cli.output.is_none() && !cli.pager);
+    }
+
+    #[test]
+    fn regex_ignores_the_literal_strategy() {
+        for flag in &["--aho-corasick", "--no-aho-corasick"] {
+            let (cli, warnings) = resolve_args(&["foo", "-p", ".", "--regex", flag]).unwrap();
+            assert_eq!(warnings.len(), 1, "{}", flag);
+            assert!(!cli.aho_corasick && !cli.no_aho_corasick);
+        }
+    }
+
+    #[test]
+    fn rejection_wins_over_warnings() {
+        let message = rejected(&["foo", "--files-from", "-", "--max-depth", "1", "-t", "rust"]);
+        assert!(message.contains("--max-depth"));
+    }
+
+    #[cfg(not(feature = "json"))]
+    #[test]
+    fn json_output_needs_the_json_feature() {
+        for args in &[&["foo", "-p", ".", "--json"][..], &["foo", "-p", ".", "--format", "sarif"],
+            &["--stdio-server"]]
+        {
+            assert!(rejected(args).contains("`json` feature"), "{:?}", args);
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn compressed_inputs_need_the_compression_feature() {
+        for flag in &["-z", "--archive"] {
+            assert!(rejected(&["foo", "-p", ".", flag]).contains("`compression` feature"));
+        }
+    }
+}
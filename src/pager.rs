@@ -0,0 +1,46 @@
+use anyhow::{Context, Result as AnyhowResult};
+
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+/// A pager, such as `less`, showing what is written to its input.
+pub struct Pager {
+    process: Child,
+    input: ChildStdin,
+}
+
+impl Pager {
+    /// Start `$PAGER`, or `less` when it is not set. Returns `None` when paging is turned off
+    /// by setting `PAGER` to an empty string or `cat`.
+    pub fn spawn() -> AnyhowResult<Option<Pager>> {
+        let command = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+        let mut words = command.split_whitespace();
+        let program = match words.next() {
+            Some(program) if program != "cat" => program,
+            _ => return Ok(None),
+        };
+        let mut pager = Command::new(program);
+        pager.args(words).stdin(Stdio::piped());
+        // As in git: quit right away when everything fits on one screen, let colors through
+        // and leave the results on the screen after quitting.
+        if std::env::var_os("LESS").is_none() {
+            pager.env("LESS", "FRX");
+        }
+        let mut process = pager.spawn()
+            .with_context(|| format!("Could not start the pager: {}", command))?;
+        let input = process.stdin.take().expect("stdin is piped");
+        Ok(Some(Pager { process, input }))
+    }
+
+    /// The pipe to the pager. Writing fails with `BrokenPipe` once the user quits it.
+    pub fn input(&mut self) -> &mut ChildStdin {
+        &mut self.input
+    }
+
+    /// Close the pipe and wait until the user quits the pager.
+    pub fn wait(self) -> AnyhowResult<()> {
+        let Pager { mut process, input } = self;
+        drop(input);
+        process.wait().context("Could not wait for the pager")?;
+        Ok(())
+    }
+}
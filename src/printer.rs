@@ -0,0 +1,1148 @@
+use anyhow::{anyhow, Result as AnyhowResult};
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::{Result, Write};
+use std::ops::Range;
+#[cfg(feature = "json")]
+use std::path::PathBuf;
+
+use crate::color::{self, Style, Theme};
+use crate::event;
+#[cfg(feature = "json")]
+use crate::event::Event;
+use crate::html;
+use crate::hyperlink;
+use crate::markdown;
+use crate::matcher::Groups;
+#[cfg(feature = "json")]
+use crate::sarif;
+use crate::sink::{MatchedLine, Sink};
+use crate::table::Table;
+use crate::{RunStats, SearchError};
+#[cfg(feature = "json")]
+use crate::FileSummary;
+use crate::template::{self, Template};
+
+/// How the results are written, as picked with `--format`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+    /// grep style lines.
+    Text,
+    /// grep style lines, a matching line once for each occurrence of the pattern with the
+    /// column of that occurrence, as Vim's `grepprg` expects. Picked with `--vimgrep`.
+    Vimgrep,
+    /// One JSON object per line for each event of the search, see `event::Event`.
+    Json,
+    /// A row for each occurrence of the pattern.
+    Table(Table),
+    /// A section for each input, with its lines in a code block.
+    Markdown,
+    /// A page with a section for each input.
+    Html,
+    /// A SARIF log with a result for each occurrence of the pattern, written at the end.
+    Sarif,
+    /// A line for each matching line, or each occurrence if the template refers to them.
+    Template(Template),
+}
+
+impl Format {
+    // The formatter writing the results in this format.
+    fn formatter<W: Write>(self) -> Box<dyn Formatter<W>> {
+        match self {
+            Format::Text => Box::new(Text::default()),
+            Format::Vimgrep => Box::new(Vimgrep::default()),
+            #[cfg(feature = "json")]
+            Format::Json => Box::new(Json),
+            Format::Table(table) => Box::new(table),
+            Format::Markdown => Box::new(Markdown),
+            Format::Html => Box::new(Html),
+            #[cfg(feature = "json")]
+            Format::Sarif => Box::new(Sarif::default()),
+            Format::Template(template) => Box::new(template),
+            // Rejected with the other options.
+            #[cfg(not(feature = "json"))]
+            Format::Json | Format::Sarif => unreachable!("JSON output needs the `json` feature"),
+        }
+    }
+}
+
+/// Parse a `--format` value: the name of a format, or a template with placeholders such as
+/// `{path}:{line_no}:{text}`.
+pub fn parse_format(format: &str) -> AnyhowResult<Format> {
+    match format {
+        "text" => Ok(Format::Text),
+        "json" => Ok(Format::Json),
+        "csv" => Ok(Format::Table(Table::Csv)),
+        "tsv" => Ok(Format::Table(Table::Tsv)),
+        "markdown" => Ok(Format::Markdown),
+        "html" => Ok(Format::Html),
+        "sarif" => Ok(Format::Sarif),
+        _ if format.contains('{') => template::parse(format).map(Format::Template),
+        _ => Err(anyhow!("Unknown format: {} (expected text, json, csv, tsv, markdown, html, \
+            sarif or a template such as '{{path}}:{{line_no}}:{{text}}')", format)),
+    }
+}
+
+/// The order `--sort-results` prints matching lines in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortResults {
+    /// By file, then line number.
+    Path,
+    /// By line number, then file.
+    Line,
+    /// The lines with the most occurrences of the pattern first, then by file and line number.
+    Frequency,
+}
+
+/// Parse a `--sort-results` value.
+pub fn parse_sort_results(sort: &str) -> AnyhowResult<SortResults> {
+    match sort {
+        "path" => Ok(SortResults::Path),
+        "line" => Ok(SortResults::Line),
+        "frequency" => Ok(SortResults::Frequency),
+        _ => Err(anyhow!("Unknown result order: {} (expected path, line or frequency)", sort)),
+    }
+}
+
+// A matching line held back by `--sort-results`.
+struct HeldLine {
+    file: String,
+    number: u64,
+    offset: u64,
+    text: String,
+    spans: Vec<Range<usize>>,
+    groups: Vec<Groups>,
+}
+
+/// How `--unique` reports the distinct occurrences of the pattern.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unique {
+    /// Each of them once, in the order they were first found.
+    Values,
+    /// Each of them once with its number of occurrences, most frequent first.
+    Counts,
+}
+
+/// Writes the results of a search in one format. The printer hands each result to its
+/// formatter, along with what every format shares, in `Out`.
+pub trait Formatter<W: Write> {
+    /// Write a matching line of the current file.
+    fn matched_line(&mut self, out: &mut Out<W>, line: &MatchedLine) -> Result<()>;
+
+    /// Write a line surrounding a match. Formats with nothing but the matches leave it out.
+    fn context_line(&mut self, _out: &mut Out<W>, _number: u64, _offset: u64, _text: &str)
+        -> Result<()>
+    {
+        Ok(())
+    }
+
+    /// Report that the current file is binary and matches, and on how many lines when they
+    /// were counted. Formats with a record for each line read leave it out.
+    fn binary_match(&mut self, _out: &mut Out<W>, _lines: Option<usize>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Finish the results of the current file, once something was written for it.
+    fn end_file(&mut self, _out: &mut Out<W>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Write what was held back until the search is over.
+    fn finish(&mut self, _out: &mut Out<W>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Write the totals of the search.
+    fn summary(&mut self, _out: &mut Out<W>, _summary: RunStats) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether file names are headings, whatever the options.
+    fn headings(&self) -> bool {
+        false
+    }
+}
+
+// What every format shares: the parts of the lines to print, and how.
+#[derive(Default)]
+struct Options {
+    with_filename: bool,
+    heading: bool,
+    line_number: bool,
+    column: bool,
+    byte_offset: bool,
+    only_matching: bool,
+    unique: Option<Unique>,
+    max_columns: Option<usize>,
+    // Whether printed lines end with a NUL byte rather than a newline, as records of
+    // `--null-data` do.
+    null_data: bool,
+    context: bool,
+    // The styles of the output, `None` when it is not colored.
+    theme: Option<Theme>,
+    stats: bool,
+    pattern: String,
+}
+
+// What was printed so far.
+#[derive(Default)]
+struct State {
+    // Name of the input being searched.
+    file: String,
+    // The URL file names link to, when the current file is on disk.
+    link: Option<String>,
+    // Number of the line of the current file printed last, to tell whether the next one
+    // continues the same group of context.
+    last_line: Option<u64>,
+    // Whether any line has been printed yet, since the first group needs no separator and
+    // the first row of a table is preceded by the header.
+    printed: bool,
+    // Whether anything was printed for the current file yet: its heading, or the `begin`
+    // event.
+    began: bool,
+    // Number of matching lines reported for the current file.
+    matched_lines: u64,
+    binary: bool,
+}
+
+/// Where a formatter writes, with the options of the printer and what was printed so far.
+pub struct Out<'a, W: Write> {
+    writer: &'a mut W,
+    options: &'a Options,
+    state: &'a mut State,
+    // Whether file names are printed as headings rather than on every line.
+    headings: bool,
+}
+
+impl<W: Write> Out<'_, W> {
+    // Print the heading of the current file before its first line, after a blank line if
+    // another file came before.
+    fn write_heading(&mut self) -> Result<()> {
+        if !self.headings || self.state.began {
+            return Ok(());
+        }
+        if self.state.printed {
+            writeln!(self.writer)?;
+        }
+        self.state.began = true;
+        self.state.printed = true;
+        self.write_file(None)?;
+        writeln!(self.writer)
+    }
+
+    // Print a `--` line before line `number` if it does not directly follow the last one.
+    // With headings, files are separated by the blank line instead.
+    fn separate(&mut self, number: u64) -> Result<()> {
+        if !self.options.context {
+            return Ok(());
+        }
+        let adjacent = self.state.last_line.is_some_and(|last| last + 1 == number);
+        let first_of_file = self.state.last_line.is_none() && self.headings;
+        if self.state.printed && !adjacent && !first_of_file {
+            writeln!(self.writer, "--")?;
+        }
+        self.state.printed = true;
+        self.state.last_line = Some(number);
+        Ok(())
+    }
+
+    // Write the prefix of a line, with `separator` after each part. Context lines have no
+    // `column`.
+    fn prefix(&mut self, number: u64, offset: u64, column: Option<usize>, separator: char)
+        -> Result<()>
+    {
+        let theme = self.options.theme;
+        if self.options.with_filename && !self.options.heading {
+            self.prefix_file(&separator.to_string(), Some(number))?;
+        }
+        if self.options.line_number {
+            paint(self.writer, theme.map(|theme| theme.line_number), number)?;
+            write!(self.writer, "{}", separator)?;
+        }
+        if let (true, Some(column)) = (self.options.column, column) {
+            paint(self.writer, theme.map(|theme| theme.column), column + 1)?;
+            write!(self.writer, "{}", separator)?;
+        }
+        if self.options.byte_offset {
+            write!(self.writer, "{}{}", offset, separator)?;
+        }
+        Ok(())
+    }
+
+    // Print `line` with its matches highlighted, prefixed with the column of `focus`, a
+    // match. Long lines are shortened around `focus`.
+    fn whole_line(&mut self, line: &MatchedLine, focus: Range<usize>) -> Result<()> {
+        self.prefix(line.number, line.offset, Some(focus.start), ':')?;
+        let shown = self.shorten(line.text, focus)?;
+        let style = match self.options.theme {
+            Some(theme) => theme.matched,
+            None => {
+                write!(self.writer, "{}", &line.text[shown.clone()])?;
+                return self.end_shortened(line.text, shown);
+            }
+        };
+        let mut end = shown.start;
+        for span in line.spans {
+            // Only the part of the match that is shown.
+            let span = span.start.max(shown.start)..span.end.min(shown.end);
+            if span.is_empty() {
+                continue;
+            }
+            write!(self.writer, "{}", &line.text[end..span.start])?;
+            paint(self.writer, Some(style), &line.text[span.clone()])?;
+            end = span.end;
+        }
+        write!(self.writer, "{}", &line.text[end..shown.end])?;
+        self.end_shortened(line.text, shown)
+    }
+
+    // The byte range of `text` to print: all of it, or `max_columns` characters showing the
+    // bytes `focus` when it is longer. What is left out at the start is reported right away.
+    fn shorten(&mut self, text: &str, focus: Range<usize>) -> Result<Range<usize>> {
+        let max = match self.options.max_columns {
+            Some(max) => max,
+            None => return Ok(0..text.len()),
+        };
+        let length = text.chars().count();
+        if length <= max {
+            return Ok(0..text.len());
+        }
+        // Matches that end close enough to the start are shown with it, the others from
+        // their first character, or as much of them as fits.
+        let start = text[..focus.start].chars().count();
+        let end = start + text[focus].chars().count();
+        let first = if end <= max { 0 } else { start.min(length - max) };
+        let byte = |index| text.char_indices().nth(index).map_or(text.len(), |(byte, _)| byte);
+        let shown = byte(first)..byte(first + max);
+        if first > 0 {
+            write!(self.writer, "[\u{2026} {} chars]", first)?;
+            if !text[shown.clone()].starts_with(char::is_whitespace) {
+                write!(self.writer, " ")?;
+            }
+        }
+        Ok(shown)
+    }
+
+    // End a line shortened to `shown`, telling how much of `text` was left out at the end.
+    fn end_shortened(&mut self, text: &str, shown: Range<usize>) -> Result<()> {
+        if shown.end < text.len() {
+            let left_out = text[shown.end..].chars().count();
+            if !text[shown].ends_with(char::is_whitespace) {
+                write!(self.writer, " ")?;
+            }
+            write!(self.writer, "[\u{2026} {} more chars]", left_out)?;
+        }
+        self.end_line()
+    }
+
+    // End a printed line, with the terminator of the records of the input.
+    fn end_line(&mut self) -> Result<()> {
+        self.writer.write_all(if self.options.null_data { b"\0" } else { b"\n" })
+    }
+
+    // Write the name of the current file followed by `separator`, linking to `line`.
+    fn prefix_file(&mut self, separator: &str, line: Option<u64>) -> Result<()> {
+        self.write_file(line)?;
+        write!(self.writer, "{}", separator)
+    }
+
+    // Write the name of the current file, as a link to `line` if there is one.
+    fn write_file(&mut self, line: Option<u64>) -> Result<()> {
+        if let Some(link) = &self.state.link {
+            hyperlink::write_start(self.writer, link, line)?;
+        }
+        paint(self.writer, self.options.theme.map(|theme| theme.path), &self.state.file)?;
+        if self.state.link.is_some() {
+            hyperlink::write_end(self.writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// grep style lines, prefixed and colored as the options say.
+#[derive(Default)]
+struct Text {
+    // The distinct occurrences found so far for `--unique`, with the order in which they were
+    // first found and how often.
+    distinct: HashMap<String, (usize, usize)>,
+}
+
+impl<W: Write> Formatter<W> for Text {
+    fn matched_line(&mut self, out: &mut Out<W>, line: &MatchedLine) -> Result<()> {
+        if out.options.unique.is_some() {
+            for span in line.spans.iter().filter(|span| !span.is_empty()) {
+                let first = self.distinct.len();
+                let value = line.text[span.clone()].to_string();
+                self.distinct.entry(value).or_insert((first, 0)).1 += 1;
+            }
+            return Ok(());
+        }
+        out.write_heading()?;
+        out.separate(line.number)?;
+        if !out.options.only_matching {
+            return out.whole_line(line, line.spans.first().cloned().unwrap_or(0..0));
+        }
+        // Like grep, empty matches are not worth a line of their own.
+        for span in line.spans.iter().filter(|span| !span.is_empty()) {
+            out.prefix(line.number, line.offset + span.start as u64, Some(span.start), ':')?;
+            let style = out.options.theme.map(|theme| theme.matched);
+            paint(out.writer, style, &line.text[span.clone()])?;
+            out.end_line()?;
+        }
+        Ok(())
+    }
+
+    fn context_line(&mut self, out: &mut Out<W>, number: u64, offset: u64, text: &str)
+        -> Result<()>
+    {
+        out.write_heading()?;
+        out.separate(number)?;
+        out.prefix(number, offset, None, '-')?;
+        let shown = out.shorten(text, 0..0)?;
+        write!(out.writer, "{}", &text[shown.clone()])?;
+        out.end_shortened(text, shown)
+    }
+
+    fn binary_match(&mut self, out: &mut Out<W>, lines: Option<usize>) -> Result<()> {
+        // Set apart like the group of lines of a file.
+        if out.headings {
+            if out.state.printed {
+                writeln!(out.writer)?;
+            }
+            out.state.printed = true;
+        }
+        match lines {
+            Some(lines) => {
+                writeln!(out.writer, "Binary file {} matches ({} lines)", out.state.file, lines)
+            }
+            None => writeln!(out.writer, "Binary file {} matches", out.state.file),
+        }
+    }
+
+    /// Print each distinct occurrence once for `unique`.
+    fn finish(&mut self, out: &mut Out<W>) -> Result<()> {
+        let unique = match out.options.unique {
+            Some(unique) => unique,
+            None => return Ok(()),
+        };
+        let mut distinct: Vec<_> = self.distinct.drain().collect();
+        match unique {
+            Unique::Values => distinct.sort_by_key(|(_, (first, _))| *first),
+            Unique::Counts => distinct.sort_by_key(|(_, (first, count))| (Reverse(*count), *first)),
+        }
+        for (value, (_, count)) in distinct {
+            // Aligned like `uniq -c`.
+            if unique == Unique::Counts {
+                write!(out.writer, "{:>7} ", count)?;
+            }
+            paint(out.writer, out.options.theme.map(|theme| theme.matched), value)?;
+            out.end_line()?;
+        }
+        Ok(())
+    }
+
+    fn summary(&mut self, out: &mut Out<W>, summary: RunStats) -> Result<()> {
+        if !out.options.stats {
+            return Ok(());
+        }
+        write_summary(out.writer, &summary)
+    }
+}
+
+/// grep style lines for Vim's `grepprg`: a matching line once for each occurrence of the
+/// pattern, each time with the column of that occurrence.
+#[derive(Default)]
+struct Vimgrep {
+    text: Text,
+}
+
+impl<W: Write> Formatter<W> for Vimgrep {
+    fn matched_line(&mut self, out: &mut Out<W>, line: &MatchedLine) -> Result<()> {
+        // Occurrences printed on their own lines already have a line each.
+        if out.options.unique.is_some() || out.options.only_matching {
+            return self.text.matched_line(out, line);
+        }
+        out.write_heading()?;
+        out.separate(line.number)?;
+        for span in line.spans {
+            out.whole_line(line, span.clone())?;
+        }
+        Ok(())
+    }
+
+    fn context_line(&mut self, out: &mut Out<W>, number: u64, offset: u64, text: &str)
+        -> Result<()>
+    {
+        self.text.context_line(out, number, offset, text)
+    }
+
+    fn binary_match(&mut self, out: &mut Out<W>, lines: Option<usize>) -> Result<()> {
+        self.text.binary_match(out, lines)
+    }
+
+    fn finish(&mut self, out: &mut Out<W>) -> Result<()> {
+        self.text.finish(out)
+    }
+
+    fn summary(&mut self, out: &mut Out<W>, summary: RunStats) -> Result<()> {
+        self.text.summary(out, summary)
+    }
+}
+
+/// One JSON object per line for each event of the search, see `event::Event`.
+#[cfg(feature = "json")]
+struct Json;
+
+#[cfg(feature = "json")]
+impl Json {
+    // Write the `begin` event of the current file, unless it already was.
+    fn begin<W: Write>(out: &mut Out<W>) -> Result<()> {
+        if out.state.began {
+            return Ok(());
+        }
+        out.state.began = true;
+        write_event(out.writer, &Event::Begin { path: &out.state.file })
+    }
+}
+
+#[cfg(feature = "json")]
+impl<W: Write> Formatter<W> for Json {
+    fn matched_line(&mut self, out: &mut Out<W>, line: &MatchedLine) -> Result<()> {
+        Json::begin(out)?;
+        let event = event::Match::new(&out.state.file, &out.options.pattern, line);
+        write_event(out.writer, &Event::Match(event))
+    }
+
+    fn context_line(&mut self, out: &mut Out<W>, number: u64, offset: u64, text: &str)
+        -> Result<()>
+    {
+        Json::begin(out)?;
+        let event = context_event(&out.state.file, number, offset, text);
+        write_event(out.writer, &Event::Context(event))
+    }
+
+    fn binary_match(&mut self, out: &mut Out<W>, lines: Option<usize>) -> Result<()> {
+        // The `end` event tells the rest.
+        out.state.binary = true;
+        out.state.matched_lines = lines.map_or(1, |lines| lines as u64);
+        Json::begin(out)
+    }
+
+    fn end_file(&mut self, out: &mut Out<W>) -> Result<()> {
+        write_event(out.writer, &Event::End(FileSummary {
+            path: PathBuf::from(&out.state.file),
+            matched_lines: out.state.matched_lines,
+            binary: out.state.binary,
+        }))
+    }
+
+    fn summary(&mut self, out: &mut Out<W>, summary: RunStats) -> Result<()> {
+        write_event(out.writer, &Event::Summary(summary))
+    }
+}
+
+/// A row for each occurrence of the pattern, after a header.
+impl<W: Write> Formatter<W> for Table {
+    fn matched_line(&mut self, out: &mut Out<W>, line: &MatchedLine) -> Result<()> {
+        if !out.state.printed {
+            out.state.printed = true;
+            self.write_header(out.writer)?;
+        }
+        let event = event::Match::new(&out.state.file, &out.options.pattern, line);
+        self.write_match(out.writer, &event)
+    }
+}
+
+/// A line for each matching line, or each occurrence if the template refers to them.
+impl<W: Write> Formatter<W> for Template {
+    fn matched_line(&mut self, out: &mut Out<W>, line: &MatchedLine) -> Result<()> {
+        write_template(out.writer, self, &out.state.file, line)
+    }
+}
+
+/// A section for each input, with its lines in a code block.
+struct Markdown;
+
+impl Markdown {
+    // Start the section of the current file, unless it already was. Sections are set apart
+    // by a blank line.
+    fn begin<W: Write>(out: &mut Out<W>) -> Result<()> {
+        if out.state.began {
+            return Ok(());
+        }
+        if out.state.printed {
+            writeln!(out.writer)?;
+        }
+        out.state.began = true;
+        out.state.printed = true;
+        markdown::write_begin(out.writer, &out.state.file)
+    }
+}
+
+impl<W: Write> Formatter<W> for Markdown {
+    fn matched_line(&mut self, out: &mut Out<W>, line: &MatchedLine) -> Result<()> {
+        Markdown::begin(out)?;
+        out.separate(line.number)?;
+        let event = event::Match::new(&out.state.file, &out.options.pattern, line);
+        markdown::write_match(out.writer, &event)
+    }
+
+    fn context_line(&mut self, out: &mut Out<W>, number: u64, offset: u64, text: &str)
+        -> Result<()>
+    {
+        Markdown::begin(out)?;
+        out.separate(number)?;
+        markdown::write_context(out.writer, &context_event(&out.state.file, number, offset, text))
+    }
+
+    fn binary_match(&mut self, out: &mut Out<W>, lines: Option<usize>) -> Result<()> {
+        if out.state.printed {
+            writeln!(out.writer)?;
+        }
+        out.state.printed = true;
+        markdown::write_binary(out.writer, &out.state.file, lines)
+    }
+
+    fn end_file(&mut self, out: &mut Out<W>) -> Result<()> {
+        markdown::write_end(out.writer)
+    }
+
+    fn headings(&self) -> bool {
+        true
+    }
+}
+
+/// A page with a section for each input.
+struct Html;
+
+impl Html {
+    // Write the start of the page, unless it already was.
+    fn start_page<W: Write>(out: &mut Out<W>) -> Result<()> {
+        if out.state.printed {
+            return Ok(());
+        }
+        out.state.printed = true;
+        html::write_header(out.writer)
+    }
+
+    // Start the section of the current file, unless it already was.
+    fn begin<W: Write>(out: &mut Out<W>) -> Result<()> {
+        if out.state.began {
+            return Ok(());
+        }
+        Html::start_page(out)?;
+        out.state.began = true;
+        html::write_begin(out.writer, &out.state.file)
+    }
+}
+
+impl<W: Write> Formatter<W> for Html {
+    fn matched_line(&mut self, out: &mut Out<W>, line: &MatchedLine) -> Result<()> {
+        Html::begin(out)?;
+        out.separate(line.number)?;
+        let event = event::Match::new(&out.state.file, &out.options.pattern, line);
+        html::write_match(out.writer, &event)
+    }
+
+    fn context_line(&mut self, out: &mut Out<W>, number: u64, offset: u64, text: &str)
+        -> Result<()>
+    {
+        Html::begin(out)?;
+        out.separate(number)?;
+        html::write_context(out.writer, &context_event(&out.state.file, number, offset, text))
+    }
+
+    fn binary_match(&mut self, out: &mut Out<W>, lines: Option<usize>) -> Result<()> {
+        Html::start_page(out)?;
+        html::write_binary(out.writer, &out.state.file, lines)
+    }
+
+    fn end_file(&mut self, out: &mut Out<W>) -> Result<()> {
+        html::write_end(out.writer)
+    }
+
+    fn summary(&mut self, out: &mut Out<W>, summary: RunStats) -> Result<()> {
+        Html::start_page(out)?;
+        html::write_footer(out.writer, &summary)
+    }
+
+    fn headings(&self) -> bool {
+        true
+    }
+}
+
+/// A SARIF log with a result for each occurrence of the pattern, written at the end.
+#[cfg(feature = "json")]
+#[derive(Default)]
+struct Sarif {
+    // Started with the first result, once the pattern, which is its rule, is known.
+    log: Option<sarif::Log>,
+}
+
+#[cfg(feature = "json")]
+impl Sarif {
+    fn log(&mut self, pattern: &str) -> &mut sarif::Log {
+        self.log.get_or_insert_with(|| sarif::Log::new(pattern))
+    }
+}
+
+#[cfg(feature = "json")]
+impl<W: Write> Formatter<W> for Sarif {
+    fn matched_line(&mut self, out: &mut Out<W>, line: &MatchedLine) -> Result<()> {
+        let event = event::Match::new(&out.state.file, &out.options.pattern, line);
+        self.log(&out.options.pattern).add_match(&event);
+        Ok(())
+    }
+
+    fn binary_match(&mut self, out: &mut Out<W>, _lines: Option<usize>) -> Result<()> {
+        self.log(&out.options.pattern).add_binary(&out.state.file);
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Out<W>) -> Result<()> {
+        self.log(&out.options.pattern).write(out.writer)
+    }
+}
+
+/// Formats the results of a search, grep style or as structured records, and writes them to
+/// the underlying writer.
+pub struct Printer<W: Write> {
+    writer: W,
+    options: Options,
+    state: State,
+    formatter: Box<dyn Formatter<W>>,
+    sort_results: Option<SortResults>,
+    // The matching lines held back until they are sorted.
+    held: Vec<HeldLine>,
+    messages: bool,
+    hyperlinks: bool,
+    // Where the results are recorded as JSON events for `--journal`, whatever the format.
+    #[cfg(feature = "json")]
+    journal: Option<Journal>,
+}
+
+// The record of the results of a run, with what was recorded so far.
+#[cfg(feature = "json")]
+struct Journal {
+    writer: Box<dyn Write>,
+    state: State,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(writer: W) -> Printer<W> {
+        Printer {
+            writer,
+            options: Options::default(),
+            state: State::default(),
+            formatter: Format::Text.formatter(),
+            sort_results: None,
+            held: Vec::new(),
+            messages: true,
+            hyperlinks: false,
+            #[cfg(feature = "json")]
+            journal: None,
+        }
+    }
+
+    /// Prefix every line with the name of its file.
+    pub fn with_filename(mut self, with_filename: bool) -> Printer<W> {
+        self.options.with_filename = with_filename;
+        self
+    }
+
+    /// Print the name of each file once, above its lines, instead of prefixing every line
+    /// with it. Files are then separated by a blank line. Only used along `with_filename`.
+    pub fn heading(mut self, heading: bool) -> Printer<W> {
+        self.options.heading = heading;
+        self
+    }
+
+    /// Prefix every line with its 1-based line number.
+    pub fn line_number(mut self, line_number: bool) -> Printer<W> {
+        self.options.line_number = line_number;
+        self
+    }
+
+    /// Prefix every line with the 1-based column, in bytes, of its first match.
+    pub fn column(mut self, column: bool) -> Printer<W> {
+        self.options.column = column;
+        self
+    }
+
+    /// Prefix every line with the offset of its first byte in the input.
+    pub fn byte_offset(mut self, byte_offset: bool) -> Printer<W> {
+        self.options.byte_offset = byte_offset;
+        self
+    }
+
+    /// Print every occurrence of the pattern on its own line instead of the whole line.
+    /// Columns and byte offsets are then those of the occurrence.
+    pub fn only_matching(mut self, only_matching: bool) -> Printer<W> {
+        self.options.only_matching = only_matching;
+        self
+    }
+
+    /// Instead of printing the occurrences of the pattern, collect them and print each
+    /// distinct one once, when the search is over. See `finish`.
+    pub fn unique(mut self, unique: Option<Unique>) -> Printer<W> {
+        self.options.unique = unique;
+        self
+    }
+
+    /// Hold matching lines back until the search is over, to print them in this order.
+    /// See `finish`.
+    pub fn sort_results(mut self, sort_results: Option<SortResults>) -> Printer<W> {
+        self.sort_results = sort_results;
+        self
+    }
+
+    /// Shorten lines longer than this many characters to that many, around their first
+    /// match, and tell how many characters were left out before and after.
+    pub fn max_columns(mut self, max_columns: Option<usize>) -> Printer<W> {
+        self.options.max_columns = max_columns;
+        self
+    }
+
+    /// End the lines printed with a NUL byte instead of a newline, like `grep -z`, for the
+    /// records of `--null-data` that may hold newlines. Headings, separators and the lines
+    /// about files still end with a newline.
+    pub fn null_data(mut self, null_data: bool) -> Printer<W> {
+        self.options.null_data = null_data;
+        self
+    }
+
+    /// Separate groups of lines that are not adjacent with a `--` line, as context lines are
+    /// printed.
+    pub fn context(mut self, context: bool) -> Printer<W> {
+        self.options.context = context;
+        self
+    }
+
+    /// Highlight matches, file names, line numbers and columns with ANSI colors.
+    pub fn theme(mut self, theme: Option<Theme>) -> Printer<W> {
+        self.options.theme = theme;
+        self
+    }
+
+    /// Write the results in `format`. The options picking the parts of the lines to print
+    /// only apply to text.
+    pub fn format(mut self, format: Format) -> Printer<W> {
+        self.formatter = format.formatter();
+        self
+    }
+
+    /// Print the totals of the search after the results, as text. The JSON output always
+    /// has them.
+    pub fn stats(mut self, stats: bool) -> Printer<W> {
+        self.options.stats = stats;
+        self
+    }
+
+    /// Print the errors about inputs that could not be searched, to stderr.
+    pub fn messages(mut self, messages: bool) -> Printer<W> {
+        self.messages = messages;
+        self
+    }
+
+    /// Make file names links to the file, and line, in terminals.
+    pub fn hyperlinks(mut self, hyperlinks: bool) -> Printer<W> {
+        self.hyperlinks = hyperlinks;
+        self
+    }
+
+    /// The pattern searched for, which is the rule of the SARIF log.
+    pub fn pattern(mut self, pattern: &str) -> Printer<W> {
+        self.options.pattern = pattern.to_string();
+        self
+    }
+
+    /// Also write the results to `journal`, as the events of `--json`.
+    #[cfg(feature = "json")]
+    pub fn journal(mut self, journal: Box<dyn Write>) -> Printer<W> {
+        self.journal = Some(Journal { writer: journal, state: State::default() });
+        self
+    }
+
+    // Write an event to the journal, if there is one, with the `Json` formatter.
+    #[cfg(feature = "json")]
+    fn record(&mut self, event: impl FnOnce(&mut Out<Box<dyn Write>>) -> Result<()>)
+        -> Result<()>
+    {
+        match &mut self.journal {
+            Some(journal) => event(&mut Out {
+                writer: &mut journal.writer,
+                options: &self.options,
+                state: &mut journal.state,
+                headings: false,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The writer, for output that does not go through the printer, like `--estimate`.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    // The formatter, and what it writes with.
+    fn split(&mut self) -> (&mut dyn Formatter<W>, Out<'_, W>) {
+        let options = &self.options;
+        let out = Out {
+            writer: &mut self.writer,
+            options,
+            state: &mut self.state,
+            headings: self.formatter.headings() || options.with_filename && options.heading,
+        };
+        (&mut *self.formatter, out)
+    }
+
+    /// Start printing the results of the input called `name`.
+    pub fn begin_file(&mut self, name: &str) {
+        let state = &mut self.state;
+        state.file.clear();
+        state.file.push_str(name);
+        state.last_line = None;
+        state.began = false;
+        state.matched_lines = 0;
+        state.binary = false;
+        state.link = if self.hyperlinks { hyperlink::file_url(name) } else { None };
+        #[cfg(feature = "json")]
+        if let Some(journal) = &mut self.journal {
+            journal.state = State { file: name.to_string(), ..State::default() };
+        }
+    }
+
+    /// Finish printing the results of the current input.
+    pub fn end_file(&mut self) -> Result<()> {
+        #[cfg(feature = "json")]
+        self.record(|out| if out.state.began { Json.end_file(out) } else { Ok(()) })?;
+        if !self.state.began {
+            return Ok(());
+        }
+        let (formatter, mut out) = self.split();
+        formatter.end_file(&mut out)
+    }
+
+    /// Print the results held back until the search is over, for `unique`, `sort_results`
+    /// and the formats written at the end.
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(sort) = self.sort_results {
+            self.print_held(sort)?;
+        }
+        let (formatter, mut out) = self.split();
+        formatter.finish(&mut out)
+    }
+
+    /// Print the totals of the search.
+    pub fn summary(&mut self, summary: RunStats) -> Result<()> {
+        #[cfg(feature = "json")]
+        self.record(|out| Json.summary(out, summary.clone()))?;
+        let (formatter, mut out) = self.split();
+        formatter.summary(&mut out, summary)
+    }
+
+    /// Print a matching line of the current file as `file:line_number:column:byte_offset:text`,
+    /// leaving out the parts not wanted.
+    pub fn matched_line(&mut self, line: &MatchedLine) -> Result<()> {
+        if self.sort_results.is_some() {
+            self.held.push(HeldLine {
+                file: self.state.file.clone(),
+                number: line.number,
+                offset: line.offset,
+                text: line.text.to_string(),
+                spans: line.spans.to_vec(),
+                groups: line.groups.to_vec(),
+            });
+            return Ok(());
+        }
+        self.print_matched_line(line)
+    }
+
+    // Print the lines held back by `sort_results`, sorted.
+    fn print_held(&mut self, sort: SortResults) -> Result<()> {
+        let mut held = std::mem::take(&mut self.held);
+        match sort {
+            SortResults::Path => held.sort_by(|a, b| (&a.file, a.number).cmp(&(&b.file, b.number))),
+            SortResults::Line => held.sort_by(|a, b| (a.number, &a.file).cmp(&(b.number, &b.file))),
+            SortResults::Frequency => held.sort_by(|a, b| {
+                (Reverse(a.spans.len()), &a.file, a.number)
+                    .cmp(&(Reverse(b.spans.len()), &b.file, b.number))
+            }),
+        }
+        for line in &held {
+            if line.file != self.state.file {
+                self.end_file()?;
+                self.begin_file(&line.file);
+            }
+            self.print_matched_line(&MatchedLine {
+                number: line.number,
+                offset: line.offset,
+                text: &line.text,
+                spans: &line.spans,
+                groups: &line.groups,
+            })?;
+        }
+        self.end_file()
+    }
+
+    fn print_matched_line(&mut self, line: &MatchedLine) -> Result<()> {
+        #[cfg(feature = "json")]
+        self.record(|out| {
+            out.state.matched_lines += 1;
+            Json.matched_line(out, line)
+        })?;
+        self.state.matched_lines += 1;
+        let (formatter, mut out) = self.split();
+        formatter.matched_line(&mut out, line)
+    }
+
+    /// Print a line surrounding a match, as `file-line_number-byte_offset-text`.
+    pub fn context_line(&mut self, number: u64, offset: u64, text: &str) -> Result<()> {
+        #[cfg(feature = "json")]
+        self.record(|out| Json.context_line(out, number, offset, text))?;
+        let (formatter, mut out) = self.split();
+        formatter.context_line(&mut out, number, offset, text)
+    }
+
+    /// Print the name of the current file, for `-l` and `-L`.
+    pub fn file_name(&mut self) -> Result<()> {
+        let (_, mut out) = self.split();
+        out.prefix_file("", None)?;
+        writeln!(out.writer)
+    }
+
+    /// Print the number of matching lines, or matches, of the current file.
+    pub fn count(&mut self, count: usize) -> Result<()> {
+        let (_, mut out) = self.split();
+        if out.options.with_filename {
+            out.prefix_file(":", None)?;
+        }
+        writeln!(out.writer, "{}", count)
+    }
+
+    /// Report that the current file is binary and matches, and on how many lines when they
+    /// were counted.
+    pub fn binary_match(&mut self, lines: Option<usize>) -> Result<()> {
+        #[cfg(feature = "json")]
+        self.record(|out| Json.binary_match(out, lines))?;
+        let (formatter, mut out) = self.split();
+        formatter.binary_match(&mut out, lines)
+    }
+}
+
+impl<W: Write> Sink for Printer<W> {
+    fn on_file_start(&mut self, name: &str) {
+        Printer::begin_file(self, name)
+    }
+
+    fn on_match(&mut self, line: &MatchedLine) -> Result<()> {
+        Printer::matched_line(self, line)
+    }
+
+    fn on_context(&mut self, number: u64, offset: u64, text: &str) -> Result<()> {
+        Printer::context_line(self, number, offset, text)
+    }
+
+    fn on_binary(&mut self, lines: Option<usize>) -> Result<()> {
+        Printer::binary_match(self, lines)
+    }
+
+    fn on_count(&mut self, count: usize) -> Result<()> {
+        Printer::count(self, count)
+    }
+
+    fn on_file_name(&mut self) -> Result<()> {
+        Printer::file_name(self)
+    }
+
+    fn on_file_end(&mut self) -> Result<()> {
+        Printer::end_file(self)
+    }
+
+    fn on_error(&mut self, error: SearchError) {
+        if self.messages {
+            eprintln!("Error: {:#}", anyhow::Error::from(error));
+        }
+    }
+}
+
+// The event reporting the context line `text`, found in `file`.
+fn context_event<'a>(file: &'a str, number: u64, offset: u64, text: &'a str)
+    -> event::Context<'a>
+{
+    event::Context { path: file, line_number: number, absolute_offset: offset, text }
+}
+
+/// Format a number of bytes with a binary unit, e.g. `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+// Write the totals of the search as text, set apart from the results by a blank line.
+fn write_summary(writer: &mut impl Write, summary: &RunStats) -> Result<()> {
+    let elapsed = summary.elapsed_seconds;
+    let throughput = summary.bytes_read as f64 / elapsed.max(1e-9);
+    writeln!(writer)?;
+    writeln!(writer, "{} matching lines", summary.matched_lines)?;
+    writeln!(writer, "{} inputs searched, {} binary", summary.searched, summary.binary)?;
+    writeln!(writer, "{} entries ignored", summary.ignored)?;
+    writeln!(writer, "{} errors", summary.errors)?;
+    writeln!(writer, "{} lines read, {}", summary.lines_read, format_size(summary.bytes_read))?;
+    if let Some(hits) = summary.matcher_cache_hits {
+        writeln!(writer, "{} compiled matchers reused", hits)?;
+    }
+    writeln!(writer, "{:.3} seconds elapsed on {} thread(s), {}/s", elapsed, summary.threads,
+        format_size(throughput as u64))
+}
+
+// Write `template` for `line`, found in `file`: once, or once for each occurrence of the
+// pattern.
+fn write_template(writer: &mut impl Write, template: &Template, file: &str, line: &MatchedLine)
+    -> Result<()>
+{
+    let mut fields = template::Fields {
+        path: file,
+        line_number: line.number,
+        offset: line.offset,
+        text: line.text,
+        groups: Vec::new(),
+    };
+    if !template.per_match() {
+        return template.write(writer, &fields);
+    }
+    for (index, span) in line.spans.iter().enumerate() {
+        // Without captured groups, the occurrence is all there is.
+        fields.groups =
+            line.groups.get(index).cloned().unwrap_or_else(|| vec![Some(span.clone())]);
+        template.write(writer, &fields)?;
+    }
+    Ok(())
+}
+
+// Write `event` as a line of JSON, in its envelope.
+#[cfg(feature = "json")]
+fn write_event(writer: &mut impl Write, event: &Event) -> Result<()> {
+    serde_json::to_writer(&mut *writer, &event::Envelope { version: event::VERSION, event })?;
+    writeln!(writer)
+}
+
+// Write `text`, in `style` if there is one.
+fn paint(writer: &mut impl Write, style: Option<Style>, text: impl Display) -> Result<()> {
+    match style {
+        Some(style) => write!(writer, "{}", color::paint(style, text.to_string())),
+        None => write!(writer, "{}", text),
+    }
+}
@@ -0,0 +1,151 @@
+//! Python bindings of `Searcher`, with the `python` feature, to search from notebooks and
+//! scripts rather than run the command line and parse what it prints. Build the extension
+//! module with `cargo rustc --release --lib --features python --crate-type cdylib`, and
+//! copy `librust_cli_book.so` to `grrs.so` somewhere on the path of Python.
+//!
+//! ```python
+//! import grrs
+//!
+//! for found in grrs.search("TODO", ["src", "README.md"], ignore_case=True, type="rust"):
+//!     print(found.path, found.line_number, found.text)
+//! ```
+//!
+//! Options are the settings of `SearchConfigBuilder`, as keyword arguments: `regex`,
+//! `ignore_case`, `smart_case`, `max_count`, `type` and `type_not`, `include` and `exclude`
+//! (a string or a list of them), `hidden`, `follow`, `max_depth`, `max_filesize`,
+//! `search_zip`, `text` and `lossy`. A pattern or an option that is not valid raises
+//! `ValueError`. Files that cannot be searched are skipped with a `RuntimeWarning`.
+
+// The code `pyfunction` generates converts results that need no conversion.
+#![allow(clippy::useless_conversion)]
+
+use pyo3::exceptions::{PyOSError, PyRuntimeError, PyRuntimeWarning, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use std::path::PathBuf;
+
+use crate::{Case, SearchConfig, SearchConfigBuilder, SearchError, Searcher};
+
+/// A line containing the pattern.
+#[pyclass(name = "Match", module = "grrs", get_all, frozen)]
+pub struct PyMatch {
+    /// The file the line is in.
+    path: PathBuf,
+    /// 1-based.
+    line_number: u64,
+    /// Offset of the first byte of the line in the file.
+    offset: u64,
+    /// The line, without its terminator.
+    text: String,
+    /// The occurrences of the pattern in the UTF-8 bytes of `text`, as `(start, end)`.
+    spans: Vec<(usize, usize)>,
+}
+
+#[pymethods]
+impl PyMatch {
+    fn __repr__(&self) -> String {
+        format!("Match(path={:?}, line_number={}, text={:?})", self.path, self.line_number,
+            self.text)
+    }
+}
+
+/// The lines containing `pattern` in the files at `paths`, a path or a list of them, and in
+/// the files under the directories among them, as a list of `Match`.
+#[pyfunction]
+#[pyo3(signature = (pattern, paths, **options))]
+fn search(py: Python<'_>, pattern: &str, paths: &Bound<'_, PyAny>,
+    options: Option<&Bound<'_, PyDict>>) -> PyResult<Vec<PyMatch>>
+{
+    let paths: Vec<PathBuf> = match paths.extract::<PathBuf>() {
+        Ok(path) => vec![path],
+        Err(_) => paths.extract()?,
+    };
+    let mut builder = SearchConfig::builder().pattern(pattern);
+    if let Some(options) = options {
+        for (name, value) in options {
+            builder = option(builder, &name.extract::<String>()?, &value)?;
+        }
+    }
+    let searcher = Searcher::new(builder.build().map_err(to_py)?).map_err(to_py)?;
+    // Other Python threads go on while the files are searched.
+    let (matches, errors) = py.allow_threads(|| {
+        let mut matches = Vec::new();
+        let mut errors = Vec::new();
+        for path in &paths {
+            for found in searcher.search_path(path) {
+                match found {
+                    Ok(found) => matches.push(PyMatch {
+                        path: found.path,
+                        line_number: found.line_number,
+                        offset: found.offset,
+                        text: found.text,
+                        spans: found.spans.iter().map(|span| (span.start, span.end)).collect(),
+                    }),
+                    Err(err) => errors.push(err),
+                }
+            }
+        }
+        (matches, errors)
+    });
+    let warning = py.get_type_bound::<PyRuntimeWarning>();
+    for err in errors {
+        PyErr::warn_bound(py, &warning, &format!("{:#}", anyhow::Error::from(err)), 1)?;
+    }
+    Ok(matches)
+}
+
+// Set the option `name` of `builder` to `value`.
+fn option(builder: SearchConfigBuilder, name: &str, value: &Bound<'_, PyAny>)
+    -> PyResult<SearchConfigBuilder>
+{
+    Ok(match name {
+        "regex" => builder.regex(value.extract()?),
+        "ignore_case" if value.extract()? => builder.case(Case::Insensitive),
+        "smart_case" if value.extract()? => builder.case(Case::Smart),
+        "ignore_case" | "smart_case" => builder,
+        "max_count" => builder.max_count(value.extract()?),
+        "type" => strings(value)?.into_iter().fold(builder, SearchConfigBuilder::file_type),
+        "type_not" => {
+            strings(value)?.into_iter().fold(builder, SearchConfigBuilder::file_type_not)
+        }
+        "include" => strings(value)?.into_iter().fold(builder, SearchConfigBuilder::include),
+        "exclude" => strings(value)?.into_iter().fold(builder, SearchConfigBuilder::exclude),
+        "hidden" => builder.hidden(value.extract()?),
+        "follow" => builder.follow(value.extract()?),
+        "max_depth" => builder.max_depth(value.extract()?),
+        "max_filesize" => builder.max_filesize(value.extract()?),
+        "search_zip" => builder.search_zip(value.extract()?),
+        "text" => builder.text(value.extract()?),
+        "lossy" => builder.lossy(value.extract()?),
+        _ => {
+            return Err(PyTypeError::new_err(
+                format!("search() got an unexpected keyword argument '{}'", name)));
+        }
+    })
+}
+
+// A string, or a list of them.
+fn strings(value: &Bound<'_, PyAny>) -> PyResult<Vec<String>> {
+    match value.extract::<String>() {
+        Ok(string) => Ok(vec![string]),
+        Err(_) => value.extract(),
+    }
+}
+
+fn to_py(err: SearchError) -> PyErr {
+    let new_err = match err {
+        SearchError::Pattern(_) | SearchError::Config(_) => PyValueError::new_err,
+        SearchError::Io { .. } => PyOSError::new_err,
+        _ => PyRuntimeError::new_err,
+    };
+    new_err(format!("{:#}", anyhow::Error::from(err)))
+}
+
+/// The `grrs` Python module.
+#[pymodule]
+fn grrs(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(search, module)?)?;
+    module.add_class::<PyMatch>()?;
+    Ok(())
+}
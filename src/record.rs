@@ -0,0 +1,114 @@
+//! The results of an input searched on a worker thread, kept until the printer, which only
+//! the main thread writes with, can take them.
+
+use std::io::Result;
+use std::ops::Range;
+
+use crate::matcher::Groups;
+use crate::sink::{MatchedLine, Sink};
+
+/// Records what the search of an input hands to its `Sink`, to replay it later.
+#[derive(Default)]
+pub struct Recorder {
+    records: Vec<Record>,
+}
+
+enum Record {
+    Begin(String),
+    Match {
+        number: u64,
+        offset: u64,
+        text: String,
+        spans: Vec<Range<usize>>,
+        groups: Vec<Groups>,
+    },
+    Context {
+        number: u64,
+        offset: u64,
+        text: String,
+    },
+    Binary(Option<usize>),
+    Count(usize),
+    FileName,
+    End,
+}
+
+impl Recorder {
+    /// Add `lines` to the numbers of the lines recorded, which were counted from the start of
+    /// a chunk of the input rather than from the start of the input.
+    pub fn shift_lines(&mut self, lines: u64) {
+        for record in &mut self.records {
+            if let Record::Match { number, .. } | Record::Context { number, .. } = record {
+                *number += lines;
+            }
+        }
+    }
+
+    /// Hand everything recorded to `sink`, in the order it was recorded.
+    pub fn replay(self, sink: &mut impl Sink) -> Result<()> {
+        for record in self.records {
+            match record {
+                Record::Begin(name) => sink.on_file_start(&name),
+                Record::Match { number, offset, text, spans, groups } => {
+                    sink.on_match(&MatchedLine {
+                        number,
+                        offset,
+                        text: &text,
+                        spans: &spans,
+                        groups: &groups,
+                    })?;
+                }
+                Record::Context { number, offset, text } => {
+                    sink.on_context(number, offset, &text)?;
+                }
+                Record::Binary(lines) => sink.on_binary(lines)?,
+                Record::Count(count) => sink.on_count(count)?,
+                Record::FileName => sink.on_file_name()?,
+                Record::End => sink.on_file_end()?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Sink for Recorder {
+    fn on_file_start(&mut self, name: &str) {
+        self.records.push(Record::Begin(name.to_string()));
+    }
+
+    fn on_match(&mut self, line: &MatchedLine) -> Result<()> {
+        self.records.push(Record::Match {
+            number: line.number,
+            offset: line.offset,
+            text: line.text.to_string(),
+            spans: line.spans.to_vec(),
+            groups: line.groups.to_vec(),
+        });
+        Ok(())
+    }
+
+    fn on_context(&mut self, number: u64, offset: u64, text: &str) -> Result<()> {
+        self.records.push(Record::Context { number, offset, text: text.to_string() });
+        Ok(())
+    }
+
+    fn on_binary(&mut self, lines: Option<usize>) -> Result<()> {
+        self.records.push(Record::Binary(lines));
+        Ok(())
+    }
+
+    fn on_count(&mut self, count: usize) -> Result<()> {
+        self.records.push(Record::Count(count));
+        Ok(())
+    }
+
+    fn on_file_name(&mut self) -> Result<()> {
+        self.records.push(Record::FileName);
+        Ok(())
+    }
+
+    fn on_file_end(&mut self) -> Result<()> {
+        self.records.push(Record::End);
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+//! Matches as a SARIF 2.1.0 log, for `--format sarif`, to upload as code scanning results
+//! in CI. The pattern is the one rule of the log and each occurrence of it is a result.
+//!
+//! See https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html.
+
+use serde::Serialize;
+
+use std::io::{Result, Write};
+
+use crate::event;
+use crate::hyperlink::encode_path;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// The results of a search, kept until the log can be written as a whole.
+#[derive(Default)]
+pub struct Log {
+    pattern: String,
+    rule_id: String,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Document<'a> {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: [Run<'a>; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Run<'a> {
+    tool: Tool,
+    // Columns count characters, as the text of the lines is what is shown.
+    column_kind: &'static str,
+    results: &'a [SarifResult],
+}
+
+#[derive(Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Serialize)]
+struct Driver {
+    name: &'static str,
+    version: &'static str,
+    rules: [Rule; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    id: String,
+    short_description: Message,
+}
+
+#[derive(Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: String,
+    level: &'static str,
+    message: Message,
+    locations: [Location; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<Region>,
+}
+
+#[derive(Serialize)]
+struct ArtifactLocation {
+    uri: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: u64,
+    /// 1-based, in characters.
+    start_column: usize,
+    /// 1-based, in characters, the end excluded.
+    end_column: usize,
+    snippet: Message,
+}
+
+impl Log {
+    /// A log of the occurrences of `pattern`.
+    pub fn new(pattern: &str) -> Log {
+        Log { pattern: pattern.to_string(), rule_id: rule_id(pattern), results: Vec::new() }
+    }
+
+    /// Add a result for each occurrence of the pattern in the line of `event`.
+    pub fn add_match(&mut self, event: &event::Match) {
+        for submatch in &event.submatches {
+            let start_column = event.text[..submatch.start].chars().count() + 1;
+            self.results.push(SarifResult {
+                rule_id: self.rule_id.clone(),
+                level: "warning",
+                message: Message { text: format!("Found {:?}", submatch.text) },
+                locations: [Location {
+                    physical_location: PhysicalLocation {
+                        artifact_location: ArtifactLocation { uri: uri(event.path) },
+                        region: Some(Region {
+                            start_line: event.line_number,
+                            start_column,
+                            end_column: start_column + submatch.text.chars().count(),
+                            snippet: Message { text: event.text.to_string() },
+                        }),
+                    },
+                }],
+            });
+        }
+    }
+
+    /// Add a result for a binary input, whose lines are not reported.
+    pub fn add_binary(&mut self, path: &str) {
+        self.results.push(SarifResult {
+            rule_id: self.rule_id.clone(),
+            level: "warning",
+            message: Message { text: "Binary file matches".to_string() },
+            locations: [Location {
+                physical_location: PhysicalLocation {
+                    artifact_location: ArtifactLocation { uri: uri(path) },
+                    region: None,
+                },
+            }],
+        });
+    }
+
+    /// Write the log.
+    pub fn write(&self, writer: &mut impl Write) -> Result<()> {
+        let document = Document {
+            schema: SCHEMA,
+            version: "2.1.0",
+            runs: [Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: "grrs",
+                        version: env!("CARGO_PKG_VERSION"),
+                        rules: [Rule {
+                            id: self.rule_id.clone(),
+                            short_description: Message {
+                                text: format!("Occurrences of {:?}", self.pattern),
+                            },
+                        }],
+                    },
+                },
+                column_kind: "unicodeCodePoints",
+                results: &self.results,
+            }],
+        };
+        serde_json::to_writer_pretty(&mut *writer, &document)?;
+        writeln!(writer)
+    }
+}
+
+// An identifier for `pattern`, made of its letters and digits in lower case with dashes in
+// between, like `todo` or `unwrap`.
+fn rule_id(pattern: &str) -> String {
+    let words: Vec<_> = pattern
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.is_empty() {
+        return "pattern".to_string();
+    }
+    words.join("-").to_lowercase().chars().take(64).collect()
+}
+
+// `path` as a URI: relative paths stay relative to the directory searched from, which code
+// scanning resolves against the root of the repository.
+fn uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let relative = path.trim_start_matches("./");
+    let scheme = if relative.starts_with('/') { "file://" } else { "" };
+    format!("{}{}", scheme, encode_path(relative))
+}
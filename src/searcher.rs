@@ -0,0 +1,879 @@
+//! The search as a library: a `Searcher` set up with a `SearchConfig` searches files and
+//! directories, and returns the matching lines as `Match` values instead of printing them.
+
+use anyhow::Result as AnyhowResult;
+#[cfg(any(feature = "json", feature = "serde"))]
+use serde::Deserialize;
+#[cfg(any(feature = "json", feature = "serde"))]
+use serde::Serialize;
+use structopt::StructOpt;
+
+use std::convert::TryFrom;
+use std::fmt::{self, Debug, Formatter};
+use std::io::Result;
+#[cfg(not(target_arch = "wasm32"))]
+use std::ops::ControlFlow;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::sink::{MatchedLine, Sink};
+use crate::source::{Buffer, Source};
+use crate::{decode, matcher, options};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{globs, types, walk};
+use crate::{CancellationToken, Cli, DecoderRegistry, SearchError, Stats};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::OutputError;
+
+/// What to search for, and how: the checked settings of the command line, or those given to
+/// a `SearchConfigBuilder`. Get one from `SearchConfig::builder()`.
+#[derive(Clone)]
+pub struct SearchConfig {
+    // The settings as the command line holds them, which is how the search reads them.
+    pub(crate) args: Cli,
+    warnings: Vec<String>,
+    // The patterns, once `SearchConfigBuilder::build` compiled them to check them, with what
+    // is derived from them, e.g. the literal a case-insensitive pattern requires. Searches
+    // share it rather than compiling the patterns again.
+    pub(crate) matcher: Option<Arc<dyn matcher::Matcher>>,
+}
+
+impl SearchConfig {
+    /// A builder whose settings left to their default search like `grrs` does without
+    /// options.
+    pub fn builder() -> SearchConfigBuilder {
+        SearchConfigBuilder::default()
+    }
+
+    /// The settings that were ignored or that may not do what was meant, to show the user.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    // The compiled patterns: those `build` compiled, or compiled now.
+    pub(crate) fn matcher(&self) -> AnyhowResult<Arc<dyn matcher::Matcher>> {
+        match &self.matcher {
+            Some(matcher) => Ok(matcher.clone()),
+            None => Ok(crate::pattern_matcher(&self.args, &self.args.patterns()?)?.into()),
+        }
+    }
+}
+
+impl Debug for SearchConfig {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.debug_struct("SearchConfig")
+            .field("args", &self.args)
+            .field("warnings", &self.warnings)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TryFrom<Cli> for SearchConfig {
+    type Error = SearchError;
+
+    /// Check the command line, resetting the flags that lose a conflict with another, as
+    /// `options::resolve` does. Fails if it combines options that cannot be.
+    fn try_from(mut args: Cli) -> std::result::Result<SearchConfig, SearchError> {
+        let warnings = options::resolve(&mut args).map_err(config_error)?;
+        Ok(SearchConfig { args, warnings, matcher: None })
+    }
+}
+
+/// Whether the case of the letters matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Case {
+    #[default]
+    Sensitive,
+    Insensitive,
+    /// Insensitive, unless a pattern has an uppercase letter.
+    Smart,
+}
+
+/// Sets up a `SearchConfig`, which `build` checks.
+#[derive(Debug, Clone, Default)]
+pub struct SearchConfigBuilder {
+    patterns: Vec<String>,
+    regex: bool,
+    case: Case,
+    context: Option<(usize, usize)>,
+    max_count: Option<usize>,
+    types: Vec<String>,
+    types_not: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    hidden: bool,
+    follow: bool,
+    max_depth: Option<usize>,
+    max_filesize: Option<u64>,
+    search_zip: bool,
+    text: bool,
+    lossy: bool,
+    threads: usize,
+    decoders: DecoderRegistry,
+}
+
+impl SearchConfigBuilder {
+    /// Look for `pattern`. Lines containing any of the patterns match.
+    pub fn pattern(mut self, pattern: impl Into<String>) -> SearchConfigBuilder {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    /// Treat the patterns as regular expressions, in the syntax of the `regex` crate, rather
+    /// than as literal text.
+    pub fn regex(mut self, regex: bool) -> SearchConfigBuilder {
+        self.regex = regex;
+        self
+    }
+
+    /// Whether the case of the letters matters, which it does by default.
+    pub fn case(mut self, case: Case) -> SearchConfigBuilder {
+        self.case = case;
+        self
+    }
+
+    /// Keep this many lines before and after each match.
+    pub fn context(mut self, before: usize, after: usize) -> SearchConfigBuilder {
+        self.context = Some((before, after));
+        self
+    }
+
+    /// Stop reading a file after this many matching lines.
+    pub fn max_count(mut self, max_count: usize) -> SearchConfigBuilder {
+        self.max_count = Some(max_count);
+        self
+    }
+
+    /// Only search files of this type (e.g. `rust`, `md`). Can be repeated.
+    pub fn file_type(mut self, name: impl Into<String>) -> SearchConfigBuilder {
+        self.types.push(name.into());
+        self
+    }
+
+    /// Do not search files of this type. Can be repeated.
+    pub fn file_type_not(mut self, name: impl Into<String>) -> SearchConfigBuilder {
+        self.types_not.push(name.into());
+        self
+    }
+
+    /// Only search files matching this glob. Globs containing `/` match the path, the others
+    /// the file name. Can be repeated.
+    pub fn include(mut self, glob: impl Into<String>) -> SearchConfigBuilder {
+        self.include.push(glob.into());
+        self
+    }
+
+    /// Skip files and directories matching this glob. Can be repeated.
+    pub fn exclude(mut self, glob: impl Into<String>) -> SearchConfigBuilder {
+        self.exclude.push(glob.into());
+        self
+    }
+
+    /// Also search hidden files and directories.
+    pub fn hidden(mut self, hidden: bool) -> SearchConfigBuilder {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Follow the symbolic links found in directories.
+    pub fn follow(mut self, follow: bool) -> SearchConfigBuilder {
+        self.follow = follow;
+        self
+    }
+
+    /// Descend at most this many directories below the path searched.
+    pub fn max_depth(mut self, max_depth: usize) -> SearchConfigBuilder {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Skip the files larger than this many bytes found in directories.
+    pub fn max_filesize(mut self, max_filesize: u64) -> SearchConfigBuilder {
+        self.max_filesize = Some(max_filesize);
+        self
+    }
+
+    /// Search compressed files decompressed.
+    pub fn search_zip(mut self, search_zip: bool) -> SearchConfigBuilder {
+        self.search_zip = search_zip;
+        self
+    }
+
+    /// Search binary files as if they were text.
+    pub fn text(mut self, text: bool) -> SearchConfigBuilder {
+        self.text = text;
+        self
+    }
+
+    /// Replace invalid UTF-8 in the matching lines with U+FFFD instead of failing.
+    pub fn lossy(mut self, lossy: bool) -> SearchConfigBuilder {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Number of files searched at the same time. 0, the default, picks it from the CPUs and
+    /// what is searched.
+    pub fn threads(mut self, threads: usize) -> SearchConfigBuilder {
+        self.threads = threads;
+        self
+    }
+
+    /// Search the files `decoders` are picked for decoded, e.g. the text extracted from
+    /// documents, before the compression formats of `search_zip`.
+    pub fn decoders(mut self, decoders: DecoderRegistry) -> SearchConfigBuilder {
+        self.decoders = decoders;
+        self
+    }
+
+    /// Check the settings. Fails if there is no pattern, if a pattern is not a valid regular
+    /// expression, if a glob or a file type is not valid, or if the settings conflict the way
+    /// options of the command line can.
+    pub fn build(self) -> std::result::Result<SearchConfig, SearchError> {
+        if self.patterns.is_empty() {
+            return Err(SearchError::Pattern("No pattern to search for".into()));
+        }
+        let ignore_case = match self.case {
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+            Case::Smart => {
+                !self.patterns.iter().any(|pattern| pattern.chars().any(char::is_uppercase))
+            }
+        };
+        let mut args = Cli::from_iter_safe(["grrs", "--regexp", "", "--path", "."])
+            .map_err(|err| SearchError::Other(err.into()))?;
+        args.regexp = self.patterns;
+        args.regex = self.regex;
+        if ignore_case {
+            // The `regex` crate ignores case, literal patterns are escaped for it.
+            for pattern in &mut args.regexp {
+                let literal = if self.regex { pattern.clone() } else { regex::escape(pattern) };
+                *pattern = format!("(?i){}", literal);
+            }
+            args.regex = true;
+        }
+        (args.before_context, args.after_context) = self.context.unzip();
+        args.max_count = self.max_count;
+        args.types = self.types;
+        args.types_not = self.types_not;
+        args.include = self.include;
+        args.exclude = self.exclude;
+        args.hidden = self.hidden;
+        args.follow = self.follow;
+        args.max_depth = self.max_depth;
+        args.max_filesize = self.max_filesize;
+        args.search_zip = self.search_zip;
+        args.text = self.text;
+        args.lossy = self.lossy;
+        args.threads = self.threads;
+        args.decoders = self.decoders;
+        // The command line reports these once it searches, which is too late for a library.
+        let matcher = crate::pattern_matcher(&args, &args.patterns()?)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        types::TypeRegistry::new().matcher(&args.types, &args.types_not).map_err(config_error)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        globs::GlobFilter::new(&args.include, &args.exclude).map_err(config_error)?;
+        let config = SearchConfig::try_from(args)?;
+        Ok(SearchConfig { matcher: Some(matcher.into()), ..config })
+    }
+}
+
+fn config_error(err: anyhow::Error) -> SearchError {
+    SearchError::Config(err.into())
+}
+
+/// A line containing the pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Match {
+    /// The file the line is in.
+    pub path: PathBuf,
+    /// 1-based line number.
+    pub line_number: u64,
+    /// Offset of the first byte of the line in the file.
+    pub offset: u64,
+    /// The line, without its terminator.
+    pub text: String,
+    /// Byte ranges of the occurrences of the pattern in `text`, from left to right.
+    pub spans: Vec<Range<usize>>,
+}
+
+/// What was found in an input that matched, as `--json` reports it in its `end` events.
+/// With the `serde` feature, it can be read back as well as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(any(feature = "json", feature = "serde"), derive(Serialize))]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct FileSummary {
+    pub path: PathBuf,
+    /// Number of matching lines found. For binary inputs, whose lines are not reported,
+    /// reading may have stopped at the first one.
+    pub matched_lines: u64,
+    pub binary: bool,
+}
+
+/// The totals of a whole search, as `--json` reports them in its `summary` event. With the
+/// `json` or `serde` feature, they can be read back as well as written.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(any(feature = "json", feature = "serde"), derive(Serialize, Deserialize))]
+pub struct RunStats {
+    pub matched_lines: usize,
+    /// Inputs searched: files, archive entries or file versions.
+    pub searched: usize,
+    /// Searched inputs that turned out to be binary.
+    pub binary: usize,
+    /// Entries left out while walking directories, e.g. hidden or excluded ones.
+    pub ignored: usize,
+    /// Files and directories that could not be searched.
+    pub errors: usize,
+    pub lines_read: u64,
+    /// Bytes read, after decompression and transcoding.
+    pub bytes_read: u64,
+    /// Lines longer than the maximum line length.
+    pub long_lines: usize,
+    /// Number of threads the files were searched on.
+    pub threads: usize,
+    pub elapsed_seconds: f64,
+    /// Searches of this process that reused a compiled matcher rather than compiling their
+    /// patterns again. Only `--stdio-server` keeps matchers, it is `None` otherwise.
+    #[cfg_attr(any(feature = "json", feature = "serde"),
+        serde(skip_serializing_if = "Option::is_none"))]
+    pub matcher_cache_hits: Option<usize>,
+}
+
+/// Searches paths for the patterns of a `SearchConfig`, which are compiled once, by the
+/// builder of the config or when the searcher is created. A wasm32 build has no file system: it only searches sources, with
+/// `search_source` and `search_bytes`.
+pub struct Searcher {
+    config: SearchConfig,
+    matcher: Arc<dyn matcher::Matcher>,
+    cancellation: CancellationToken,
+}
+
+impl Searcher {
+    /// A searcher for `config`, compiling its patterns unless its builder did.
+    pub fn new(config: SearchConfig) -> std::result::Result<Searcher, SearchError> {
+        let matcher = config.matcher()?;
+        Ok(Searcher { config, matcher, cancellation: CancellationToken::new() })
+    }
+
+    /// Stop the searches of this searcher once `token` is cancelled, going on from then or
+    /// running: the traversal and the file being read are given up, and the search fails
+    /// with `SearchError::Cancelled`, after the lines found until then.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Searcher {
+        self.cancellation = token;
+        self
+    }
+
+    /// Search the file at `path`, or the files under it if it is a directory, for their
+    /// matching lines, file after file in the order of their paths. Each file is searched
+    /// when the iterator gets to it. Binary files have no lines to return. A file that
+    /// cannot be searched gives an error, after the lines found in it if any, and the search
+    /// goes on with the next one.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn search_path(&self, path: impl AsRef<Path>)
+        -> impl Iterator<Item = std::result::Result<Match, SearchError>> + '_
+    {
+        let args = self.args(path.as_ref());
+        let (files, error) = match walk::from_args(&args, &*self.matcher) {
+            Ok(files) => (Some(files), None),
+            Err(err) => (None, Some(Err(err.into()))),
+        };
+        error.into_iter()
+            .chain(files.into_iter().flatten().flat_map(move |file| self.matches(file, &args)))
+            // Nothing is searched once cancelled.
+            .scan(false, |cancelled, found| {
+                if *cancelled {
+                    return None;
+                }
+                *cancelled = matches!(found, Err(SearchError::Cancelled));
+                Some(found)
+            })
+    }
+
+    // The matching lines of `file`, then the error that stopped its search, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn matches(&self, file: AnyhowResult<PathBuf>, args: &Cli)
+        -> Vec<std::result::Result<Match, SearchError>>
+    {
+        if self.cancellation.is_cancelled() {
+            return vec![Err(SearchError::Cancelled)];
+        }
+        let file = match file {
+            Ok(file) => file,
+            Err(err) => return vec![Err(err.into())],
+        };
+        let mut matches = Vec::new();
+        let mut collector = Collector { path: &file, matches: &mut matches };
+        let result = crate::search_file(&file, args, &*self.matcher, &mut collector,
+            &mut Stats::default(), self.cancellation.flag());
+        let error = match result {
+            // The search of the file may have been given up.
+            Ok(()) if self.cancellation.is_cancelled() => Some(SearchError::Cancelled),
+            result => result.err().map(|err| SearchError::in_input(err, &file)),
+        };
+        matches.into_iter().map(Ok).chain(error.map(Err)).collect()
+    }
+
+    /// Search like `search_path`, handing the results to `sink` as they are found rather than
+    /// returning them. The files that cannot be searched are handed to `sink` too, and the
+    /// search goes on without them. Returns the totals of the search. Fails if `sink` does.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn search_into(&self, path: impl AsRef<Path>, sink: &mut impl Sink)
+        -> std::result::Result<RunStats, SearchError>
+    {
+        let start = std::time::Instant::now();
+        let args = self.args(path.as_ref());
+        let mut stats = Stats::default();
+        let mut walker = walk::from_args(&args, &*self.matcher)?;
+        for file in &mut walker {
+            if self.cancellation.is_cancelled() {
+                return Err(SearchError::Cancelled);
+            }
+            let file = match file {
+                Ok(file) => file,
+                Err(err) => {
+                    stats.errors += 1;
+                    sink.on_error(err.into());
+                    continue;
+                }
+            };
+            match crate::search_file(&file, &args, &*self.matcher, sink, &mut stats,
+                self.cancellation.flag())
+            {
+                Ok(()) => {}
+                Err(err) if err.is::<OutputError>() => return Err(err.into()),
+                Err(err) => {
+                    stats.errors += 1;
+                    sink.on_error(SearchError::in_input(err, &file));
+                }
+            }
+        }
+        if self.cancellation.is_cancelled() {
+            return Err(SearchError::Cancelled);
+        }
+        stats.ignored = walker.ignored();
+        Ok(stats.run_stats(1, start.elapsed()))
+    }
+
+    /// Search each of `paths` like `search_path`, on as many threads as the config says,
+    /// handing the matching lines to `on_match` in the order of the paths, as they are found.
+    /// Once `on_match` breaks, it is not called again and the search stops: in the middle of
+    /// the file being searched, and on every thread. Returns `Break` then. Files that cannot
+    /// be searched are skipped, and the first of their errors returned once the others were.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn search_with<P: AsRef<Path>>(&self, paths: impl IntoIterator<Item = P>,
+        on_match: impl FnMut(Match) -> ControlFlow<()>)
+        -> std::result::Result<ControlFlow<()>, SearchError>
+    {
+        let paths: Vec<_> = paths.into_iter().map(|path| path.as_ref().to_path_buf()).collect();
+        // Several paths are searched like a list of files.
+        let args = match paths.as_slice() {
+            [path] => self.args(path),
+            _ => Cli { path: None, ..self.args(Path::new("")) },
+        };
+        let files = paths.iter().flat_map(|path| {
+            let files: Box<dyn Iterator<Item = _>> =
+                match walk::from_args(&self.args(path), &*self.matcher) {
+                    Ok(files) => Box::new(files),
+                    Err(err) => Box::new(std::iter::once(Err(err))),
+                };
+            files
+        });
+        let mut sink = CallbackSink { path: PathBuf::new(), on_match, broke: false, error: None };
+        let mut stats = Stats::default();
+        let threads = crate::threads(&args);
+        let cancelled = self.cancellation.flag();
+        let result = if threads > 1 {
+            crate::search_parallel(files, threads, &args, &*self.matcher, &mut sink, &mut stats,
+                cancelled)
+        } else {
+            let mut result = Ok(());
+            for file in files {
+                if self.cancellation.is_cancelled() {
+                    result = Err(SearchError::Cancelled.into());
+                    break;
+                }
+                match file {
+                    Ok(file) => if let Err(err) = crate::search_file(&file, &args,
+                        &*self.matcher, &mut sink, &mut stats, cancelled)
+                    {
+                        if sink.broke {
+                            break;
+                        }
+                        sink.on_error(SearchError::in_input(err, &file));
+                    },
+                    Err(err) => sink.on_error(err.into()),
+                }
+            }
+            result
+        };
+        // The sink failed to stop the search.
+        if sink.broke {
+            return Ok(ControlFlow::Break(()));
+        }
+        result?;
+        if self.cancellation.is_cancelled() {
+            return Err(SearchError::Cancelled);
+        }
+        match sink.error {
+            Some(error) => Err(error),
+            None => Ok(ControlFlow::Continue(())),
+        }
+    }
+
+    /// Search the bytes of `source` for their matching lines, as `search_path` searches a
+    /// file, decoded as the decoders of the config pick for its name. Their paths are the
+    /// name of `source`.
+    pub fn search_source(&self, source: &(impl Source + ?Sized))
+        -> std::result::Result<Vec<Match>, SearchError>
+    {
+        let args = &self.config.args;
+        let name = source.name();
+        let reader = source.open()
+            .map_err(|err| SearchError::Io { path: name.to_path_buf(), source: err })?;
+        let mut reader = decode::reader(name, reader, args.search_zip, &args.decoders)?;
+        let mut matches = Vec::new();
+        let mut collector = Collector { path: name, matches: &mut matches };
+        crate::search_reader(&name.display().to_string(), &mut reader, args, &*self.matcher,
+            &mut collector, &mut Stats::default(), self.cancellation.flag())?;
+        if self.cancellation.is_cancelled() {
+            return Err(SearchError::Cancelled);
+        }
+        Ok(matches)
+    }
+
+    /// Search `bytes`, the contents of an input called `name`, for their matching lines, as
+    /// `search_path` searches a file. Nothing is read from the file system: buffers already
+    /// in memory, such as the logs shown by a viewer, are searched as they are.
+    pub fn search_bytes(&self, name: &str, bytes: &[u8])
+        -> std::result::Result<Vec<Match>, SearchError>
+    {
+        self.search_source(&Buffer::new(name, bytes))
+    }
+
+    // The arguments of the command line searching `path` as set up.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn args(&self, path: &Path) -> Cli {
+        let mut args = self.config.args.clone();
+        args.path = Some(path.to_path_buf());
+        args.sort = crate::Sort::Path;
+        // The number of threads is picked anew for what `path` is.
+        args.picked_threads = Default::default();
+        args
+    }
+}
+
+// Collects the matching lines of the file at `path`, leaving out everything else.
+struct Collector<'a> {
+    path: &'a Path,
+    matches: &'a mut Vec<Match>,
+}
+
+impl Sink for Collector<'_> {
+    fn on_match(&mut self, line: &MatchedLine) -> Result<()> {
+        self.matches.push(Match {
+            path: self.path.to_path_buf(),
+            line_number: line.number,
+            offset: line.offset,
+            text: line.text.to_string(),
+            spans: line.spans.to_vec(),
+        });
+        Ok(())
+    }
+}
+
+// Hands the matching lines to `on_match` until it breaks, and keeps the first error.
+#[cfg(not(target_arch = "wasm32"))]
+struct CallbackSink<F> {
+    // The file being searched, as named by the search.
+    path: PathBuf,
+    on_match: F,
+    // Set once `on_match` breaks, which the sink fails to stop the search.
+    broke: bool,
+    error: Option<SearchError>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<F: FnMut(Match) -> ControlFlow<()>> Sink for CallbackSink<F> {
+    fn on_file_start(&mut self, name: &str) {
+        self.path = PathBuf::from(name);
+    }
+
+    fn on_match(&mut self, line: &MatchedLine) -> Result<()> {
+        let found = Match {
+            path: self.path.clone(),
+            line_number: line.number,
+            offset: line.offset,
+            text: line.text.to_string(),
+            spans: line.spans.to_vec(),
+        };
+        if (self.on_match)(found).is_break() {
+            self.broke = true;
+            return Err(std::io::Error::other("Stopped by the callback"));
+        }
+        Ok(())
+    }
+
+    fn on_error(&mut self, error: SearchError) {
+        self.error.get_or_insert(error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{BufRead, Read};
+
+    use crate::sink::{Counter, Summaries};
+    use crate::{Decoder, Transcoder};
+
+    // A directory of its own with `files` in it, removed when dropped.
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new(name: &str, files: &[(&str, &str)]) -> Dir {
+            let dir = std::env::temp_dir()
+                .join(format!("grrs-searcher-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            for (file, contents) in files {
+                std::fs::write(dir.join(file), contents).unwrap();
+            }
+            Dir(dir)
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn builder(patterns: &[&str]) -> SearchConfigBuilder {
+        patterns.iter().fold(SearchConfig::builder(), |builder, pattern| builder.pattern(*pattern))
+    }
+
+    fn searcher(patterns: &[&str], regex: bool) -> Searcher {
+        Searcher::new(builder(patterns).regex(regex).build().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn matches_carry_their_position_and_spans() {
+        let dir = Dir::new("position", &[("a.txt", "one\ntwo two\nthree\n")]);
+        let matches: Vec<_> = searcher(&["two"], false).search_path(dir.0.join("a.txt"))
+            .collect::<std::result::Result<_, _>>().unwrap();
+        assert_eq!(matches, [Match {
+            path: dir.0.join("a.txt"),
+            line_number: 2,
+            offset: 4,
+            text: "two two".to_string(),
+            spans: vec![0..3, 4..7],
+        }]);
+    }
+
+    #[test]
+    fn buffers_are_searched_like_files() {
+        let searcher = searcher(&["two"], false);
+        let matches = searcher.search_bytes("log", b"one\ntwo two\nthree\n").unwrap();
+        assert_eq!(matches, [Match {
+            path: PathBuf::from("log"),
+            line_number: 2,
+            offset: 4,
+            text: "two two".to_string(),
+            spans: vec![0..3, 4..7],
+        }]);
+        assert!(searcher.search_bytes("binary", b"two\0").unwrap().is_empty());
+    }
+
+    #[test]
+    fn the_searcher_shares_the_matcher_its_config_compiled() {
+        let config = builder(&["two"]).case(Case::Insensitive).build().unwrap();
+        let compiled = config.matcher.clone().unwrap();
+        let searcher = Searcher::new(config).unwrap();
+        assert!(Arc::ptr_eq(&compiled, &searcher.matcher));
+        assert_eq!(searcher.search_bytes("log", b"TWO\n").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn files_and_buffers_are_sources() {
+        let dir = Dir::new("source", &[("a.log", "one\ntwo\n")]);
+        let searcher = searcher(&["two"], false);
+        let path = dir.0.join("a.log");
+        let from_file = searcher.search_source(path.as_path()).unwrap();
+        assert_eq!(from_file, searcher.search_source(&Buffer::new(&path, b"one\ntwo\n")).unwrap());
+        assert_eq!(from_file[0].path, path);
+        let missing = searcher.search_source(dir.0.join("missing.log").as_path());
+        assert!(matches!(missing, Err(SearchError::Io { .. })));
+    }
+
+    #[test]
+    fn the_callback_stops_the_search() {
+        let dir = Dir::new("callback", &[("a.txt", "x1\nx2\n"), ("b.txt", "x3\n")]);
+        for threads in [1, 4] {
+            let config = builder(&["x"]).threads(threads).build().unwrap();
+            let searcher = Searcher::new(config).unwrap();
+            let mut found = Vec::new();
+            let flow = searcher.search_with([dir.0.join("a.txt"), dir.0.join("b.txt")], |line| {
+                found.push(line.text);
+                ControlFlow::Break(())
+            });
+            assert_eq!(flow.unwrap(), ControlFlow::Break(()));
+            assert_eq!(found, ["x1"]);
+            let mut found = Vec::new();
+            let flow = searcher.search_with([&dir.0], |line| {
+                found.push(line.text);
+                ControlFlow::Continue(())
+            });
+            assert_eq!(flow.unwrap(), ControlFlow::Continue(()));
+            assert_eq!(found, ["x1", "x2", "x3"]);
+        }
+    }
+
+    #[test]
+    fn cancelled_searches_stop_with_an_error() {
+        let dir = Dir::new("cancel", &[("a.txt", "x\n"), ("b.txt", "x\n")]);
+        let token = CancellationToken::new();
+        let searcher = searcher(&["x"], false).with_cancellation(token.clone());
+        let mut found = searcher.search_path(&dir.0);
+        assert_eq!(found.next().unwrap().unwrap().text, "x");
+        token.cancel();
+        assert!(matches!(found.next(), Some(Err(SearchError::Cancelled))));
+        assert!(found.next().is_none());
+        let result = searcher.search_into(&dir.0, &mut Counter::default());
+        assert!(matches!(result, Err(SearchError::Cancelled)));
+        let result = searcher.search_with([&dir.0], |_| ControlFlow::Continue(()));
+        assert!(matches!(result, Err(SearchError::Cancelled)));
+    }
+
+    // Upper-cases the text of the files it is picked for.
+    struct Shout;
+
+    impl Decoder for Shout {
+        fn extensions(&self) -> &[&str] {
+            &["shout"]
+        }
+
+        fn magic(&self) -> &[u8] {
+            b"%SHOUT"
+        }
+
+        fn decode<'a>(&self, mut input: Box<dyn BufRead + 'a>) -> Result<Box<dyn Read + 'a>> {
+            let mut text = String::new();
+            input.read_to_string(&mut text)?;
+            Ok(Box::new(std::io::Cursor::new(text.to_uppercase())))
+        }
+    }
+
+    #[test]
+    fn decoders_are_picked_by_extension_then_magic() {
+        let files = [("a.shout", "quiet\n"), ("b.txt", "%SHOUT quiet\n"), ("c.txt", "quiet\n")];
+        let dir = Dir::new("decoders", &files);
+        // Not valid UTF-8: "café" in Latin-1.
+        std::fs::write(dir.0.join("d.latin"), b"caf\xe9\n").unwrap();
+        let mut decoders = DecoderRegistry::new();
+        decoders.add(Shout);
+        decoders.add(Transcoder::new("latin1", &["latin"]).unwrap());
+        let config = builder(&["QUIET", "café"]).decoders(decoders).build().unwrap();
+        let found: Vec<_> = Searcher::new(config).unwrap().search_path(&dir.0)
+            .map(|found| found.unwrap().path)
+            .collect();
+        assert_eq!(found, [dir.0.join("a.shout"), dir.0.join("b.txt"), dir.0.join("d.latin")]);
+    }
+
+    #[test]
+    fn directories_are_searched_in_the_order_of_the_paths() {
+        let dir = Dir::new("directory", &[("b.txt", "y\nx2\n"), ("a.txt", "x1\n")]);
+        let found: Vec<_> = searcher(&["x[0-9]"], true).search_path(&dir.0)
+            .map(|found| found.unwrap().text)
+            .collect();
+        assert_eq!(found, ["x1", "x2"]);
+    }
+
+    #[test]
+    fn files_that_cannot_be_searched_give_an_error() {
+        let dir = Dir::new("error", &[]);
+        let searcher = searcher(&["x"], false);
+        let mut matches = searcher.search_path(dir.0.join("missing.txt"));
+        match matches.next() {
+            Some(Err(SearchError::Io { path, .. })) => assert_eq!(path, dir.0.join("missing.txt")),
+            other => panic!("not an I/O error: {:?}", other),
+        }
+        assert!(matches.next().is_none());
+    }
+
+    #[test]
+    fn invalid_utf8_errors_name_their_file() {
+        let dir = Dir::new("encoding", &[]);
+        std::fs::write(dir.0.join("a.txt"), b"x\xff\n").unwrap();
+        let searcher = searcher(&["x"], false);
+        let mut matches = searcher.search_path(dir.0.join("a.txt"));
+        match matches.next() {
+            Some(Err(SearchError::Encoding { path, .. })) => {
+                assert_eq!(path, Some(dir.0.join("a.txt")));
+            }
+            other => panic!("not an encoding error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sinks_get_the_results_and_errors() {
+        let dir = Dir::new("sink", &[("a.txt", "x\nx\n"), ("b.txt", "y\n"), ("c.txt", "x\n")]);
+        let searcher = searcher(&["x"], false);
+        let mut counter = Counter::default();
+        searcher.search_into(&dir.0, &mut counter).unwrap();
+        searcher.search_into(dir.0.join("missing.txt"), &mut counter).unwrap();
+        assert_eq!((counter.lines, counter.files, counter.errors), (3, 2, 1));
+    }
+
+    #[test]
+    fn files_and_totals_are_summed_up() {
+        let files = [("a.txt", "x\nx\n"), ("b.txt", "y\n"), ("c.txt", "\0x\n")];
+        let dir = Dir::new("summaries", &files);
+        let searcher = searcher(&["x"], false);
+        let mut summaries = Summaries::default();
+        let stats = searcher.search_into(&dir.0, &mut summaries).unwrap();
+        assert_eq!(summaries.files, [
+            FileSummary { path: dir.0.join("a.txt"), matched_lines: 2, binary: false },
+            FileSummary { path: dir.0.join("c.txt"), matched_lines: 1, binary: true },
+        ]);
+        assert_eq!((stats.searched, stats.binary, stats.errors), (3, 1, 0));
+    }
+
+    #[cfg(all(feature = "serde", feature = "json"))]
+    #[test]
+    fn matches_are_serialized_and_read_back() {
+        let found = Match {
+            path: PathBuf::from("a.txt"),
+            line_number: 2,
+            offset: 4,
+            text: "two two".to_string(),
+            spans: vec![0..3, 4..7],
+        };
+        let json = serde_json::to_string(&found).unwrap();
+        assert_eq!(serde_json::from_str::<Match>(&json).unwrap(), found);
+    }
+
+    #[test]
+    fn case_is_ignored_as_asked() {
+        let dir = Dir::new("case", &[("a.txt", "Word\nword\nWORD.\n")]);
+        let found = |builder: SearchConfigBuilder| {
+            let searcher = Searcher::new(builder.build().unwrap()).unwrap();
+            let matches = searcher.search_path(&dir.0);
+            matches.map(|found| found.unwrap().text).collect::<Vec<_>>()
+        };
+        assert_eq!(found(builder(&["word"])), ["word"]);
+        assert_eq!(found(builder(&["word."]).case(Case::Insensitive)), ["WORD."]);
+        assert_eq!(found(builder(&["word"]).case(Case::Smart)), ["Word", "word", "WORD."]);
+        assert_eq!(found(builder(&["Word"]).case(Case::Smart)), ["Word"]);
+    }
+
+    #[test]
+    fn settings_are_checked_when_built() {
+        assert!(matches!(SearchConfig::builder().build(), Err(SearchError::Pattern(_))));
+        assert!(matches!(builder(&["("]).regex(true).build(), Err(SearchError::Pattern(_))));
+        assert!(builder(&["("]).build().is_ok());
+        assert!(matches!(builder(&["x"]).include("[").build(), Err(SearchError::Config(_))));
+        let unknown_type = builder(&["x"]).file_type("no such type").build();
+        assert!(matches!(unknown_type, Err(SearchError::Config(_))));
+    }
+}
@@ -0,0 +1,258 @@
+//! A long-lived search process for editor plugins, speaking newline-delimited JSON over
+//! stdin and stdout.
+//!
+//! Every request is one line: `{"id": 1, "method": "...", "params": {...}}`.
+//! - `search` runs the tool with `params.args`, the same arguments the command line takes
+//!   (e.g. `["TODO", "-p", "src"]`). Every line it prints is streamed back as
+//!   `{"id": 1, "event": "output", "line": "..."}` while it runs.
+//! - `cancel` stops the search whose id is `params.id`.
+//! - `config` sets `params.args` as extra arguments for every later search.
+//!
+//! Every request ends with exactly one `done`, `cancelled` or `error` event carrying its id.
+//! Searches run in the background, so `cancel` is handled while one is in progress. A
+//! `search` whose id is already running is an error: `cancel` could not tell them apart.
+//!
+//! The matchers compiled for the latest searches are kept for the next ones, and `--stats`
+//! tells how many searches reused one.
+
+use anyhow::{anyhow, Context, Result as AnyhowResult};
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::convert::TryFrom;
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::matcher::Cache;
+use crate::{CancellationToken, Cli, SearchConfig};
+
+// Events from all searches go through the same stdout, one whole line at a time.
+type Output = Arc<Mutex<std::io::Stdout>>;
+
+// How many compiled matchers are kept for later searches: editors tend to search for the
+// few patterns under the cursor over and over.
+const CACHED_MATCHERS: usize = 32;
+
+/// Serve requests from stdin until it is closed, then wait for the running searches.
+pub fn serve() -> AnyhowResult<()> {
+    let output: Output = Arc::new(Mutex::new(std::io::stdout()));
+    let running: Arc<Running> = Default::default();
+    let matchers = Arc::new(Cache::new(CACHED_MATCHERS));
+    let mut config_args: Vec<String> = Vec::new();
+    let mut searches = Vec::new();
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line.context("Could not read request from stdin!")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                send(&output, &json!({"id": null, "event": "error", "message": err.to_string()}))?;
+                continue;
+            }
+        };
+        let id = request["id"].clone();
+        let params = &request["params"];
+        match request["method"].as_str() {
+            Some("search") => {
+                let args = match string_array(&params["args"]) {
+                    Ok(args) => args,
+                    Err(err) => {
+                        send_error(&output, &id, &err)?;
+                        continue;
+                    }
+                };
+                let cancellation = match running.start(&id) {
+                    Some(cancellation) => cancellation,
+                    None => {
+                        let err = anyhow!("A search is already running with id {}", id);
+                        send_error(&output, &id, &err)?;
+                        continue;
+                    }
+                };
+                let output = output.clone();
+                let running = running.clone();
+                let config_args = config_args.clone();
+                let matchers = matchers.clone();
+                searches.push(std::thread::spawn(move || {
+                    let result = search(&id, args, config_args, &matchers, &output,
+                        &cancellation);
+                    running.finish(&id);
+                    let event = match result {
+                        Ok(()) => json!({"id": id, "event": "done"}),
+                        Err(_) if cancellation.is_cancelled() => {
+                            json!({"id": id, "event": "cancelled"})
+                        }
+                        Err(err) => {
+                            json!({"id": id, "event": "error", "message": format!("{:#}", err)})
+                        }
+                    };
+                    // Nobody is left to tell if stdout itself is broken.
+                    let _ = send(&output, &event);
+                }));
+            }
+            Some("cancel") => {
+                if !running.cancel(&params["id"]) {
+                    let err = anyhow!("No search is running with id {}", params["id"]);
+                    send_error(&output, &id, &err)?;
+                    continue;
+                }
+                send(&output, &json!({"id": id, "event": "done"}))?;
+            }
+            Some("config") => match string_array(&params["args"]) {
+                Ok(args) => {
+                    config_args = args;
+                    send(&output, &json!({"id": id, "event": "done"}))?;
+                }
+                Err(err) => send_error(&output, &id, &err)?,
+            },
+            _ => send_error(&output, &id, &anyhow!("Unknown method: {}", request["method"]))?,
+        }
+    }
+
+    for search in searches {
+        let _ = search.join();
+    }
+    Ok(())
+}
+
+// Cancellation flags of the searches in progress, keyed by their serialized id.
+#[derive(Default)]
+struct Running(Mutex<HashMap<String, CancellationToken>>);
+
+impl Running {
+    // The flag of a new search, or `None` if one with the same id is still running.
+    fn start(&self, id: &Value) -> Option<CancellationToken> {
+        match self.0.lock().unwrap().entry(id.to_string()) {
+            Entry::Occupied(_) => None,
+            Entry::Vacant(entry) => Some(entry.insert(CancellationToken::new()).clone()),
+        }
+    }
+
+    fn finish(&self, id: &Value) {
+        self.0.lock().unwrap().remove(&id.to_string());
+    }
+
+    // Whether a search with this id was running.
+    fn cancel(&self, id: &Value) -> bool {
+        match self.0.lock().unwrap().get(&id.to_string()) {
+            Some(cancellation) => {
+                cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Run one search, parsing its arguments exactly like the command line does.
+fn search(id: &Value, args: Vec<String>, config_args: Vec<String>, matchers: &Arc<Cache>,
+    output: &Output, cancellation: &CancellationToken) -> AnyhowResult<()>
+{
+    let argv = std::iter::once("grrs".to_string()).chain(args).chain(config_args);
+    let mut args = Cli::from_iter_safe(argv).map_err(|err| anyhow!(err.message))?;
+    if args.stdio_server || args.json_schema || args.files {
+        return Err(anyhow!(
+            "--stdio-server, --json-schema and --files cannot be used in a search request"));
+    }
+    args.matchers = Some(matchers.clone());
+    let config = SearchConfig::try_from(args)?;
+    // Warnings go to stderr, where editors usually log them, rather than into the protocol.
+    for warning in config.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+    let mut writer = EventWriter {
+        id: id.clone(),
+        line: Vec::new(),
+        output: output.clone(),
+        cancellation: cancellation.clone(),
+    };
+    crate::run(&config, &mut writer, cancellation.flag())?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn string_array(value: &Value) -> AnyhowResult<Vec<String>> {
+    value.as_array()
+        .and_then(|values| values.iter().map(|value| value.as_str().map(String::from)).collect())
+        .ok_or_else(|| anyhow!("`params.args` must be an array of strings"))
+}
+
+fn send(output: &Output, event: &Value) -> AnyhowResult<()> {
+    let mut output = output.lock().unwrap();
+    writeln!(output, "{}", event)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn send_error(output: &Output, id: &Value, err: &anyhow::Error) -> AnyhowResult<()> {
+    send(output, &json!({"id": id, "event": "error", "message": format!("{:#}", err)}))
+}
+
+// Turns the text a search prints into `output` events, one per line.
+// Writing fails once the search is cancelled, which unwinds it through the usual `?`s.
+struct EventWriter {
+    id: Value,
+    // The line being printed, until its newline arrives.
+    line: Vec<u8>,
+    output: Output,
+    cancellation: CancellationToken,
+}
+
+impl EventWriter {
+    fn send_line(&mut self) -> std::io::Result<()> {
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+        send(&self.output, &json!({"id": self.id, "event": "output", "line": line}))
+            .map_err(std::io::Error::other)
+    }
+}
+
+impl Write for EventWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Not `ErrorKind::Interrupted`: `write_all` would just retry the write forever.
+        if self.cancellation.is_cancelled() {
+            return Err(std::io::Error::other("search cancelled"));
+        }
+        for &byte in buf {
+            if byte == b'\n' {
+                self.send_line()?;
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.line.is_empty() {
+            self.send_line()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_of_running_searches_are_not_reused() {
+        let running = Running::default();
+        let first = running.start(&json!(1)).expect("started");
+        assert!(running.start(&json!(1)).is_none());
+        // The same id as a string is another id.
+        let other = running.start(&json!("1")).expect("started");
+
+        assert!(running.cancel(&json!(1)));
+        assert!(first.is_cancelled());
+        assert!(!other.is_cancelled());
+
+        running.finish(&json!(1));
+        assert!(!running.cancel(&json!(1)));
+        assert!(running.start(&json!(1)).is_some());
+    }
+}
@@ -0,0 +1,134 @@
+//! What the search hands its results to, as it finds them: a `Sink`. The printer writes them
+//! in the format asked for, a recorder keeps them when the input is searched on another
+//! thread, and other programs can take them through `Searcher::search_into`.
+
+use std::io::Result;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::matcher::Groups;
+use crate::{FileSummary, SearchError};
+
+/// A line containing the pattern, as handed to a sink.
+pub struct MatchedLine<'a> {
+    /// 1-based line number.
+    pub number: u64,
+    /// Offset of the first byte of the line in the input.
+    pub offset: u64,
+    pub text: &'a str,
+    /// Byte ranges of the occurrences of the pattern in `text`, from left to right.
+    pub spans: &'a [Range<usize>],
+    /// The capture groups of each occurrence, when a template refers to them. Empty
+    /// otherwise.
+    pub groups: &'a [Groups],
+}
+
+/// Takes the results of a search, input after input. Only `on_match` has to be implemented,
+/// the other events are ignored by default. An error ends the search.
+pub trait Sink {
+    /// The search of the input called `name` starts.
+    fn on_file_start(&mut self, _name: &str) {}
+
+    fn on_match(&mut self, line: &MatchedLine) -> Result<()>;
+
+    /// A line printed around the matches, with `--context`.
+    fn on_context(&mut self, _number: u64, _offset: u64, _text: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// The input is binary and matched, on `lines` lines if they were counted.
+    fn on_binary(&mut self, _lines: Option<usize>) -> Result<()> {
+        Ok(())
+    }
+
+    /// What `--count` counted in the input.
+    fn on_count(&mut self, _count: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// The name of the input is to be listed, for `-l` or `-L`.
+    fn on_file_name(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The search of the input is over.
+    fn on_file_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// An input could not be searched. The search goes on without it.
+    fn on_error(&mut self, _error: SearchError) {}
+}
+
+/// Counts what is found, without keeping any of it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Counter {
+    /// Matching lines.
+    pub lines: usize,
+    /// Inputs with at least one matching line.
+    pub files: usize,
+    /// Inputs that could not be searched.
+    pub errors: usize,
+    // Whether the current input matched yet.
+    matched: bool,
+}
+
+impl Sink for Counter {
+    fn on_file_start(&mut self, _name: &str) {
+        self.matched = false;
+    }
+
+    fn on_match(&mut self, _line: &MatchedLine) -> Result<()> {
+        self.lines += 1;
+        if !self.matched {
+            self.matched = true;
+            self.files += 1;
+        }
+        Ok(())
+    }
+
+    fn on_error(&mut self, _error: SearchError) {
+        self.errors += 1;
+    }
+}
+
+/// Sums up each input that matched, as `--json` does in its `end` events.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summaries {
+    /// One for each input that matched, in the order they were searched.
+    pub files: Vec<FileSummary>,
+    // The name of the current input, and whether it matched yet.
+    name: String,
+    matched: bool,
+}
+
+impl Summaries {
+    // The summary of the current input, which matched.
+    fn current(&mut self) -> &mut FileSummary {
+        if !self.matched {
+            self.matched = true;
+            let path = PathBuf::from(&self.name);
+            self.files.push(FileSummary { path, matched_lines: 0, binary: false });
+        }
+        self.files.last_mut().expect("the current input was just added")
+    }
+}
+
+impl Sink for Summaries {
+    fn on_file_start(&mut self, name: &str) {
+        self.name = name.to_string();
+        self.matched = false;
+    }
+
+    fn on_match(&mut self, _line: &MatchedLine) -> Result<()> {
+        self.current().matched_lines += 1;
+        Ok(())
+    }
+
+    fn on_binary(&mut self, lines: Option<usize>) -> Result<()> {
+        let current = self.current();
+        current.binary = true;
+        current.matched_lines = lines.map_or(1, |lines| lines as u64);
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+//! Where the bytes searched come from. `Searcher::search_source` reads them from a `Source`,
+//! so that searching does not depend on a file system: a wasm32 build, which has none,
+//! searches the buffers it is handed.
+
+use std::io::{BufRead, Result};
+use std::path::Path;
+
+/// An input to search: bytes to read, and a name to report their matches under.
+pub trait Source {
+    /// The name of the input, which picks its decoder as the path of a file would.
+    fn name(&self) -> &Path;
+
+    /// Read the bytes of the input, from the start.
+    fn open(&self) -> Result<Box<dyn BufRead + '_>>;
+}
+
+/// Bytes already in memory, such as the logs shown by a viewer.
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer<'a> {
+    name: &'a Path,
+    bytes: &'a [u8],
+}
+
+impl<'a> Buffer<'a> {
+    pub fn new(name: &'a (impl AsRef<Path> + ?Sized), bytes: &'a [u8]) -> Buffer<'a> {
+        Buffer { name: name.as_ref(), bytes }
+    }
+}
+
+impl Source for Buffer<'_> {
+    fn name(&self) -> &Path {
+        self.name
+    }
+
+    fn open(&self) -> Result<Box<dyn BufRead + '_>> {
+        Ok(Box::new(self.bytes))
+    }
+}
+
+/// The file at the path, read as stored: no memory map and no archive entries, which the
+/// search of paths has.
+#[cfg(not(target_arch = "wasm32"))]
+impl Source for Path {
+    fn name(&self) -> &Path {
+        self
+    }
+
+    fn open(&self) -> Result<Box<dyn BufRead + '_>> {
+        Ok(Box::new(std::io::BufReader::new(std::fs::File::open(self)?)))
+    }
+}
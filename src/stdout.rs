@@ -0,0 +1,56 @@
+//! Buffered writing to stdout that only holds the stdout lock while flushing.
+
+use std::io::{Result, Write};
+
+/// Collects what is written in a buffer of its own, and only takes the stdout lock to write
+/// the buffer out when it is full or flushed. Unlike a `BufWriter` around a `StdoutLock`,
+/// other threads can print in between flushes, and nothing has to keep the lock alive.
+pub struct BufferedStdout {
+    buffer: Vec<u8>,
+    capacity: usize,
+}
+
+impl BufferedStdout {
+    pub fn with_capacity(capacity: usize) -> BufferedStdout {
+        BufferedStdout { buffer: Vec::with_capacity(capacity), capacity }
+    }
+
+    // Write the buffer and `rest` out under a single lock, so that they stay together.
+    fn write_out(&mut self, rest: &[u8]) -> Result<()> {
+        let stdout = std::io::stdout();
+        let mut lock = stdout.lock();
+        // Cleared even on errors, which would otherwise be written again on the next flush.
+        let result = lock.write_all(&self.buffer).and_then(|()| lock.write_all(rest));
+        self.buffer.clear();
+        result?;
+        lock.flush()
+    }
+}
+
+impl Write for BufferedStdout {
+    fn write(&mut self, bytes: &[u8]) -> Result<usize> {
+        if self.buffer.len() + bytes.len() <= self.capacity {
+            self.buffer.extend_from_slice(bytes);
+        } else if bytes.len() < self.capacity {
+            self.write_out(&[])?;
+            self.buffer.extend_from_slice(bytes);
+        } else {
+            // Too large to be buffered, it goes straight out after what is waiting.
+            self.write_out(bytes)?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_out(&[])
+    }
+}
+
+impl Drop for BufferedStdout {
+    // Errors cannot be reported from here, so `flush` should be called before dropping.
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            let _ = self.write_out(&[]);
+        }
+    }
+}
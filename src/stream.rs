@@ -0,0 +1,373 @@
+//! `--async`: every input is read as a `ByteStream` on a tokio runtime, so that a URL, a FIFO
+//! or a file followed with `--tail` that has nothing to give holds up none of the others. Each
+//! input is searched by a task of its own, line by line as its bytes arrive, and the matching
+//! lines are handed to the calling thread, which prints them at once.
+
+use anyhow::{Context, Result as AnyhowResult};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio::sync::{mpsc, Semaphore};
+
+use std::future::Future;
+use std::io::{Read, Result, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::matcher::Matcher;
+use crate::printer::Printer;
+use crate::sink::{MatchedLine, Sink};
+use crate::webhook::Webhook;
+use crate::{event, net, walk, Cli, OutputError, SearchError, Stats};
+
+/// Bytes that arrive over time, from whatever kind of input.
+pub trait ByteStream: Send {
+    /// Append the next bytes of the input to `buffer`, waiting for them if need be, and return
+    /// how many there were: 0 once the input has ended.
+    fn read(&mut self, buffer: &mut Vec<u8>) -> impl Future<Output = Result<usize>> + Send;
+}
+
+/// An input read as it comes, such as a file or a FIFO.
+struct Reader<R>(R);
+
+impl<R: AsyncRead + Unpin + Send> ByteStream for Reader<R> {
+    async fn read(&mut self, buffer: &mut Vec<u8>) -> Result<usize> {
+        self.0.read_buf(buffer).await
+    }
+}
+
+// How often a file followed with `--tail` is checked for new lines once its end is reached.
+const TAIL_POLL: Duration = Duration::from_millis(250);
+
+/// A file read on past its end, like `tail -f`. It is read again from the start when it is
+/// truncated.
+struct Tail {
+    file: tokio::fs::File,
+    position: u64,
+}
+
+impl ByteStream for Tail {
+    async fn read(&mut self, buffer: &mut Vec<u8>) -> Result<usize> {
+        loop {
+            let read = self.file.read_buf(buffer).await?;
+            if read > 0 {
+                self.position += read as u64;
+                return Ok(read);
+            }
+            if self.file.metadata().await?.len() < self.position {
+                self.position = self.file.seek(SeekFrom::Start(0)).await?;
+            }
+            tokio::time::sleep(TAIL_POLL).await;
+        }
+    }
+}
+
+/// The body of a URL. The HTTP client blocks, so the body is read on a thread of its own and
+/// handed over in chunks.
+struct Url(mpsc::Receiver<Result<Vec<u8>>>);
+
+impl Url {
+    /// Request `url`, reading its body `chunk_size` bytes at a time.
+    fn open(url: String, chunk_size: usize) -> Url {
+        let (sender, receiver) = mpsc::channel(4);
+        tokio::task::spawn_blocking(move || {
+            let mut body = match net::open(&url) {
+                Ok(body) => body,
+                Err(err) => {
+                    let _ = sender.blocking_send(Err(std::io::Error::other(err)));
+                    return;
+                }
+            };
+            loop {
+                let mut chunk = vec![0; chunk_size];
+                let result = body.read(&mut chunk).map(|read| {
+                    chunk.truncate(read);
+                    chunk
+                });
+                let end = !matches!(&result, Ok(chunk) if !chunk.is_empty());
+                // Nobody reads the body any more once the search is over.
+                if sender.blocking_send(result).is_err() || end {
+                    return;
+                }
+            }
+        });
+        Url(receiver)
+    }
+}
+
+impl ByteStream for Url {
+    async fn read(&mut self, buffer: &mut Vec<u8>) -> Result<usize> {
+        let chunk = self.0.recv().await.transpose()?.unwrap_or_default();
+        buffer.extend_from_slice(&chunk);
+        Ok(chunk.len())
+    }
+}
+
+/// What the task searching an input hands to the thread printing the results.
+enum Found {
+    Line { input: Arc<str>, number: u64, offset: u64, text: String, spans: Vec<Range<usize>> },
+    /// The binary input matched. It is not read any further.
+    Binary(Arc<str>),
+    /// An input could not be searched.
+    Error(anyhow::Error),
+    /// An input was read to its end, or the walk is over, with these totals.
+    Done(Stats),
+}
+
+// How many results are queued for printing before the tasks wait for them to be printed.
+const QUEUED_RESULTS: usize = 1024;
+
+// How many files, not followed with `--tail`, are read at the same time.
+const OPEN_FILES: usize = 64;
+
+// How much of each input is looked at to tell binary files from text.
+const BINARY_CHECK_SIZE: usize = crate::BINARY_CHECK_SIZE as usize;
+
+/// Search the inputs of `args` at the same time, printing each matching line as soon as it is
+/// read. It goes on until every input has ended, which a file followed with `--tail` never
+/// does, or until `cancelled` is set.
+pub fn search<W: Write>(args: &Cli, matcher: Arc<dyn Matcher>, pattern: &str,
+    printer: &mut Printer<W>, stats: &mut Stats, cancelled: &AtomicBool) -> AnyhowResult<()>
+{
+    let webhook = args.webhook.as_deref().map(Webhook::start);
+    let post = |found: &Found| match (&webhook, found) {
+        (Some(webhook), Found::Line { input, number, offset, text, spans }) => {
+            let line = MatchedLine { number: *number, offset: *offset, text, spans, groups: &[] };
+            webhook.send(&event::Match::new(input, pattern, &line))
+        }
+        _ => Ok(()),
+    };
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(crate::threads(args))
+        .enable_all()
+        .build()
+        .context("Could not start the async runtime")?;
+    let walker = match args.path.as_deref().filter(|path| net::is_url(path)) {
+        Some(_) => None,
+        None => Some(walk::from_args(args, &*matcher)?),
+    };
+    let shared = Arc::new(args.clone());
+    // Set once the results are no longer wanted, for the walk to give up.
+    let stop = Arc::new(AtomicBool::new(false));
+    let (sender, mut receiver) = mpsc::channel(QUEUED_RESULTS);
+    let result = runtime.block_on(async {
+        let stop = stop.clone();
+        tokio::task::spawn_blocking(move || start(walker, shared, matcher, sender, &stop));
+        loop {
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(SearchError::Cancelled.into());
+            }
+            let found = match tokio::time::timeout(crate::CANCEL_POLL, receiver.recv()).await {
+                Ok(Some(found)) => found,
+                Ok(None) => return Ok(()),
+                Err(_) => continue,
+            };
+            post(&found)?;
+            print(found, printer, stats)?;
+            // Whatever else is there is printed along, and the lot written out at once.
+            while let Ok(found) = receiver.try_recv() {
+                post(&found)?;
+                print(found, printer, stats)?;
+            }
+            printer.get_mut().flush().map_err(OutputError)?;
+            if args.quiet && stats.matches > 0 {
+                return Ok(());
+            }
+        }
+    });
+    stop.store(true, Ordering::Relaxed);
+    // The tasks still reading, and the threads blocked on a URL, are left behind.
+    runtime.shutdown_background();
+    result
+}
+
+// Print what was found.
+fn print<W: Write>(found: Found, printer: &mut Printer<W>, stats: &mut Stats)
+    -> AnyhowResult<()>
+{
+    match found {
+        Found::Line { input, number, offset, text, spans } => {
+            printer.on_file_start(&input);
+            let line = MatchedLine { number, offset, text: &text, spans: &spans, groups: &[] };
+            printer.on_match(&line).map_err(OutputError)?;
+            printer.on_file_end().map_err(OutputError)?;
+            stats.matches += 1;
+        }
+        Found::Binary(input) => {
+            printer.on_file_start(&input);
+            printer.on_binary(None).map_err(OutputError)?;
+            printer.on_file_end().map_err(OutputError)?;
+            stats.matches += 1;
+        }
+        Found::Error(err) => crate::report(err, printer, stats),
+        Found::Done(totals) => stats.add(&totals),
+    }
+    Ok(())
+}
+
+/// Start a task searching each input: the URL of `args`, or the files of `walker`, as the walk
+/// finds them. Runs on a thread of the runtime that may block.
+fn start(walker: Option<walk::Walker>, args: Arc<Cli>, matcher: Arc<dyn Matcher>,
+    found: mpsc::Sender<Found>, stop: &AtomicBool)
+{
+    let runtime = tokio::runtime::Handle::current();
+    let mut walker = match walker {
+        Some(walker) => walker,
+        None => {
+            let url = args.path.as_deref().unwrap_or(Path::new("")).to_string_lossy().into();
+            runtime.spawn(search_url(url, args.clone(), matcher, found));
+            return;
+        }
+    };
+    let open_files = Arc::new(Semaphore::new(OPEN_FILES));
+    for path in walk::sorted(&mut walker, args.sort) {
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let path = match path {
+            Ok(path) => path,
+            Err(err) => {
+                let _ = found.blocking_send(Found::Error(err));
+                continue;
+            }
+        };
+        // Files followed with `--tail` are never done with, and do not count.
+        let permit = match args.tail {
+            true => None,
+            false => runtime.block_on(open_files.clone().acquire_owned()).ok(),
+        };
+        let (args, matcher, found) = (args.clone(), matcher.clone(), found.clone());
+        runtime.spawn(async move {
+            search_path(path, args, matcher, found).await;
+            drop(permit);
+        });
+    }
+    let _ = found.blocking_send(Found::Done(Stats { ignored: walker.ignored(), ..Stats::default() }));
+}
+
+/// An input on disk, opened.
+enum Opened {
+    File(tokio::fs::File),
+    #[cfg(unix)]
+    Fifo(tokio::net::unix::pipe::Receiver),
+}
+
+// Open the file, or the FIFO, at `path`.
+async fn open(path: &Path, tail: bool) -> Result<Opened> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        if tokio::fs::metadata(path).await?.file_type().is_fifo() {
+            let mut options = tokio::net::unix::pipe::OpenOptions::new();
+            // A FIFO opened for writing too always has a writer left, itself, and so never
+            // ends.
+            #[cfg(target_os = "linux")]
+            options.read_write(tail);
+            return options.open_receiver(path).map(Opened::Fifo);
+        }
+    }
+    tokio::fs::File::open(path).await.map(Opened::File)
+}
+
+// Search the file, or the FIFO, at `path`.
+async fn search_path(path: PathBuf, args: Arc<Cli>, matcher: Arc<dyn Matcher>,
+    found: mpsc::Sender<Found>)
+{
+    let name: Arc<str> = path.display().to_string().into();
+    let (input, matcher) = (name.clone(), &*matcher);
+    let result = match open(&path, args.tail).await {
+        Ok(Opened::File(file)) if args.tail => {
+            search_stream(input, Tail { file, position: 0 }, &args, matcher, &found).await
+        }
+        Ok(Opened::File(file)) => search_stream(input, Reader(file), &args, matcher, &found).await,
+        #[cfg(unix)]
+        Ok(Opened::Fifo(fifo)) => search_stream(input, Reader(fifo), &args, matcher, &found).await,
+        Err(err) => Err(err.into()),
+    };
+    done(result.with_context(|| format!("Could not search {}", name)), &found).await;
+}
+
+// Search the body of `url`.
+async fn search_url(url: Arc<str>, args: Arc<Cli>, matcher: Arc<dyn Matcher>,
+    found: mpsc::Sender<Found>)
+{
+    let stream = Url::open(url.to_string(), args.read_buffer);
+    let result = search_stream(url.clone(), stream, &args, &*matcher, &found).await;
+    done(result.with_context(|| format!("Could not search {}", url)), &found).await;
+}
+
+// Hand how the search of an input ended to the printing thread.
+async fn done(result: AnyhowResult<Stats>, found: &mpsc::Sender<Found>) {
+    let _ = found.send(match result {
+        Ok(stats) => Found::Done(stats),
+        Err(err) => Found::Error(err),
+    }).await;
+}
+
+/// Search the lines of `stream` as they arrive, handing those that match to `found`. Lines
+/// end with the line terminator of `args`, and the last one also with the end of the input.
+/// Returns the totals of the input.
+async fn search_stream(input: Arc<str>, mut stream: impl ByteStream, args: &Cli,
+    matcher: &dyn Matcher, found: &mpsc::Sender<Found>) -> AnyhowResult<Stats>
+{
+    let terminator = crate::line_terminator(args);
+    let mut stats = Stats { searched: 1, ..Stats::default() };
+    // The lines read but not searched yet, the last of which may still be coming.
+    let mut buffer = Vec::new();
+    // Offset of `buffer` in the input.
+    let mut offset = 0;
+    let mut binary = None;
+    let mut matching_lines = 0;
+    loop {
+        buffer.reserve(args.read_buffer);
+        let read = stream.read(&mut buffer).await?;
+        // With `--null-data` NUL bytes are merely the line separators.
+        let binary = *binary.get_or_insert_with(|| {
+            let head = &buffer[..buffer.len().min(BINARY_CHECK_SIZE)];
+            !args.text && !args.null_data && head.contains(&0)
+        });
+        let mut start = 0;
+        loop {
+            let end = match memchr::memchr(terminator, &buffer[start..]) {
+                Some(length) => start + length,
+                None if read == 0 && start < buffer.len() => buffer.len(),
+                None => break,
+            };
+            let mut line = &buffer[start..end];
+            let line_offset = offset + start as u64;
+            start = (end + 1).min(buffer.len());
+            stats.lines += 1;
+            // Like `BufRead::lines`, a `\r\n` terminator is removed as a whole.
+            if terminator == b'\n' && line.last() == Some(&b'\r') {
+                line = &line[..line.len() - 1];
+            }
+            if !matcher.is_match(line) {
+                continue;
+            }
+            let result = if binary {
+                stats.binary += 1;
+                Found::Binary(input.clone())
+            } else {
+                let text = crate::decode(line, args)?.into_owned();
+                let spans = matcher.find_iter(text.as_bytes()).collect();
+                let number = stats.lines;
+                Found::Line { input: input.clone(), number, offset: line_offset, text, spans }
+            };
+            matching_lines += 1;
+            // The results are no longer wanted once the printing thread is gone.
+            let done = binary || args.max_count.is_some_and(|max| matching_lines >= max);
+            if found.send(result).await.is_err() || done {
+                stats.bytes = offset + start as u64;
+                return Ok(stats);
+            }
+        }
+        offset += start as u64;
+        buffer.drain(..start);
+        if read == 0 {
+            stats.bytes = offset;
+            return Ok(stats);
+        }
+    }
+}
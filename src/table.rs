@@ -0,0 +1,67 @@
+//! Matches as rows of CSV or TSV, for `--format csv` and `--format tsv`.
+
+use std::io::{Result, Write};
+
+use crate::event;
+
+/// The names of the columns, written as the first row.
+const HEADER: [&str; 5] = ["path", "line", "column", "matched_text", "line_text"];
+
+/// Which flavor of table to write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Table {
+    /// Comma-separated, quoted as in RFC 4180.
+    Csv,
+    /// Tab-separated. Tabs, line breaks and backslashes are escaped as `\t`, `\n`, `\r` and
+    /// `\\`, since TSV has no quoting.
+    Tsv,
+}
+
+impl Table {
+    pub fn write_header(self, writer: &mut impl Write) -> Result<()> {
+        self.write_row(writer, &HEADER)
+    }
+
+    /// Write a row for each occurrence of the pattern in the line of `event`.
+    pub fn write_match(self, writer: &mut impl Write, event: &event::Match) -> Result<()> {
+        let line = event.line_number.to_string();
+        for submatch in &event.submatches {
+            let column = (submatch.start + 1).to_string();
+            self.write_row(writer, &[event.path, &line, &column, submatch.text, event.text])?;
+        }
+        Ok(())
+    }
+
+    fn write_row(self, writer: &mut impl Write, fields: &[&str]) -> Result<()> {
+        for (index, field) in fields.iter().enumerate() {
+            if index > 0 {
+                writer.write_all(if self == Table::Csv { b"," } else { b"\t" })?;
+            }
+            match self {
+                Table::Csv => write_csv_field(writer, field)?,
+                Table::Tsv => write_tsv_field(writer, field)?,
+            }
+        }
+        writeln!(writer)
+    }
+}
+
+fn write_csv_field(writer: &mut impl Write, field: &str) -> Result<()> {
+    if !field.contains(&[',', '"', '\n', '\r'][..]) {
+        return write!(writer, "{}", field);
+    }
+    write!(writer, "\"{}\"", field.replace('"', "\"\""))
+}
+
+fn write_tsv_field(writer: &mut impl Write, field: &str) -> Result<()> {
+    for character in field.chars() {
+        match character {
+            '\t' => write!(writer, "\\t")?,
+            '\n' => write!(writer, "\\n")?,
+            '\r' => write!(writer, "\\r")?,
+            '\\' => write!(writer, "\\\\")?,
+            character => write!(writer, "{}", character)?,
+        }
+    }
+    Ok(())
+}
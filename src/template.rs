@@ -0,0 +1,163 @@
+//! Output formatted by a user template, for `--format '{path}:{line_no}\t{text}'`.
+
+use anyhow::{anyhow, Result as AnyhowResult};
+
+use std::io::{Result, Write};
+
+use crate::matcher::{Groups, Matcher};
+
+/// A parsed `--format` template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Path,
+    LineNumber,
+    /// 1-based, in bytes.
+    Column,
+    /// Offset of the first byte of the line in the input.
+    Offset,
+    Match,
+    Text,
+    Group(usize),
+    /// A named group, until `resolve` replaces it with its index.
+    NamedGroup(String),
+}
+
+/// What the placeholders of a template stand for, for one line or occurrence.
+pub struct Fields<'a> {
+    pub path: &'a str,
+    pub line_number: u64,
+    pub offset: u64,
+    pub text: &'a str,
+    /// The occurrence of the pattern and its capture groups, whole occurrence first.
+    pub groups: Groups,
+}
+
+/// Parse a template. Placeholders are `{path}`, `{line_no}`, `{column}`, `{offset}`,
+/// `{match}`, `{text}` for the whole line, and capture groups by number or name, e.g. `{1}`.
+/// `{{` and `}}` are literal braces, and `\t`, `\n` and `\\` are escapes.
+pub fn parse(template: &str) -> AnyhowResult<Template> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut characters = template.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' => match characters.next() {
+                Some('t') => literal.push('\t'),
+                Some('n') => literal.push('\n'),
+                Some('\\') => literal.push('\\'),
+                other => return Err(anyhow!("Invalid escape in format: \\{}",
+                    other.map(String::from).unwrap_or_default())),
+            },
+            '}' if characters.as_str().starts_with('}') => {
+                characters.next();
+                literal.push('}');
+            }
+            '{' if characters.as_str().starts_with('{') => {
+                characters.next();
+                literal.push('{');
+            }
+            '{' => {
+                let rest = characters.as_str();
+                let end = rest.find('}')
+                    .ok_or_else(|| anyhow!("Unclosed placeholder in format: {{{}", rest))?;
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(placeholder(&rest[..end])?);
+                characters = rest[end + 1..].chars();
+            }
+            '}' => return Err(anyhow!("Unmatched }} in format, write }}}} for a literal one")),
+            character => literal.push(character),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+    Ok(Template { parts })
+}
+
+fn placeholder(name: &str) -> AnyhowResult<Part> {
+    let part = match name {
+        "path" => Part::Path,
+        "line_no" => Part::LineNumber,
+        "column" => Part::Column,
+        "offset" => Part::Offset,
+        "match" => Part::Match,
+        "text" => Part::Text,
+        "" => return Err(anyhow!("Empty placeholder in format")),
+        name => match name.parse() {
+            Ok(index) => Part::Group(index),
+            Err(_) => Part::NamedGroup(name.to_string()),
+        },
+    };
+    Ok(part)
+}
+
+impl Template {
+    /// Replace named capture groups with their index in the pattern of `matcher`, checking
+    /// that every group exists.
+    pub fn resolve(mut self, matcher: &dyn Matcher) -> AnyhowResult<Template> {
+        for part in &mut self.parts {
+            match part {
+                Part::NamedGroup(name) => {
+                    let index = matcher.group_index(name).ok_or_else(|| anyhow!(
+                        "Unknown placeholder in format: {{{}}} (not a field nor a named group \
+                         of the pattern)", name))?;
+                    *part = Part::Group(index);
+                }
+                Part::Group(index) if *index >= matcher.group_count() => {
+                    return Err(anyhow!("The pattern has no capture group {}", index));
+                }
+                _ => {}
+            }
+        }
+        Ok(self)
+    }
+
+    /// Whether the template is written once for each occurrence of the pattern rather than
+    /// once for each matching line, because it refers to the occurrence.
+    pub fn per_match(&self) -> bool {
+        self.parts.iter().any(|part| {
+            matches!(part, Part::Column | Part::Match | Part::Group(_) | Part::NamedGroup(_))
+        })
+    }
+
+    /// Whether the template refers to capture groups other than the whole occurrence.
+    pub fn has_groups(&self) -> bool {
+        self.parts.iter().any(|part| match part {
+            Part::Group(index) => *index > 0,
+            Part::NamedGroup(_) => true,
+            _ => false,
+        })
+    }
+
+    /// Write the template filled in with `fields`, followed by a line break.
+    pub fn write(&self, writer: &mut impl Write, fields: &Fields) -> Result<()> {
+        let group = |index: usize| fields.groups.get(index).cloned().flatten();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => write!(writer, "{}", literal)?,
+                Part::Path => write!(writer, "{}", fields.path)?,
+                Part::LineNumber => write!(writer, "{}", fields.line_number)?,
+                Part::Column => write!(writer, "{}", group(0).map_or(0, |span| span.start) + 1)?,
+                Part::Offset => write!(writer, "{}", fields.offset)?,
+                Part::Text => write!(writer, "{}", fields.text)?,
+                Part::Match | Part::Group(0) => {
+                    write!(writer, "{}", group(0).map_or("", |span| &fields.text[span]))?;
+                }
+                // A group that did not take part in the match is left empty.
+                Part::Group(index) => {
+                    write!(writer, "{}", group(*index).map_or("", |span| &fields.text[span]))?;
+                }
+                Part::NamedGroup(_) => unreachable!("named groups are resolved first"),
+            }
+        }
+        writeln!(writer)
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::{anyhow, Context, Result as AnyhowResult};
+
+use std::collections::BTreeMap;
+
+// File types known out of the box, as a name and the file name globs it covers.
+// Loosely follows the names ripgrep uses so `--type` feels familiar.
+const DEFAULT_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"]),
+    ("cs", &["*.cs"]),
+    ("css", &["*.css", "*.scss"]),
+    ("csv", &["*.csv", "*.tsv"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.jsx", "*.mjs", "*.cjs"]),
+    ("json", &["*.json"]),
+    ("log", &["*.log"]),
+    ("make", &["Makefile", "makefile", "GNUmakefile", "*.mk"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("xml", &["*.xml"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// Maps file type names (`rust`, `md`, ...) to the globs that select their files.
+pub struct TypeRegistry {
+    types: BTreeMap<String, Vec<glob::Pattern>>,
+}
+
+impl TypeRegistry {
+    /// Creates a registry holding the built-in types.
+    pub fn new() -> TypeRegistry {
+        let mut types = BTreeMap::new();
+        for (name, globs) in DEFAULT_TYPES {
+            let globs = globs.iter()
+                .map(|glob| glob::Pattern::new(glob).expect("built-in type globs are valid"))
+                .collect();
+            types.insert(name.to_string(), globs);
+        }
+        TypeRegistry { types }
+    }
+
+    /// Adds a glob to a type from a `name:glob` definition, creating the type if needed.
+    pub fn add(&mut self, definition: &str) -> AnyhowResult<()> {
+        let (name, glob) = definition.split_once(':')
+            .filter(|(name, glob)| !name.is_empty() && !glob.is_empty())
+            .ok_or_else(|| anyhow!("Invalid type definition {:?}, expected name:glob", definition))?;
+        let glob = glob::Pattern::new(glob)
+            .with_context(|| format!("Invalid glob in type definition {:?}", definition))?;
+        self.types.entry(name.to_string()).or_default().push(glob);
+        Ok(())
+    }
+
+    /// Builds the matcher for the selected (`--type`) and negated (`--type-not`) type names.
+    pub fn matcher(&self, select: &[String], negate: &[String]) -> AnyhowResult<TypeMatcher> {
+        Ok(TypeMatcher { select: self.globs(select)?, negate: self.globs(negate)? })
+    }
+
+    fn globs(&self, names: &[String]) -> AnyhowResult<Vec<glob::Pattern>> {
+        let mut globs = Vec::new();
+        for name in names {
+            let type_globs = self.types.get(name)
+                .ok_or_else(|| anyhow!("Unrecognized file type: {}", name))?;
+            globs.extend(type_globs.iter().cloned());
+        }
+        Ok(globs)
+    }
+}
+
+/// Decides which file names pass the `--type`/`--type-not` selection.
+pub struct TypeMatcher {
+    select: Vec<glob::Pattern>,
+    negate: Vec<glob::Pattern>,
+}
+
+impl TypeMatcher {
+    /// Whether any type was selected or negated. Without one every file passes.
+    pub fn is_empty(&self) -> bool {
+        self.select.is_empty() && self.negate.is_empty()
+    }
+
+    pub fn is_match(&self, file_name: &str) -> bool {
+        if self.negate.iter().any(|glob| glob.matches(file_name)) {
+            return false;
+        }
+        self.select.is_empty() || self.select.iter().any(|glob| glob.matches(file_name))
+    }
+}
@@ -0,0 +1,328 @@
+//! Reading small files in batches through io_uring, on Linux with the `io-uring` feature: a
+//! single system call reads a whole batch of files, instead of a couple of `read` calls for
+//! each of them. Without the feature, on other systems, or where the kernel does not allow
+//! io_uring, there is no ring and the files are read one by one as usual.
+
+use std::fs::File;
+use std::path::Path;
+
+/// Number of files read in one batch.
+pub const BATCH_FILES: usize = 32;
+
+/// Read the files of `paths` that are regular files of at most `max_size` bytes in one batch
+/// through `ring`, and return their contents. The other files, and those that could not be
+/// read, get `None`: they are to be opened and read the usual way, which reports the errors.
+pub fn read_small_files(ring: &mut Ring, paths: &[&Path], max_size: u64) -> Vec<Option<Vec<u8>>> {
+    let files: Vec<_> = paths.iter()
+        .map(|path| {
+            let file = File::open(path).ok()?;
+            let metadata = file.metadata().ok()?;
+            let small = metadata.is_file() && metadata.len() <= max_size;
+            Some((file, metadata.len())).filter(|_| small)
+        })
+        .collect();
+    let opened: Vec<_> = files.iter().flatten().collect();
+    let mut contents = ring.read(&opened).into_iter();
+    files.iter()
+        .map(|file| file.as_ref().and_then(|_| contents.next().flatten()))
+        .collect()
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use linux::Ring;
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod linux {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr::NonNull;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    // From <linux/io_uring.h>.
+    const IORING_OP_READ: u8 = 22;
+    const IORING_ENTER_GETEVENTS: u32 = 1;
+    const IORING_FEAT_SINGLE_MMAP: u32 = 1;
+    const IORING_OFF_SQ_RING: libc::off_t = 0;
+    const IORING_OFF_CQ_RING: libc::off_t = 0x800_0000;
+    const IORING_OFF_SQES: libc::off_t = 0x1000_0000;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SqringOffsets {
+        head: u32,
+        tail: u32,
+        ring_mask: u32,
+        ring_entries: u32,
+        flags: u32,
+        dropped: u32,
+        array: u32,
+        resv1: u32,
+        user_addr: u64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CqringOffsets {
+        head: u32,
+        tail: u32,
+        ring_mask: u32,
+        ring_entries: u32,
+        overflow: u32,
+        cqes: u32,
+        flags: u32,
+        resv1: u32,
+        user_addr: u64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Params {
+        sq_entries: u32,
+        cq_entries: u32,
+        flags: u32,
+        sq_thread_cpu: u32,
+        sq_thread_idle: u32,
+        features: u32,
+        wq_fd: u32,
+        resv: [u32; 3],
+        sq_off: SqringOffsets,
+        cq_off: CqringOffsets,
+    }
+
+    // A submission queue entry, laid out for the reads only.
+    #[repr(C)]
+    #[derive(Default)]
+    struct Sqe {
+        opcode: u8,
+        flags: u8,
+        ioprio: u16,
+        fd: i32,
+        off: u64,
+        addr: u64,
+        len: u32,
+        rw_flags: u32,
+        user_data: u64,
+        buf_index: u16,
+        personality: u16,
+        splice_fd_in: i32,
+        addr3: u64,
+        pad: u64,
+    }
+
+    #[repr(C)]
+    struct Cqe {
+        user_data: u64,
+        res: i32,
+        flags: u32,
+    }
+
+    // A memory mapping of the ring, unmapped when dropped.
+    struct Mapping {
+        pointer: NonNull<u8>,
+        length: usize,
+    }
+
+    impl Mapping {
+        fn new(fd: i32, length: usize, offset: libc::off_t) -> Option<Mapping> {
+            // SAFETY: a fresh shared mapping of the ring, which the kernel sized for `length`.
+            let pointer = unsafe {
+                libc::mmap(std::ptr::null_mut(), length, libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED | libc::MAP_POPULATE, fd, offset)
+            };
+            if pointer == libc::MAP_FAILED {
+                return None;
+            }
+            Some(Mapping { pointer: NonNull::new(pointer.cast())?, length })
+        }
+
+        // The field at `offset` in the mapping, as given by the kernel in `Params`.
+        fn at<T>(&self, offset: u32) -> *mut T {
+            // SAFETY: the kernel's offsets are within the mapping.
+            unsafe { self.pointer.as_ptr().add(offset as usize).cast() }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: mapped in `new`, and nothing points into it any more.
+            unsafe { libc::munmap(self.pointer.as_ptr().cast(), self.length) };
+        }
+    }
+
+    /// An io_uring instance, for the reads of one thread.
+    pub struct Ring {
+        fd: i32,
+        params: Params,
+        // The submission ring, and the completion ring if the kernel maps it separately.
+        sq: Mapping,
+        cq: Option<Mapping>,
+        sqes: Mapping,
+        // Set if a batch failed in a way that leaves reads in flight: the ring is not used
+        // again.
+        broken: bool,
+    }
+
+    impl Ring {
+        /// A ring, or `None` if the kernel does not provide io_uring, or forbids it, as
+        /// container sandboxes often do.
+        pub fn new() -> Option<Ring> {
+            let mut params = Params::default();
+            let entries = super::BATCH_FILES as u32;
+            // SAFETY: `params` is the `struct io_uring_params` the call fills in.
+            let fd = unsafe {
+                libc::syscall(libc::SYS_io_uring_setup, entries, &mut params as *mut Params)
+            } as i32;
+            if fd < 0 {
+                return None;
+            }
+            let sq_length = params.sq_off.array as usize + params.sq_entries as usize * 4;
+            let cq_length = params.cq_off.cqes as usize
+                + params.cq_entries as usize * std::mem::size_of::<Cqe>();
+            let single = params.features & IORING_FEAT_SINGLE_MMAP != 0;
+            let mapped = (|| {
+                let sq_length = if single { sq_length.max(cq_length) } else { sq_length };
+                let sq = Mapping::new(fd, sq_length, IORING_OFF_SQ_RING)?;
+                let cq = if single {
+                    None
+                } else {
+                    Some(Mapping::new(fd, cq_length, IORING_OFF_CQ_RING)?)
+                };
+                let sqes_length = params.sq_entries as usize * std::mem::size_of::<Sqe>();
+                let sqes = Mapping::new(fd, sqes_length, IORING_OFF_SQES)?;
+                Some((sq, cq, sqes))
+            })();
+            match mapped {
+                Some((sq, cq, sqes)) => Some(Ring { fd, params, sq, cq, sqes, broken: false }),
+                None => {
+                    // SAFETY: the ring was just created and is not used.
+                    unsafe { libc::close(fd) };
+                    None
+                }
+            }
+        }
+
+        fn cq(&self) -> &Mapping {
+            self.cq.as_ref().unwrap_or(&self.sq)
+        }
+
+        fn atomic(mapping: &Mapping, offset: u32) -> &AtomicU32 {
+            // SAFETY: the ring's head and tail are aligned `u32`s shared with the kernel.
+            unsafe { &*mapping.at::<AtomicU32>(offset) }
+        }
+
+        /// Read each of `files`, given with their sizes, from the start. Reads that fail or
+        /// find the file larger than its size give `None`.
+        pub fn read(&mut self, files: &[&(File, u64)]) -> Vec<Option<Vec<u8>>> {
+            let mut contents: Vec<_> = files.iter()
+                // One byte more than the size tells whether the file grew since.
+                .map(|(_, size)| Some(vec![0; *size as usize + 1]))
+                .collect();
+            for start in (0..files.len()).step_by(self.params.sq_entries as usize) {
+                let end = files.len().min(start + self.params.sq_entries as usize);
+                if self.broken || !self.read_batch(&files[start..end], &mut contents[start..end]) {
+                    contents[start..].iter_mut().for_each(|content| *content = None);
+                    break;
+                }
+            }
+            contents
+        }
+
+        // Submit the reads of `files` into `contents` and wait for them all. Returns false if
+        // the ring failed, leaving `contents` in use by the kernel, in which case the buffers
+        // are leaked rather than freed under its feet.
+        fn read_batch(&mut self, files: &[&(File, u64)], contents: &mut [Option<Vec<u8>>])
+            -> bool
+        {
+            let sq_off = &self.params.sq_off;
+            let mask = self.params.sq_entries - 1;
+            let tail = Ring::atomic(&self.sq, sq_off.tail);
+            let mut position = tail.load(Ordering::Acquire);
+            for (index, ((file, _), content)) in files.iter().zip(contents.iter_mut()).enumerate() {
+                let buffer = content.as_mut().expect("every file has a buffer");
+                let slot = position & mask;
+                let sqe = Sqe {
+                    opcode: IORING_OP_READ,
+                    fd: file.as_raw_fd(),
+                    addr: buffer.as_mut_ptr() as u64,
+                    len: buffer.len() as u32,
+                    user_data: index as u64,
+                    ..Sqe::default()
+                };
+                // SAFETY: `slot` is within the rings, and the kernel only reads the entries
+                // and array slots past the tail once the tail is moved past them.
+                unsafe {
+                    self.sqes.at::<Sqe>(0).add(slot as usize).write(sqe);
+                    self.sq.at::<u32>(sq_off.array).add(slot as usize).write(slot);
+                }
+                position = position.wrapping_add(1);
+            }
+            tail.store(position, Ordering::Release);
+
+            let mut submitted = 0;
+            let mut completed = 0;
+            let cq_off = &self.params.cq_off;
+            while completed < files.len() {
+                // SAFETY: a plain system call on the ring.
+                let entered = unsafe {
+                    libc::syscall(libc::SYS_io_uring_enter, self.fd,
+                        (files.len() - submitted) as u32, 1u32, IORING_ENTER_GETEVENTS,
+                        std::ptr::null::<libc::sigset_t>(), 0usize)
+                };
+                if entered < 0 {
+                    let errno = std::io::Error::last_os_error().raw_os_error();
+                    if matches!(errno, Some(libc::EINTR) | Some(libc::EAGAIN) | Some(libc::EBUSY)) {
+                        continue;
+                    }
+                    self.broken = true;
+                    contents.iter_mut().for_each(|content| {
+                        std::mem::forget(content.take());
+                    });
+                    return false;
+                }
+                submitted += entered as usize;
+                let head = Ring::atomic(self.cq(), cq_off.head);
+                let cq_tail = Ring::atomic(self.cq(), cq_off.tail).load(Ordering::Acquire);
+                let mut position = head.load(Ordering::Acquire);
+                while position != cq_tail {
+                    let slot = position & (self.params.cq_entries - 1);
+                    // SAFETY: the entries between the head and the tail are filled in.
+                    let cqe = unsafe { self.cq().at::<Cqe>(cq_off.cqes).add(slot as usize).read() };
+                    let content = &mut contents[cqe.user_data as usize];
+                    match content.as_mut() {
+                        Some(buffer) if cqe.res >= 0 && (cqe.res as usize) < buffer.len() => {
+                            buffer.truncate(cqe.res as usize);
+                        }
+                        _ => *content = None,
+                    }
+                    position = position.wrapping_add(1);
+                    completed += 1;
+                }
+                head.store(position, Ordering::Release);
+            }
+            true
+        }
+    }
+
+    impl Drop for Ring {
+        fn drop(&mut self) {
+            // SAFETY: the ring is closed once, after its last batch. The mappings are
+            // dropped after this, which the kernel allows.
+            unsafe { libc::close(self.fd) };
+        }
+    }
+}
+
+/// Stands in for the ring where io_uring is not built in: there never is one.
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+pub enum Ring {}
+
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+impl Ring {
+    pub fn new() -> Option<Ring> {
+        None
+    }
+
+    fn read(&mut self, _files: &[&(File, u64)]) -> Vec<Option<Vec<u8>>> {
+        match *self {}
+    }
+}
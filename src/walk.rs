@@ -0,0 +1,580 @@
+//! Finding the files to search: the roots given, and whatever the filters keep below them.
+//! Searches, `--estimate`, `--files` and `index build` all walk directories with `Walker`.
+
+use anyhow::{Context, Result as AnyhowResult};
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{DirEntry, FileType, Metadata};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+
+use crate::globs::GlobFilter;
+use crate::matcher::Matcher;
+use crate::types::TypeMatcher;
+use crate::{git, index, Cli, Sort};
+
+/// Decides whether an entry discovered during recursion is kept.
+/// Rejecting a directory also prevents the walker from descending into it.
+/// Filters may be called from several threads at once when walking in parallel.
+pub type Filter = Box<dyn Fn(&Entry) -> bool + Send + Sync>;
+
+/// An entry discovered while descending, as handed to the filters.
+pub struct Entry {
+    dir_entry: DirEntry,
+    is_dir: bool,
+    is_symlink: bool,
+}
+
+impl Entry {
+    pub fn path(&self) -> PathBuf {
+        self.dir_entry.path()
+    }
+
+    pub fn file_name(&self) -> OsString {
+        self.dir_entry.file_name()
+    }
+
+    /// Whether the walker would descend into the entry. Symlinks are resolved when followed.
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Metadata of the entry, or of the file it links to for followed symlinks.
+    pub fn metadata(&self) -> std::io::Result<Metadata> {
+        if self.is_symlink {
+            std::fs::metadata(self.dir_entry.path())
+        } else {
+            self.dir_entry.metadata()
+        }
+    }
+}
+
+// A path waiting to be visited by the walker.
+struct Pending {
+    path: PathBuf,
+    is_dir: bool,
+    // Number of directories between the path and the root it was found under, 0 for roots.
+    depth: usize,
+}
+
+// What decides which entries of a directory are visited.
+#[derive(Default)]
+struct Rules {
+    filters: Vec<Filter>,
+    follow_links: bool,
+    include_special: bool,
+    sort_by_path: bool,
+}
+
+// The entries of a directory that are to be visited, and how many were left out.
+struct Listing {
+    children: Vec<Pending>,
+    ignored: usize,
+}
+
+/// Depth-first iterator over every file reachable from a list of root paths.
+pub struct Walker {
+    // Paths that still need to be visited.
+    // The roots are pushed first and discovered entries are pushed on top of them.
+    stack: Vec<Pending>,
+    rules: Rules,
+    max_depth: Option<usize>,
+    threads: usize,
+    // Number of entries left out so far.
+    ignored: usize,
+    // Directories already descended into. Only tracked when following symlinks, since that is
+    // the only way the same directory can be reached twice and end up in a cycle.
+    visited: HashSet<DirId>,
+    // The threads walking the directories, once they are started.
+    parallel: Option<Parallel>,
+}
+
+impl Walker {
+    pub fn new(roots: Vec<PathBuf>) -> Walker {
+        // Reversed so that the roots are visited in the order they were given.
+        let stack = roots.into_iter().rev().map(|root| {
+            // `Path::is_dir` follows symlinks: a link given explicitly is always dereferenced,
+            // only links discovered while descending depend on `follow_links`.
+            let is_dir = root.is_dir();
+            Pending { path: root, is_dir, depth: 0 }
+        }).collect();
+        Walker {
+            stack,
+            rules: Rules::default(),
+            max_depth: None,
+            threads: 1,
+            ignored: 0,
+            visited: HashSet::new(),
+            parallel: None,
+        }
+    }
+
+    /// Descend into symlinked directories and search symlinked files found while descending.
+    /// Roots are resolved either way, as in grep and ripgrep.
+    pub fn follow_links(mut self, follow_links: bool) -> Walker {
+        self.rules.follow_links = follow_links;
+        self
+    }
+
+    /// Hand out FIFOs, sockets and device files found while descending. They are skipped by
+    /// default: reading a FIFO nobody writes to blocks forever, and devices may never end.
+    pub fn include_special(mut self, include_special: bool) -> Walker {
+        self.rules.include_special = include_special;
+        self
+    }
+
+    /// Visit the roots, and the entries of every directory, sorted by path.
+    pub fn sort_by_path(mut self, sort_by_path: bool) -> Walker {
+        self.rules.sort_by_path = sort_by_path;
+        if sort_by_path {
+            // Reversed, like every list pushed on the stack.
+            self.stack.sort_by(|a, b| b.path.cmp(&a.path));
+        }
+        self
+    }
+
+    /// Do not descend more than `max_depth` directories below the roots.
+    /// A depth of 0 only searches the files given as roots.
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Walker {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Read directories on `threads` threads. Files then come out in no particular order, so
+    /// walks sorted by path stay on one thread.
+    pub fn threads(mut self, threads: usize) -> Walker {
+        self.threads = threads;
+        self
+    }
+
+    /// Registers a filter that is consulted for every entry found while descending.
+    /// Roots are never filtered: a path given explicitly is always searched.
+    pub fn filter<F>(mut self, filter: F) -> Walker
+        where F: Fn(&Entry) -> bool + Send + Sync + 'static
+    {
+        self.rules.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Leave out hidden files and directories, unless `hidden` is set.
+    pub fn hidden(self, hidden: bool) -> Walker {
+        if hidden {
+            self
+        } else {
+            self.filter(|entry| !is_hidden(entry))
+        }
+    }
+
+    /// Only keep the files of the types `types` selects. Directories are always descended
+    /// into, since files of any type may be below them.
+    pub fn types(self, types: TypeMatcher) -> Walker {
+        if types.is_empty() {
+            return self;
+        }
+        self.filter(move |entry| {
+            entry.is_dir() || types.is_match(&entry.file_name().to_string_lossy())
+        })
+    }
+
+    /// Keep or leave out entries according to the `--include` and `--exclude` globs.
+    pub fn globs(self, globs: GlobFilter) -> Walker {
+        if globs.is_empty() {
+            return self;
+        }
+        self.filter(move |entry| globs.is_match(entry))
+    }
+
+    /// Leave out files larger than `max_filesize` bytes. Files whose size cannot be read are
+    /// kept, opening them will report the problem.
+    pub fn max_filesize(self, max_filesize: Option<u64>) -> Walker {
+        let max_filesize = match max_filesize {
+            Some(max_filesize) => max_filesize,
+            None => return self,
+        };
+        self.filter(move |entry| {
+            entry.is_dir()
+                || entry.metadata().map_or(true, |metadata| metadata.len() <= max_filesize)
+        })
+    }
+
+    /// Number of entries found while descending that were left out so far: symlinks not
+    /// followed, special files and entries rejected by the filters. A directory counts once,
+    /// whatever it contains.
+    pub fn ignored(&self) -> usize {
+        let parallel = self.parallel.as_ref().map(|parallel| &parallel.shared.ignored);
+        self.ignored + parallel.map_or(0, |ignored| ignored.load(Ordering::Relaxed))
+    }
+
+    // Hand the rest of the walk to `self.threads` threads.
+    fn start_threads(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        let shared = Arc::new(Shared {
+            rules: std::mem::take(&mut self.rules),
+            max_depth: self.max_depth,
+            queue: Mutex::new(Queue { pending: std::mem::take(&mut self.stack), busy: 0 }),
+            ready: Condvar::new(),
+            visited: Mutex::new(std::mem::take(&mut self.visited)),
+            ignored: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+        });
+        for _ in 0..self.threads {
+            let (shared, sender) = (Arc::clone(&shared), sender.clone());
+            std::thread::spawn(move || shared.walk(&sender));
+        }
+        self.parallel = Some(Parallel { receiver, shared });
+    }
+}
+
+impl Rules {
+    // The entries of `dir`, found `depth` directories below a root, that are to be visited.
+    fn read_dir(&self, dir: &Path, depth: usize) -> AnyhowResult<Listing> {
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Could not read directory: {:?}", dir))?;
+        let mut children = Vec::new();
+        let mut ignored = 0;
+        for entry in entries {
+            let entry = entry.with_context(|| format!("Could not read directory: {:?}", dir))?;
+            // `DirEntry::file_type` does not follow symlinks. Links found while descending are
+            // skipped altogether unless `follow_links` is set.
+            let file_type = entry.file_type()
+                .with_context(|| format!("Could not read file type: {:?}", entry.path()))?;
+            let path = entry.path();
+            let is_symlink = file_type.is_symlink();
+            let (is_dir, is_special) = if is_symlink {
+                if !self.follow_links {
+                    ignored += 1;
+                    continue;
+                }
+                match std::fs::metadata(&path) {
+                    Ok(metadata) => (metadata.is_dir(), is_special(&metadata.file_type())),
+                    // A broken link is handed out as a file and fails when it gets opened.
+                    Err(_) => (false, false),
+                }
+            } else {
+                (file_type.is_dir(), is_special(&file_type))
+            };
+            if is_special && !self.include_special {
+                ignored += 1;
+                continue;
+            }
+            let entry = Entry { dir_entry: entry, is_dir, is_symlink };
+            if !self.filters.iter().all(|filter| filter(&entry)) {
+                ignored += 1;
+                continue;
+            }
+            children.push(Pending { path, is_dir, depth: depth + 1 });
+        }
+        if self.sort_by_path {
+            children.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        Ok(Listing { children, ignored })
+    }
+}
+
+impl Iterator for Walker {
+    type Item = AnyhowResult<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.threads > 1 && !self.rules.sort_by_path && self.parallel.is_none() {
+            self.start_threads();
+        }
+        if let Some(parallel) = &self.parallel {
+            // The threads are done once they have all dropped their sender.
+            return parallel.receiver.recv().ok();
+        }
+        while let Some(Pending { path, is_dir, depth }) = self.stack.pop() {
+            if !is_dir {
+                return Some(Ok(path));
+            }
+            if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                continue;
+            }
+            if self.rules.follow_links {
+                match dir_id(&path) {
+                    // Already seen: a symlink cycle or a second link to the same directory.
+                    Ok(id) => if !self.visited.insert(id) {
+                        continue;
+                    },
+                    Err(err) => {
+                        return Some(Err(err)
+                            .with_context(|| format!("Could not read directory: {:?}", path)));
+                    }
+                }
+            }
+            match self.rules.read_dir(&path, depth) {
+                Ok(listing) => {
+                    self.ignored += listing.ignored;
+                    // Reversed so that popping from the stack visits the entries in
+                    // `read_dir` order.
+                    self.stack.extend(listing.children.into_iter().rev());
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        None
+    }
+}
+
+// The threads of a walk in parallel, as seen from the walker.
+struct Parallel {
+    receiver: mpsc::Receiver<AnyhowResult<PathBuf>>,
+    shared: Arc<Shared>,
+}
+
+// What the threads of a walk in parallel share.
+struct Shared {
+    rules: Rules,
+    max_depth: Option<usize>,
+    queue: Mutex<Queue>,
+    // Signalled when directories are queued, or when the last busy thread is done.
+    ready: Condvar,
+    visited: Mutex<HashSet<DirId>>,
+    ignored: AtomicUsize,
+    // Set once nobody takes the files found anymore.
+    stopped: AtomicBool,
+}
+
+// The directories waiting to be read, and the number of threads reading one, which may
+// queue more.
+struct Queue {
+    pending: Vec<Pending>,
+    busy: usize,
+}
+
+impl Shared {
+    // Read directories off the queue until there are none left, sending the files found.
+    fn walk(&self, sender: &mpsc::Sender<AnyhowResult<PathBuf>>) {
+        while let Some(pending) = self.next_pending() {
+            let dirs = self.visit(pending, sender);
+            let mut queue = self.queue.lock().expect("the queue is never poisoned");
+            queue.busy -= 1;
+            if self.stopped.load(Ordering::Relaxed) {
+                queue.pending.clear();
+            } else {
+                queue.pending.extend(dirs);
+            }
+            self.ready.notify_all();
+        }
+    }
+
+    // Wait for a path to visit. `None` once the queue is empty and no thread can add to it.
+    fn next_pending(&self) -> Option<Pending> {
+        let mut queue = self.queue.lock().expect("the queue is never poisoned");
+        loop {
+            if let Some(pending) = queue.pending.pop() {
+                queue.busy += 1;
+                return Some(pending);
+            }
+            if queue.busy == 0 {
+                return None;
+            }
+            queue = self.ready.wait(queue).expect("the queue is never poisoned");
+        }
+    }
+
+    // Send `pending` if it is a file, or the files of the directory. Returns the directories
+    // to visit next.
+    fn visit(&self, pending: Pending, sender: &mpsc::Sender<AnyhowResult<PathBuf>>)
+        -> Vec<Pending>
+    {
+        let Pending { path, is_dir, depth } = pending;
+        let send = |result| {
+            if sender.send(result).is_err() {
+                self.stopped.store(true, Ordering::Relaxed);
+            }
+        };
+        if !is_dir {
+            send(Ok(path));
+            return Vec::new();
+        }
+        if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return Vec::new();
+        }
+        if self.rules.follow_links {
+            match dir_id(&path) {
+                Ok(id) => {
+                    let mut visited = self.visited.lock().expect("the set is never poisoned");
+                    if !visited.insert(id) {
+                        return Vec::new();
+                    }
+                }
+                Err(err) => {
+                    send(Err(err).with_context(|| format!("Could not read directory: {:?}", path)));
+                    return Vec::new();
+                }
+            }
+        }
+        let listing = match self.rules.read_dir(&path, depth) {
+            Ok(listing) => listing,
+            Err(err) => {
+                send(Err(err));
+                return Vec::new();
+            }
+        };
+        self.ignored.fetch_add(listing.ignored, Ordering::Relaxed);
+        let (dirs, files): (Vec<_>, Vec<_>) =
+            listing.children.into_iter().partition(|child| child.is_dir);
+        for file in files {
+            send(Ok(file.path));
+        }
+        dirs
+    }
+}
+
+/// The walker yielding the files a search with `args` reads, with every traversal option
+/// applied. `matcher` narrows down the files of an indexed search.
+pub fn from_args(args: &Cli, matcher: &dyn Matcher) -> AnyhowResult<Walker> {
+    let walker = if args.indexed {
+        Walker::new(index::candidates(args, matcher)?).max_depth(Some(0))
+    } else if let Some(files_from) = &args.files_from {
+        file_list(files_from, b'\n')?
+    } else if let Some(files_from0) = &args.files_from0 {
+        file_list(files_from0, b'\0')?
+    } else {
+        Walker::new(args.path.iter().cloned().collect())
+            .max_depth(args.max_depth)
+    };
+    let mut walker = walker.follow_links(args.follow)
+        .include_special(args.include_special)
+        // Also applied for the other orders, so that files that tie are ordered by path.
+        .sort_by_path(args.sort != Sort::None)
+        .threads(crate::threads(args))
+        .hidden(args.hidden);
+    // `--editor-mode` does not build the table of types just to check `--type-add`.
+    if !args.editor_mode || !args.types.is_empty() || !args.types_not.is_empty() {
+        let mut type_registry = crate::types::TypeRegistry::new();
+        for definition in &args.type_add {
+            type_registry.add(definition)?;
+        }
+        walker = walker.types(type_registry.matcher(&args.types, &args.types_not)?);
+    }
+    if args.git_tracked {
+        let roots: Vec<_> = args.path.iter().cloned().collect();
+        let tracked = git::TrackedFiles::new(&roots)?;
+        walker = walker.filter(move |entry| tracked.contains(entry));
+    }
+    if let Some(output) = &args.output {
+        // Searching the results as they are written would never end.
+        if let Ok(output) = output.canonicalize() {
+            walker = walker.filter(move |entry| {
+                entry.file_name() != output.file_name().unwrap_or_default()
+                    || entry.path().canonicalize().map_or(true, |path| path != output)
+            });
+        }
+    }
+    Ok(walker.globs(GlobFilter::new(&args.include, &args.exclude)?)
+        .max_filesize(args.max_filesize))
+}
+
+/// A walker visiting exactly the files listed in `source`.
+/// The list is the traversal: listed directories are not descended into.
+fn file_list(source: &Path, delimiter: u8) -> AnyhowResult<Walker> {
+    let paths = read_path_list(source, delimiter)?;
+    Ok(Walker::new(paths).max_depth(Some(0)))
+}
+
+/// The files of `walker` in the `sort` order. Only `mtime` and `size` need the whole walk
+/// up front, the other orders are the walker's own.
+pub fn sorted(walker: &mut Walker, sort: Sort)
+    -> Box<dyn Iterator<Item = AnyhowResult<PathBuf>> + '_>
+{
+    match sort {
+        Sort::Mtime | Sort::Size => Box::new(self::sort(walker, sort).into_iter()),
+        Sort::None | Sort::Path => Box::new(walker),
+    }
+}
+
+/// Visit every file of `walker` up front to order them by modification time or size.
+/// Errors come first, files whose metadata cannot be read come next. Ties keep the order of
+/// the walker, so sort it by path for a fully reproducible order.
+pub fn sort(walker: &mut Walker, sort: Sort) -> Vec<AnyhowResult<PathBuf>> {
+    let mut paths: Vec<_> = walker.collect();
+    paths.sort_by_cached_key(|path| {
+        let metadata = path.as_ref().ok().map(std::fs::metadata);
+        match (metadata, sort) {
+            (None, _) => (0, None, 0),
+            (Some(Err(_)), _) => (1, None, 0),
+            (Some(Ok(metadata)), Sort::Mtime) => (2, metadata.modified().ok(), 0),
+            (Some(Ok(metadata)), _) => (2, None, metadata.len()),
+        }
+    });
+    paths
+}
+
+/// Read a list of paths separated by `delimiter` from the file at `source`, or from stdin
+/// when `source` is `-`. Empty entries are ignored.
+pub fn read_path_list(source: &Path, delimiter: u8) -> AnyhowResult<Vec<PathBuf>> {
+    let mut list = Vec::new();
+    if source == Path::new("-") {
+        std::io::stdin().lock().read_to_end(&mut list)
+            .context("Could not read the file list from stdin")?;
+    } else {
+        list = std::fs::read(source)
+            .with_context(|| format!("Could not read the file list: {:?}", source))?;
+    }
+    Ok(list.split(|&byte| byte == delimiter)
+        .filter(|path| !path.is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+// Paths are arbitrary bytes on Unix, so a `find -print0` list can be used without decoding it.
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// The bytes of `path`, which `path_from_bytes` turns back into it.
+#[cfg(unix)]
+pub fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+pub fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Dotfiles and dot-directories are considered hidden, as on Unix.
+pub fn is_hidden(entry: &Entry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
+// Anything that is neither a regular file, a directory nor a symlink: FIFOs, sockets and
+// block or character devices.
+fn is_special(file_type: &FileType) -> bool {
+    !(file_type.is_file() || file_type.is_dir() || file_type.is_symlink())
+}
+
+// On Unix a directory is uniquely identified by its device and inode numbers.
+#[cfg(unix)]
+type DirId = (u64, u64);
+
+#[cfg(unix)]
+fn dir_id(path: &Path) -> std::io::Result<DirId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+// Elsewhere the canonical path, with every symlink resolved, is the closest equivalent.
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path) -> std::io::Result<DirId> {
+    path.canonicalize()
+}
@@ -0,0 +1,51 @@
+//! JavaScript bindings of the search of buffers, with the `wasm` feature, to search in a web
+//! page with the engine of the command line, e.g. the logs shown by a viewer. Build the
+//! module with `cargo rustc --release --lib --target wasm32-unknown-unknown
+//! --no-default-features --features wasm --crate-type cdylib`, then run `wasm-bindgen` on it.
+//!
+//! ```js
+//! for (const found of search("ERROR", "app.log", bytes, false, true)) {
+//!     console.log(found.lineNumber, found.text);
+//! }
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Case, SearchConfig, Searcher};
+
+/// A line containing the pattern. Numbers are those of JavaScript, which hold any offset a
+/// page has the memory for.
+#[wasm_bindgen(js_name = Match, getter_with_clone)]
+pub struct WasmMatch {
+    /// 1-based.
+    #[wasm_bindgen(js_name = lineNumber)]
+    pub line_number: f64,
+    /// Offset of the first byte of the line in the buffer.
+    pub offset: f64,
+    /// The line, without its terminator.
+    pub text: String,
+    /// The occurrences of the pattern in the UTF-8 bytes of `text`, from left to right, as
+    /// start and end pairs: `[start, end, start, end, ...]`.
+    pub spans: Vec<u32>,
+}
+
+/// Search `bytes`, the contents of an input called `name`, for the lines containing
+/// `pattern`: a regular expression in the syntax of the `regex` crate if `regex` is set,
+/// literal text otherwise. `name` picks how the bytes are decoded, as the path of a file
+/// would. Throws if the pattern is not valid, or if the bytes cannot be decoded.
+#[wasm_bindgen]
+pub fn search(pattern: &str, name: &str, bytes: &[u8], regex: bool, ignore_case: bool)
+    -> Result<Vec<WasmMatch>, JsError>
+{
+    let case = if ignore_case { Case::Insensitive } else { Case::Sensitive };
+    let config = SearchConfig::builder().pattern(pattern).regex(regex).case(case).build()?;
+    let matches = Searcher::new(config)?.search_bytes(name, bytes)?;
+    Ok(matches.into_iter().map(|found| WasmMatch {
+        line_number: found.line_number as f64,
+        offset: found.offset as f64,
+        text: found.text,
+        spans: found.spans.iter()
+            .flat_map(|span| [span.start as u32, span.end as u32])
+            .collect(),
+    }).collect())
+}
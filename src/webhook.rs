@@ -0,0 +1,119 @@
+//! `--webhook`: the matches `--tail` finds are POSTed to a URL as JSON, as they are printed:
+//! `{"version": 1, "matches": [{...}, ...]}`, each match as the `data` of a `match` event of
+//! `--json`. The matches found within a second go in the same request, and a request that
+//! fails is tried again a few times, waiting longer each time.
+
+use anyhow::Result as AnyhowResult;
+
+use crate::event;
+
+/// Sends the matches handed to it to a URL, from a thread of its own, so that a slow or
+/// unreachable server holds up neither the search nor the printing. The matches still queued
+/// are sent when it is dropped.
+#[cfg(all(feature = "net", feature = "json"))]
+pub struct Webhook {
+    sender: Option<std::sync::mpsc::Sender<serde_json::Value>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+#[cfg(all(feature = "net", feature = "json"))]
+impl Webhook {
+    pub fn start(url: &str) -> Webhook {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let url = url.to_string();
+        let thread = std::thread::spawn(move || deliver(&url, &receiver));
+        Webhook { sender: Some(sender), thread: Some(thread) }
+    }
+
+    pub fn send(&self, found: &event::Match) -> AnyhowResult<()> {
+        let found = serde_json::to_value(found)?;
+        // The thread only ends once the sender is dropped.
+        let _ = self.sender.as_ref().map(|sender| sender.send(found));
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "net", feature = "json"))]
+impl Drop for Webhook {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Send what `matches` receives to `url`, in batches, until it is closed.
+#[cfg(all(feature = "net", feature = "json"))]
+fn deliver(url: &str, matches: &std::sync::mpsc::Receiver<serde_json::Value>) {
+    // Matches found within this long of the first one of a request go along with it.
+    const BATCH_WINDOW: std::time::Duration = std::time::Duration::from_secs(1);
+    // The most matches in one request.
+    const BATCH_SIZE: usize = 100;
+
+    while let Ok(first) = matches.recv() {
+        let deadline = std::time::Instant::now() + BATCH_WINDOW;
+        let mut batch = vec![first];
+        while batch.len() < BATCH_SIZE {
+            let wait = deadline.saturating_duration_since(std::time::Instant::now());
+            match matches.recv_timeout(wait) {
+                Ok(found) => batch.push(found),
+                Err(_) => break,
+            }
+        }
+        if let Err(err) = post(url, &batch) {
+            eprintln!("Warning: {:#}", err);
+        }
+    }
+}
+
+// POST `batch` to `url`, trying again when the server cannot be reached or fails, but not
+// when it refuses the request.
+#[cfg(all(feature = "net", feature = "json"))]
+fn post(url: &str, batch: &[serde_json::Value]) -> AnyhowResult<()> {
+    use anyhow::Context;
+
+    // How many times a request is made before its matches are given up, and how long to
+    // wait before the second time. The wait doubles every time after that.
+    const ATTEMPTS: u32 = 4;
+    const FIRST_RETRY: std::time::Duration = std::time::Duration::from_millis(500);
+
+    let body = serde_json::json!({"version": event::VERSION, "matches": batch}).to_string();
+    let mut wait = FIRST_RETRY;
+    let mut attempt = 1;
+    loop {
+        let result = ureq::post(url).set("Content-Type", "application/json").send_string(&body);
+        match result {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(status, _)) if status < 500 && status != 429 => {
+                return Err(anyhow::anyhow!("{} refused {} match(es) with status {}", url,
+                    batch.len(), status));
+            }
+            Err(err) if attempt == ATTEMPTS => {
+                return Err(err).with_context(|| {
+                    format!("Could not send {} match(es) to {}", batch.len(), url)
+                });
+            }
+            Err(_) => {
+                std::thread::sleep(wait);
+                wait *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Without the `net` and `json` features `--webhook` is rejected, and nothing is ever sent.
+#[cfg(not(all(feature = "net", feature = "json")))]
+pub struct Webhook;
+
+#[cfg(not(all(feature = "net", feature = "json")))]
+impl Webhook {
+    pub fn start(_url: &str) -> Webhook {
+        Webhook
+    }
+
+    pub fn send(&self, _found: &event::Match) -> AnyhowResult<()> {
+        Ok(())
+    }
+}
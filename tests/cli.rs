@@ -0,0 +1,242 @@
+//! The output of the command line, as another program reading it would see it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A directory of files to search, removed once the test is done.
+struct Fixture(PathBuf);
+
+impl Fixture {
+    fn new(name: &str, files: &[(&str, &str)]) -> Fixture {
+        let root = std::env::temp_dir()
+            .join(format!("rust-cli-book-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        Fixture(root)
+    }
+
+    fn path(&self, file: &str) -> String {
+        self.0.join(file).display().to_string()
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The lines written to stdout by a search of `root` with `args`, sorted since the walk
+/// order is not.
+fn stdout_lines(root: &Path, args: &[&str]) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cli-book"))
+        .args(args)
+        .arg("--path")
+        .arg(root)
+        .env_remove("GRRS_CONFIG_PATH")
+        .output()
+        .unwrap();
+    let mut lines: Vec<_> = String::from_utf8(output.stdout).unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    lines.sort();
+    lines
+}
+
+fn fixture(name: &str) -> Fixture {
+    Fixture::new(name, &[
+        ("a.txt", "hello\nbye\nhello hello\n"),
+        ("b.txt", "bye\n"),
+        ("sub/c.txt", "hello\n"),
+    ])
+}
+
+#[test]
+fn files_with_and_without_matches_print_only_paths() {
+    let fixture = fixture("files");
+    let with = vec![fixture.path("a.txt"), fixture.path("sub/c.txt")];
+    assert_eq!(stdout_lines(&fixture.0, &["-l", "hello"]), with);
+    assert_eq!(stdout_lines(&fixture.0, &["-L", "hello"]), vec![fixture.path("b.txt")]);
+}
+
+#[test]
+fn counts_print_only_path_and_count() {
+    let fixture = fixture("count");
+    let a = fixture.path("a.txt");
+    let c = fixture.path("sub/c.txt");
+    assert_eq!(stdout_lines(&fixture.0, &["-c", "hello"]),
+        vec![format!("{}:2", a), format!("{}:1", c)]);
+    assert_eq!(stdout_lines(&fixture.0, &["--count-matches", "hello"]),
+        vec![format!("{}:3", a), format!("{}:1", c)]);
+    #[cfg(feature = "color")]
+    assert_eq!(stdout_lines(&fixture.0, &["-c", "--color", "always", "hello"]),
+        vec![format!("\x1b[35m{}\x1b[0m:2", a), format!("\x1b[35m{}\x1b[0m:1", c)]);
+}
+
+#[cfg(all(feature = "async", target_os = "linux"))]
+#[test]
+fn async_prints_files_while_a_fifo_waits_for_its_writer() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let fixture = Fixture::new("async", &[("a.txt", "hello\n")]);
+    let fifo = fixture.0.join("fifo");
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust-cli-book"))
+        .args(["hello", "--async", "--include-special", "--path"])
+        .arg(&fixture.0)
+        .env_remove("GRRS_CONFIG_PATH")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, format!("{}:hello\n", fixture.path("a.txt")));
+    fs::write(&fifo, "hello fifo\n").unwrap();
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, format!("{}:hello fifo\n", fixture.path("fifo")));
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn shortened_lines_start_at_the_match_when_it_fits() {
+    let fixture = Fixture::new("max-columns", &[("a.txt", "abcdefg hello and more\n")]);
+    let shortened = |max: &str| stdout_lines(Path::new(&fixture.path("a.txt")),
+        &["hello", "--max-columns", max]);
+    assert_eq!(shortened("5"), ["[\u{2026} 8 chars] hello [\u{2026} 9 more chars]"]);
+    assert_eq!(shortened("6"), ["[\u{2026} 8 chars] hello [\u{2026} 8 more chars]"]);
+    assert_eq!(shortened("3"), ["[\u{2026} 8 chars] hel [\u{2026} 11 more chars]"]);
+    assert_eq!(shortened("13"), ["abcdefg hello [\u{2026} 9 more chars]"]);
+}
+
+#[test]
+fn null_data_records_are_printed_with_their_terminator() {
+    let fixture = Fixture::new("null-data", &[("a.txt", "one\ntwo\0three\0two and one\0")]);
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cli-book"))
+        .args(["one", "--null-data", "--path"])
+        .arg(fixture.path("a.txt"))
+        .env_remove("GRRS_CONFIG_PATH")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "one\ntwo\0two and one\0");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn patterns_are_read_once_from_a_fifo() {
+    use std::process::Stdio;
+
+    let fixture = Fixture::new("patterns-fifo", &[("a.txt", "hello\nbye\n")]);
+    let fifo = fixture.0.join("patterns");
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust-cli-book"))
+        .arg("-f")
+        .arg(&fifo)
+        .arg("--path")
+        .arg(fixture.path("a.txt"))
+        .env_remove("GRRS_CONFIG_PATH")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    fs::write(&fifo, "bye\n").unwrap();
+    // Reading the patterns a second time would wait for another writer forever.
+    for _ in 0..100 {
+        if child.try_wait().unwrap().is_some() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    if child.try_wait().unwrap().is_none() {
+        child.kill().unwrap();
+        panic!("the patterns were read again");
+    }
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "bye\n");
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_events_carry_the_fields_their_schema_requires() {
+    let fixture = fixture("json");
+    let schema = Command::new(env!("CARGO_BIN_EXE_rust-cli-book"))
+        .arg("--json-schema")
+        .output()
+        .unwrap();
+    let schema: serde_json::Value = serde_json::from_slice(&schema.stdout).unwrap();
+    let events = stdout_lines(&fixture.0, &["--json", "--stats", "hello"]);
+    assert!(!events.is_empty());
+    for event in events {
+        let event: serde_json::Value = serde_json::from_str(&event).unwrap();
+        assert_eq!(event["version"], schema["properties"]["version"]["const"]);
+        let kind = event["type"].as_str().unwrap();
+        let required = schema["$defs"][kind]["required"].as_array().unwrap();
+        for field in required {
+            let field = field.as_str().unwrap();
+            assert!(event["data"].get(field).is_some(), "{} has no {}", kind, field);
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn fingerprints_survive_line_shifts_and_reindenting() {
+    let fixture = Fixture::new("fingerprint", &[("a.txt", "hello world\nhello there\n")]);
+    let fingerprints = || {
+        stdout_lines(&fixture.0, &["--json", "hello"]).iter()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|event| event["type"] == "match")
+            .map(|event| event["data"]["fingerprint"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    let before = fingerprints();
+    assert_eq!(before.len(), 2);
+    assert_ne!(before[0], before[1]);
+    assert_eq!(fingerprints(), before);
+    fs::write(fixture.0.join("a.txt"), "new line\n  hello   world\nhello there\n").unwrap();
+    assert_eq!(fingerprints(), before);
+}
+
+#[test]
+fn editor_mode_does_not_read_the_config_file() {
+    let fixture = Fixture::new("editor-mode", &[("a.txt", "hello\n"), ("config", "--count\n")]);
+    let search = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rust-cli-book"))
+            .args(args)
+            .args(["hello", "--path"])
+            .arg(fixture.path("a.txt"))
+            .env("GRRS_CONFIG_PATH", fixture.path("config"))
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(search(&[]), "1\n");
+    assert_eq!(search(&["--editor-mode"]), "hello\n");
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn report_prints_a_recorded_run_again_in_another_format() {
+    let fixture = Fixture::new("report", &[("a.txt", "hello world\nbye\n")]);
+    let journal = Fixture::new("report-journal", &[]);
+    let recorded = stdout_lines(&fixture.0, &["--journal", &journal.path(""), "hello"]);
+    assert_eq!(recorded, [format!("{}:hello world", fixture.path("a.txt"))]);
+    // The report shows the results of the run, not what the file holds now.
+    fs::write(fixture.0.join("a.txt"), "bye\n").unwrap();
+    let report = Command::new(env!("CARGO_BIN_EXE_rust-cli-book"))
+        .args(["report", "LAST", "--journal", &journal.path(""), "--format", "csv"])
+        .output()
+        .unwrap();
+    assert!(report.status.success());
+    let report = String::from_utf8(report.stdout).unwrap();
+    let rows: Vec<_> = report.lines().collect();
+    assert_eq!(rows.len(), 2);
+    assert!(rows[1].ends_with(",1,1,hello,hello world"), "{}", rows[1]);
+}